@@ -1,8 +1,13 @@
-use duckdb::{Connection, types::ValueRef};
+use duckdb::arrow::array::{Array, StringArray};
+use duckdb::arrow::datatypes::{DataType, Field};
+use duckdb::{appender_params_from_iter, params_from_iter, AccessMode, Config, Connection, InterruptHandle, OptionalExt, types::{EnumType, TimeUnit, Value, ValueRef}};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::Mutex;
-use tauri::State;
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::time::Instant;
+use tauri::{Emitter, State};
 use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,62 +24,498 @@ impl std::fmt::Display for DuckDBError {
 
 impl std::error::Error for DuckDBError {}
 
+/// Significant digits an f64 always round-trips; wider decimals stay strings
+const MAX_EXACT_DECIMAL_DIGITS: u32 = 15;
+const MAX_EXACT_DECIMAL_MANTISSA: u128 = 10u128.pow(MAX_EXACT_DECIMAL_DIGITS);
+
+/// Rows returned by duckdb_query when the caller doesn't pass max_rows
+const DEFAULT_MAX_ROWS: usize = 10_000;
+
+/// Blobs larger than this are replaced by a placeholder unless the query overrides it
+const DEFAULT_MAX_INLINE_BLOB_BYTES: usize = 64 * 1024;
+
+/// How many rows past max_rows are counted before giving up on a total
+const ROW_COUNT_SCAN_LIMIT: usize = 1_000_000;
+
 /// State for managing DuckDB connections
 pub struct DuckDBState {
-    connections: Mutex<HashMap<String, Connection>>,
+    // Each connection has its own lock so queries on different connections run
+    // concurrently; the map lock is only held long enough to look one up
+    connections: Mutex<HashMap<String, DuckDBHandle>>,
+    // Kept outside the connections lock so a running query can be interrupted
+    interrupts: Mutex<HashMap<String, Arc<InterruptHandle>>>,
+    cursors: Mutex<HashMap<String, DuckDBCursor>>,
+    /// Named in-memory databases, keyed by name
+    shared_memory: Mutex<HashMap<String, SharedMemoryDatabase>>,
 }
 
 impl Default for DuckDBState {
     fn default() -> Self {
         Self {
             connections: Mutex::new(HashMap::new()),
+            interrupts: Mutex::new(HashMap::new()),
+            cursors: Mutex::new(HashMap::new()),
+            shared_memory: Mutex::new(HashMap::new()),
         }
     }
 }
 
+/// An in-memory database shared by several connections. Each connection is a
+/// clone of `base`; the entry is dropped with the last connection using it,
+/// which releases the database once any running queries finish.
+struct SharedMemoryDatabase {
+    base: Connection,
+    connection_ids: HashSet<String>,
+}
+
+/// Source of keys for DuckDBHandle::active_clones
+static NEXT_CLONE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A per-query clone of a connection, registered for cancellation while alive
+struct ActiveClone {
+    conn: Connection,
+    id: u64,
+    active_clones: Arc<Mutex<HashMap<u64, Arc<InterruptHandle>>>>,
+}
+
+impl ActiveClone {
+    fn new(handle: &DuckDBHandle) -> Result<Self, DuckDBError> {
+        let conn = lock_unpoisoned(&handle.clone_source).try_clone().map_err(|e| DuckDBError {
+            message: format!("Failed to clone connection: {}", e),
+            code: "CONNECTION_ERROR".to_string(),
+        })?;
+        let id = NEXT_CLONE_ID.fetch_add(1, Ordering::Relaxed);
+        lock_unpoisoned(&handle.active_clones).insert(id, conn.interrupt_handle());
+
+        Ok(Self {
+            conn,
+            id,
+            active_clones: Arc::clone(&handle.active_clones),
+        })
+    }
+}
+
+impl Drop for ActiveClone {
+    fn drop(&mut self) {
+        lock_unpoisoned(&self.active_clones).remove(&self.id);
+    }
+}
+
+/// Batch size and reply channel for one duckdb_query_fetch call
+type CursorRequest = (usize, mpsc::Sender<Result<DuckDBCursorBatch, DuckDBError>>);
+
+/// An open result set. The rows live on a dedicated thread that owns a clone
+/// of the connection; dropping `requests` ends that thread.
+struct DuckDBCursor {
+    connection_id: String,
+    requests: mpsc::Sender<CursorRequest>,
+}
+
+/// An open connection along with the options it was opened with
+#[derive(Clone)]
+struct DuckDBHandle {
+    conn: Arc<Mutex<Connection>>,
+    /// Never runs queries, so it can always be locked quickly to clone a
+    /// connection for a read that would otherwise wait behind `conn`
+    clone_source: Arc<Mutex<Connection>>,
+    /// Interrupt handles of cloned connections with a query in flight
+    active_clones: Arc<Mutex<HashMap<u64, Arc<InterruptHandle>>>>,
+    read_only: bool,
+    /// Set while an explicit transaction from duckdb_begin_transaction is open
+    in_transaction: Arc<AtomicBool>,
+    /// Name of the shared in-memory database this connection belongs to
+    shared_memory: Option<String>,
+    /// Re-applied whenever an extension is loaded, since httpfs may not have
+    /// been available when the connection was opened
+    http_proxy: Option<HttpProxy>,
+}
+
+/// Proxy settings for httpfs requests
+#[derive(Clone, Debug)]
+struct HttpProxy {
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+/// Settings applied to a connection when it is opened
+#[derive(Debug, Default, Deserialize)]
+pub struct DuckDBSettings {
+    pub memory_limit: Option<String>,
+    pub threads: Option<u32>,
+    pub temp_directory: Option<String>,
+    /// Where extensions are installed and loaded from, e.g. an internal mirror
+    pub extension_directory: Option<String>,
+    /// Needed to load extensions that were not signed by DuckDB
+    pub allow_unsigned_extensions: Option<bool>,
+    /// Proxy for httpfs requests (https://, s3://, ...), e.g. `proxy.corp:3128`
+    pub http_proxy: Option<String>,
+    pub http_proxy_username: Option<String>,
+    pub http_proxy_password: Option<String>,
+}
+
+impl DuckDBSettings {
+    fn http_proxy(&self) -> Option<HttpProxy> {
+        let url = self.http_proxy.as_ref().filter(|url| !url.is_empty())?;
+        Some(HttpProxy {
+            url: url.clone(),
+            username: self.http_proxy_username.clone(),
+            password: self.http_proxy_password.clone(),
+        })
+    }
+}
+
+/// The settings actually in effect on a connection, as reported by DuckDB
+#[derive(Serialize)]
+pub struct DuckDBEffectiveSettings {
+    memory_limit: String,
+    threads: i64,
+    temp_directory: String,
+}
+
 #[derive(Serialize)]
 pub struct DuckDBConnectResult {
     connection_id: String,
+    settings: DuckDBEffectiveSettings,
 }
 
 #[derive(Serialize)]
 pub struct DuckDBQueryResult {
     columns: Vec<String>,
+    /// DuckDB type name of each column, "UNKNOWN" when it can't be determined
+    column_types: Vec<String>,
+    /// Per column: "decimal" when values are exact decimal strings, "hugeint"
+    /// when integers too large for i64 come back as strings, "uuid" for UUIDs
+    column_hints: Vec<Option<String>>,
     rows: Vec<Vec<serde_json::Value>>,
+    row_count: usize,
+    /// Time spent executing and fetching, excluding JSON conversion
+    execution_ms: u64,
+    /// True when rows were cut off by max_rows
+    truncated: bool,
+    /// Total rows produced by the query, or None if there were too many to count
+    total_rows_estimate: Option<usize>,
 }
 
 #[derive(Serialize)]
 pub struct DuckDBExecuteResult {
     rows_affected: usize,
+    execution_ms: u64,
+    /// Leading keyword of the statement, e.g. INSERT, UPDATE, CREATE
+    statement_type: String,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBExtension {
+    name: String,
+    installed: bool,
+    loaded: bool,
+    description: String,
+    version: Option<String>,
+    /// Where the extension comes from: "core", "community", or "custom"
+    source: String,
+}
+
+/// A catalog attached to a connection, from `duckdb_databases()`
+#[derive(Serialize)]
+pub struct DuckDBDatabaseInfo {
+    name: String,
+    path: Option<String>,
+    database_type: String,
+    read_only: bool,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBCatalog {
+    name: String,
+    schemas: Vec<DuckDBSchema>,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBSchema {
+    name: String,
+    tables: Vec<DuckDBTable>,
+    views: Vec<DuckDBTable>,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBTable {
+    name: String,
+    temporary: bool,
+    columns: Vec<DuckDBColumn>,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBColumn {
+    name: String,
+    data_type: String,
+    nullable: bool,
+    default: Option<String>,
+}
+
+/// Options for writing a query result to disk with `COPY ... TO`
+#[derive(Debug, Default, Deserialize)]
+pub struct DuckDBExportOptions {
+    /// CSV only: field delimiter (defaults to ",")
+    pub delimiter: Option<String>,
+    /// CSV only: whether to write a header row (defaults to true)
+    pub header: Option<bool>,
+    /// Parquet: snappy, zstd, gzip, lz4, brotli, or uncompressed.
+    /// CSV: gzip, zstd, or none; the matching extension is added to the path.
+    pub compression: Option<String>,
+    /// Replace an existing file at the target path
+    pub overwrite: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBExportResult {
+    path: String,
+    rows_written: u64,
+    elapsed_ms: u64,
+    /// Size of the written file on disk, i.e. after compression
+    file_size: u64,
+}
+
+/// File sizes around a checkpoint or vacuum, including the WAL
+#[derive(Serialize)]
+pub struct DuckDBCompactResult {
+    path: Option<String>,
+    size_before: u64,
+    size_after: u64,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBTableSummary {
+    schema: String,
+    name: String,
+    rows: u64,
+}
+
+/// Result of duckdb_export_database and duckdb_import_database
+#[derive(Serialize)]
+pub struct DuckDBDatabaseTransferResult {
+    path: String,
+    tables: Vec<DuckDBTableSummary>,
+    elapsed_ms: u64,
+}
+
+/// Payload of the "duckdb-export-progress" event
+#[derive(Clone, Serialize)]
+struct DuckDBExportProgress {
+    connection_id: String,
+    target_dir: String,
+    /// Data file DuckDB most recently finished writing
+    file: Option<String>,
+    tables_done: usize,
+    tables_total: usize,
+}
+
+/// Options for creating a table from a data file
+#[derive(Debug, Default, Deserialize)]
+pub struct DuckDBImportOptions {
+    /// "csv", "parquet", or "json"; inferred from the file extension when omitted
+    pub format: Option<String>,
+    pub header: Option<bool>,
+    pub delimiter: Option<String>,
+    pub sample_size: Option<i64>,
+    pub replace_existing: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBImportResult {
+    table_name: String,
+    row_count: i64,
+    columns: Vec<DuckDBColumn>,
+}
+
+/// Dialect and types detected by DuckDB's CSV sniffer, plus a preview of the data
+#[derive(Serialize)]
+pub struct CsvSniffResult {
+    delimiter: String,
+    quote: String,
+    newline: String,
+    has_header: bool,
+    columns: Vec<CsvSniffColumn>,
+    preview_rows: Vec<Vec<serde_json::Value>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CsvSniffColumn {
+    name: String,
+    #[serde(rename(deserialize = "type"))]
+    data_type: String,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBCursorInfo {
+    cursor_id: String,
+    columns: Vec<String>,
+    column_types: Vec<String>,
+    column_hints: Vec<Option<String>>,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBCursorBatch {
+    rows: Vec<Vec<serde_json::Value>>,
+    done: bool,
+}
+
+/// One operator in a query plan
+#[derive(Serialize)]
+pub struct DuckDBPlanNode {
+    name: String,
+    /// Actual rows for EXPLAIN ANALYZE, otherwise the planner's estimate
+    cardinality: Option<u64>,
+    /// Only present for EXPLAIN ANALYZE
+    timing_ms: Option<f64>,
+    extra_info: serde_json::Value,
+    children: Vec<DuckDBPlanNode>,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBExplainResult {
+    /// "json" when `plan` is populated, "text" when only `text` is available
+    format: String,
+    plan: Vec<DuckDBPlanNode>,
+    text: Option<String>,
 }
 
-/// Connect to a DuckDB database
+/// Outcome of one statement in a script run by duckdb_execute_batch
+#[derive(Serialize)]
+pub struct DuckDBStatementOutcome {
+    index: usize,
+    statement: String,
+    /// Set for statements that return rows (SELECT, SHOW, DESCRIBE, ...)
+    result: Option<DuckDBQueryResult>,
+    /// Set for DML and DDL statements
+    rows_affected: Option<usize>,
+    error: Option<DuckDBError>,
+}
+
+/// One entry per statement from duckdb_query_multi: the query result fields
+/// for statements that return rows, `rows_affected` for everything else
+#[derive(Serialize)]
+pub struct DuckDBStatementResult {
+    statement: String,
+    #[serde(flatten)]
+    result: Option<DuckDBQueryResult>,
+    rows_affected: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBBatchResult {
+    statements: Vec<DuckDBStatementOutcome>,
+    first_error_index: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBTransactionState {
+    in_transaction: bool,
+}
+
+#[derive(Serialize)]
+pub struct DuckDBAppendResult {
+    rows_appended: usize,
+}
+
+/// Credentials for cloud storage, turned into a `CREATE SECRET` statement
+#[derive(Debug, Deserialize)]
+pub struct DuckDBSecretConfig {
+    pub name: String,
+    /// One of "s3", "r2", "gcs", or "azure"
+    pub secret_type: String,
+    pub key_id: Option<String>,
+    pub secret: Option<String>,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+    pub url_style: Option<String>,
+    pub session_token: Option<String>,
+    /// Azure only: either a full connection string or an account name
+    pub connection_string: Option<String>,
+    pub account_name: Option<String>,
+    pub scope: Option<String>,
+    pub persistent: Option<bool>,
+}
+
+/// A secret as listed back to the frontend; credential values are never included
+#[derive(Serialize)]
+pub struct DuckDBSecretInfo {
+    name: String,
+    secret_type: String,
+    provider: String,
+    persistent: bool,
+    storage: String,
+    scope: Vec<String>,
+}
+
+/// Connect to a DuckDB database. `md:` paths open MotherDuck databases,
+/// authenticated with `motherduck_token` when given. `:memory:name` (or
+/// `shared_memory_name`) opens an in-memory database shared with every other
+/// connection using the same name.
 #[tauri::command]
 pub fn duckdb_connect(
     state: State<DuckDBState>,
     path: String,
+    read_only: Option<bool>,
+    settings: Option<DuckDBSettings>,
+    motherduck_token: Option<String>,
+    shared_memory_name: Option<String>,
 ) -> Result<DuckDBConnectResult, DuckDBError> {
-    let conn = if path == ":memory:" || path.is_empty() {
-        Connection::open_in_memory()
-    } else {
-        Connection::open(&path)
-    }
-    .map_err(|e| DuckDBError {
-        message: format!("Failed to open connection: {}", e),
-        code: "CONNECTION_ERROR".to_string(),
-    })?;
-
+    let read_only = read_only.unwrap_or(false);
+    let settings = settings.unwrap_or_default();
+    let http_proxy = settings.http_proxy();
     let connection_id = format!("duckdb-{}", Uuid::new_v4());
-    state
-        .connections
-        .lock()
-        .map_err(|e| DuckDBError {
-            message: format!("Failed to lock connections: {}", e),
-            code: "LOCK_ERROR".to_string(),
-        })?
-        .insert(connection_id.clone(), conn);
+    let shared_memory = shared_memory_name.filter(|name| !name.is_empty()).or_else(|| {
+        path.strip_prefix(":memory:")
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+    });
+
+    let conn = match &shared_memory {
+        Some(name) => join_shared_memory(&state, name, &connection_id, read_only, &settings)?,
+        None => open_connection(&path, read_only, motherduck_token.as_deref(), &settings)?,
+    };
+    let settings = match apply_settings(&conn, &settings) {
+        Ok(settings) => settings,
+        Err(e) => {
+            if let Some(name) = &shared_memory {
+                leave_shared_memory(&state, name, &connection_id);
+            }
+            return Err(e);
+        }
+    };
+
+    let clone_source = match conn.try_clone() {
+        Ok(clone_source) => clone_source,
+        Err(e) => {
+            if let Some(name) = &shared_memory {
+                leave_shared_memory(&state, name, &connection_id);
+            }
+            return Err(DuckDBError {
+                message: format!("Failed to clone connection: {}", e),
+                code: "CONNECTION_ERROR".to_string(),
+            });
+        }
+    };
 
-    Ok(DuckDBConnectResult { connection_id })
+    lock_unpoisoned(&state.interrupts).insert(connection_id.clone(), conn.interrupt_handle());
+    lock_unpoisoned(&state.connections).insert(
+        connection_id.clone(),
+        DuckDBHandle {
+            conn: Arc::new(Mutex::new(conn)),
+            clone_source: Arc::new(Mutex::new(clone_source)),
+            active_clones: Arc::new(Mutex::new(HashMap::new())),
+            read_only,
+            in_transaction: Arc::new(AtomicBool::new(false)),
+            shared_memory,
+            http_proxy,
+        },
+    );
+
+    Ok(DuckDBConnectResult {
+        connection_id,
+        settings,
+    })
 }
 
 /// Disconnect from a DuckDB database
@@ -83,149 +524,3519 @@ pub fn duckdb_disconnect(
     state: State<DuckDBState>,
     connection_id: String,
 ) -> Result<(), DuckDBError> {
-    state
-        .connections
-        .lock()
-        .map_err(|e| DuckDBError {
-            message: format!("Failed to lock connections: {}", e),
-            code: "LOCK_ERROR".to_string(),
-        })?
-        .remove(&connection_id);
+    let handle = lock_unpoisoned(&state.connections).remove(&connection_id);
+
+    // Roll back an open transaction explicitly. If a query still holds the
+    // connection, DuckDB rolls back when that last reference is dropped.
+    if let Some(handle) = handle {
+        if handle.in_transaction.load(Ordering::SeqCst) {
+            let conn = match handle.conn.try_lock() {
+                Ok(conn) => Some(conn),
+                Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+                Err(TryLockError::WouldBlock) => None,
+            };
+            if let Some(conn) = conn {
+                let _ = conn.execute_batch("ROLLBACK");
+            }
+        }
+        if let Some(name) = &handle.shared_memory {
+            leave_shared_memory(&state, name, &connection_id);
+        }
+        // Clones keep the database alive on their own, so they only need to
+        // be stopped, not waited for
+        for interrupt in lock_unpoisoned(&handle.active_clones).values() {
+            interrupt.interrupt();
+        }
+    }
+    lock_unpoisoned(&state.interrupts).remove(&connection_id);
+    lock_unpoisoned(&state.cursors).retain(|_, cursor| cursor.connection_id != connection_id);
     Ok(())
 }
 
-/// Execute a SELECT query and return results
+/// Interrupt the query currently running on a DuckDB connection
 #[tauri::command]
-pub fn duckdb_query(
-    state: State<DuckDBState>,
+pub async fn duckdb_cancel(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+) -> Result<(), DuckDBError> {
+    let interrupts = lock_unpoisoned(&state.interrupts);
+    let interrupt = interrupts.get(&connection_id).ok_or(DuckDBError {
+        message: format!("Connection not found: {}", connection_id),
+        code: "CONNECTION_NOT_FOUND".to_string(),
+    })?;
+    interrupt.interrupt();
+
+    if let Ok(handle) = get_connection(&state, &connection_id) {
+        for interrupt in lock_unpoisoned(&handle.active_clones).values() {
+            interrupt.interrupt();
+        }
+    }
+    Ok(())
+}
+
+/// Execute a SELECT query and return results. At most `max_rows` rows are
+/// returned (default 10,000) and blobs over `max_inline_blob_bytes` (default
+/// 64 KB) are replaced by `{"__blob_truncated": true, "size": N}`; pass 0 to
+/// lift either limit.
+#[tauri::command]
+pub async fn duckdb_query(
+    state: State<'_, DuckDBState>,
     connection_id: String,
     sql: String,
+    params: Option<Vec<serde_json::Value>>,
+    max_rows: Option<usize>,
+    max_inline_blob_bytes: Option<usize>,
 ) -> Result<DuckDBQueryResult, DuckDBError> {
-    let connections = state.connections.lock().map_err(|e| DuckDBError {
-        message: format!("Failed to lock connections: {}", e),
-        code: "LOCK_ERROR".to_string(),
-    })?;
-    let conn = connections
-        .get(&connection_id)
-        .ok_or(DuckDBError {
-            message: format!("Connection not found: {}", connection_id),
-            code: "CONNECTION_NOT_FOUND".to_string(),
-        })?;
+    let params = json_params_to_values(&params.unwrap_or_default())?;
+    let max_rows = max_rows.unwrap_or(DEFAULT_MAX_ROWS);
+    let blob_limit = max_inline_blob_bytes.unwrap_or(DEFAULT_MAX_INLINE_BLOB_BYTES);
+    let handle = get_connection(&state, &connection_id)?;
 
-    let mut stmt = conn.prepare(&sql).map_err(|e| DuckDBError {
-        message: format!("Failed to prepare query: {}", e),
-        code: "QUERY_ERROR".to_string(),
-    })?;
+    with_read_connection(handle, move |conn| run_query(conn, &sql, &params, max_rows, blob_limit)).await
+}
 
-    // Execute query first - column metadata is only available after execution
-    let mut result_rows = stmt.query([]).map_err(|e| DuckDBError {
-        message: format!("Failed to execute query: {}", e),
-        code: "QUERY_ERROR".to_string(),
-    })?;
+/// Execute a non-SELECT SQL statement (INSERT, UPDATE, DELETE, CREATE, etc.)
+#[tauri::command]
+pub async fn duckdb_execute(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    sql: String,
+    params: Option<Vec<serde_json::Value>>,
+) -> Result<DuckDBExecuteResult, DuckDBError> {
+    let params = json_params_to_values(&params.unwrap_or_default())?;
+    let handle = get_connection(&state, &connection_id)?;
+    let read_only = handle.read_only;
 
-    // Get column info from the executed statement
-    let column_count = result_rows.as_ref().map(|s| s.column_count()).unwrap_or(0);
-    let columns: Vec<String> = (0..column_count)
-        .map(|i| {
-            result_rows
-                .as_ref()
-                .and_then(|s| s.column_name(i).ok())
-                .map(|s| s.to_string())
-                .unwrap_or_default()
+    with_connection(handle.conn, move |conn| run_execute(conn, &sql, &params))
+        .await
+        .map_err(|e| {
+            // DuckDB rejects writes itself; translate its message into a stable code
+            if read_only && e.message.contains("read-only") {
+                DuckDBError {
+                    message: "Connection is read-only; write statements are not allowed".to_string(),
+                    code: "READ_ONLY".to_string(),
+                }
+            } else {
+                e
+            }
         })
-        .collect();
+}
 
-    let mut rows: Vec<Vec<serde_json::Value>> = Vec::new();
+/// Test a DuckDB connection by opening and immediately closing it. For
+/// MotherDuck this round-trips a query so a bad token fails with AUTH_FAILED.
+#[tauri::command]
+pub fn duckdb_test(
+    path: String,
+    read_only: Option<bool>,
+    motherduck_token: Option<String>,
+) -> Result<(), DuckDBError> {
+    let conn = open_connection(
+        &path,
+        read_only.unwrap_or(false),
+        motherduck_token.as_deref(),
+        &DuckDBSettings::default(),
+    )?;
 
-    while let Some(row) = result_rows.next().map_err(|e| DuckDBError {
-        message: format!("Failed to read row: {}", e),
-        code: "QUERY_ERROR".to_string(),
-    })? {
-        let mut values: Vec<serde_json::Value> = Vec::new();
-        for i in 0..column_count {
-            let value = row.get_ref(i).map_err(|e| DuckDBError {
-                message: format!("Failed to get column value: {}", e),
-                code: "QUERY_ERROR".to_string(),
+    if is_motherduck_path(&path) {
+        conn.execute_batch("SELECT 1").map_err(|e| motherduck_error(&e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// List the extensions known to DuckDB along with their install/load status
+#[tauri::command]
+pub async fn duckdb_list_extensions(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+) -> Result<Vec<DuckDBExtension>, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+
+    with_connection(handle.conn, |conn| query_extensions(conn, None)).await
+}
+
+/// Install an extension, optionally from a specific repository
+#[tauri::command]
+pub async fn duckdb_install_extension(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    name: String,
+    repository: Option<String>,
+) -> Result<DuckDBExtension, DuckDBError> {
+    validate_extension_name(&name)?;
+    let handle = get_connection(&state, &connection_id)?;
+
+    with_connection(handle.conn, move |conn| {
+        let mut sql = format!("INSTALL {}", name);
+        if let Some(repository) = repository.filter(|r| !r.is_empty()) {
+            // Named repositories are keywords, anything else is a URL or path
+            match repository.as_str() {
+                "core" | "core_nightly" | "community" | "local_build_debug" | "local_build_release" => {
+                    sql.push_str(&format!(" FROM {}", repository))
+                }
+                _ => sql.push_str(&format!(" FROM {}", quote_literal(&repository))),
+            }
+        }
+        conn.execute_batch(&sql).map_err(|e| DuckDBError {
+            message: format!("Failed to install extension '{}': {}", name, e),
+            code: "EXTENSION_INSTALL_ERROR".to_string(),
+        })?;
+        find_extension(conn, &name)
+    })
+    .await
+}
+
+/// Load an installed extension into the connection
+#[tauri::command]
+pub async fn duckdb_load_extension(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    name: String,
+) -> Result<DuckDBExtension, DuckDBError> {
+    validate_extension_name(&name)?;
+    let handle = get_connection(&state, &connection_id)?;
+    let http_proxy = handle.http_proxy.clone();
+
+    with_connection(handle.conn, move |conn| {
+        conn.execute_batch(&format!("LOAD {}", name))
+            .map_err(|e| DuckDBError {
+                message: format!("Failed to load extension '{}': {}", name, e),
+                code: "EXTENSION_LOAD_ERROR".to_string(),
             })?;
-            let json_value = convert_value_to_json(value);
-            values.push(json_value);
+        if let Some(proxy) = &http_proxy {
+            apply_http_proxy(conn, proxy)?;
         }
-        rows.push(values);
+        find_extension(conn, &name)
+    })
+    .await
+}
+
+/// Load an extension straight from a `.duckdb_extension` file. Returns the
+/// extension's status when DuckDB reports it under the file's base name.
+#[tauri::command]
+pub async fn duckdb_load_extension_from_path(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    path: String,
+) -> Result<Option<DuckDBExtension>, DuckDBError> {
+    let file = std::path::Path::new(&path);
+    if !file.is_file() {
+        return Err(DuckDBError {
+            message: format!("Extension file not found: {}", path),
+            code: "EXTENSION_FILE_NOT_FOUND".to_string(),
+        });
     }
+    // foo.duckdb_extension and foo.duckdb_extension.gz both register as "foo"
+    let name = file
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.split('.').next())
+        .unwrap_or_default()
+        .to_string();
+    let handle = get_connection(&state, &connection_id)?;
+    let http_proxy = handle.http_proxy.clone();
 
-    Ok(DuckDBQueryResult { columns, rows })
+    with_connection(handle.conn, move |conn| {
+        conn.execute_batch(&format!("LOAD {}", quote_literal(&path)))
+            .map_err(|e| {
+                let message = e.to_string();
+                if message.to_lowercase().contains("signature") {
+                    DuckDBError {
+                        message: format!(
+                            "Extension '{}' is not signed by DuckDB; reconnect with allow_unsigned_extensions enabled to load it: {}",
+                            path, message
+                        ),
+                        code: "EXTENSION_SIGNATURE_REJECTED".to_string(),
+                    }
+                } else {
+                    DuckDBError {
+                        message: format!("Failed to load extension '{}': {}", path, message),
+                        code: "EXTENSION_LOAD_ERROR".to_string(),
+                    }
+                }
+            })?;
+        if let Some(proxy) = &http_proxy {
+            apply_http_proxy(conn, proxy)?;
+        }
+        Ok(query_extensions(conn, Some(&name))?.into_iter().next())
+    })
+    .await
 }
 
-/// Execute a non-SELECT SQL statement (INSERT, UPDATE, DELETE, CREATE, etc.)
+/// Create (or replace) a secret used by httpfs/azure for cloud storage access
 #[tauri::command]
-pub fn duckdb_execute(
-    state: State<DuckDBState>,
+pub async fn duckdb_create_secret(
+    state: State<'_, DuckDBState>,
     connection_id: String,
-    sql: String,
-) -> Result<DuckDBExecuteResult, DuckDBError> {
-    let connections = state.connections.lock().map_err(|e| DuckDBError {
-        message: format!("Failed to lock connections: {}", e),
-        code: "LOCK_ERROR".to_string(),
-    })?;
-    let conn = connections
-        .get(&connection_id)
-        .ok_or(DuckDBError {
-            message: format!("Connection not found: {}", connection_id),
-            code: "CONNECTION_NOT_FOUND".to_string(),
+    secret: DuckDBSecretConfig,
+) -> Result<DuckDBSecretInfo, DuckDBError> {
+    let sql = build_create_secret_sql(&secret)?;
+    let handle = get_connection(&state, &connection_id)?;
+
+    with_connection(handle.conn, move |conn| {
+        conn.execute_batch(&sql).map_err(|e| DuckDBError {
+            message: redact_secret_values(
+                &format!("Failed to create secret '{}': {}", secret.name, e),
+                &secret,
+            ),
+            code: "SECRET_ERROR".to_string(),
         })?;
+        query_secrets(conn)?
+            .into_iter()
+            .find(|info| info.name == secret.name)
+            .ok_or(DuckDBError {
+                message: format!("Secret not found after creation: {}", secret.name),
+                code: "SECRET_ERROR".to_string(),
+            })
+    })
+    .await
+}
 
-    let rows_affected = conn.execute(&sql, []).map_err(|e| DuckDBError {
-        message: format!("Failed to execute statement: {}", e),
-        code: "EXECUTE_ERROR".to_string(),
-    })?;
+/// List secrets with their scope and storage, without any credential values
+#[tauri::command]
+pub async fn duckdb_list_secrets(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+) -> Result<Vec<DuckDBSecretInfo>, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
 
-    Ok(DuckDBExecuteResult { rows_affected })
+    with_connection(handle.conn, query_secrets).await
 }
 
-/// Test a DuckDB connection by opening and immediately closing it
+/// Drop a secret by name
 #[tauri::command]
-pub fn duckdb_test(path: String) -> Result<(), DuckDBError> {
-    let _conn = if path == ":memory:" || path.is_empty() {
-        Connection::open_in_memory()
-    } else {
-        Connection::open(&path)
+pub async fn duckdb_drop_secret(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    name: String,
+) -> Result<(), DuckDBError> {
+    validate_identifier(&name, "secret")?;
+    let handle = get_connection(&state, &connection_id)?;
+
+    with_connection(handle.conn, move |conn| {
+        conn.execute_batch(&format!("DROP SECRET {}", name))
+            .map_err(|e| DuckDBError {
+                message: format!("Failed to drop secret '{}': {}", name, e),
+                code: "SECRET_ERROR".to_string(),
+            })
+    })
+    .await
+}
+
+/// Attach another database file to a connection under the given alias
+#[tauri::command]
+pub async fn duckdb_attach(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    path: String,
+    alias: String,
+    read_only: Option<bool>,
+) -> Result<Vec<DuckDBDatabaseInfo>, DuckDBError> {
+    validate_identifier(&alias, "database alias")?;
+
+    // Remote locations (s3://, https://, md:) are resolved by DuckDB itself
+    let is_remote = path.contains("://") || path.starts_with("md:");
+    if !is_remote && !std::path::Path::new(&path).exists() {
+        return Err(DuckDBError {
+            message: format!("Database file not found: {}", path),
+            code: "FILE_NOT_FOUND".to_string(),
+        });
     }
-    .map_err(|e| DuckDBError {
-        message: format!("Failed to open connection: {}", e),
-        code: "CONNECTION_ERROR".to_string(),
-    })?;
 
-    Ok(())
+    let mut options = Vec::new();
+    let extension = std::path::Path::new(&path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    if matches!(extension.as_deref(), Some("sqlite" | "sqlite3" | "db")) {
+        options.push("TYPE sqlite");
+    }
+    if read_only.unwrap_or(false) {
+        options.push("READ_ONLY");
+    }
+
+    let mut sql = format!("ATTACH {} AS {}", quote_literal(&path), alias);
+    if !options.is_empty() {
+        sql.push_str(&format!(" ({})", options.join(", ")));
+    }
+
+    let handle = get_connection(&state, &connection_id)?;
+    with_connection(handle.conn, move |conn| {
+        conn.execute_batch(&sql).map_err(|e| {
+            let message = e.to_string();
+            DuckDBError {
+                code: if is_lock_error(&message) { "DATABASE_LOCKED" } else { "ATTACH_ERROR" }.to_string(),
+                message: format!("Failed to attach '{}': {}", path, message),
+            }
+        })?;
+        query_databases(conn)
+    })
+    .await
 }
 
-/// Convert a DuckDB ValueRef to a serde_json::Value
-fn convert_value_to_json(value: ValueRef) -> serde_json::Value {
-    match value {
-        ValueRef::Null => serde_json::Value::Null,
-        ValueRef::Boolean(b) => serde_json::json!(b),
-        ValueRef::TinyInt(i) => serde_json::json!(i),
-        ValueRef::SmallInt(i) => serde_json::json!(i),
-        ValueRef::Int(i) => serde_json::json!(i),
-        ValueRef::BigInt(i) => serde_json::json!(i),
-        ValueRef::HugeInt(i) => serde_json::json!(i.to_string()),
-        ValueRef::UTinyInt(i) => serde_json::json!(i),
-        ValueRef::USmallInt(i) => serde_json::json!(i),
-        ValueRef::UInt(i) => serde_json::json!(i),
-        ValueRef::UBigInt(i) => serde_json::json!(i),
-        ValueRef::Float(f) => serde_json::json!(f),
-        ValueRef::Double(f) => serde_json::json!(f),
-        ValueRef::Decimal(d) => serde_json::json!(d.to_string()),
-        ValueRef::Text(s) => serde_json::json!(String::from_utf8_lossy(s)),
-        ValueRef::Blob(b) => serde_json::json!(base64::Engine::encode(&base64::engine::general_purpose::STANDARD, b)),
-        ValueRef::Date32(d) => serde_json::json!(d),
-        ValueRef::Time64(..) => serde_json::json!(format!("{:?}", value)),
-        ValueRef::Timestamp(..) => serde_json::json!(format!("{:?}", value)),
-        ValueRef::Interval { .. } => serde_json::json!(format!("{:?}", value)),
-        ValueRef::List(..) => serde_json::json!(format!("{:?}", value)),
-        ValueRef::Enum(..) => serde_json::json!(format!("{:?}", value)),
-        ValueRef::Struct(..) => serde_json::json!(format!("{:?}", value)),
-        ValueRef::Map(..) => serde_json::json!(format!("{:?}", value)),
-        ValueRef::Array(..) => serde_json::json!(format!("{:?}", value)),
-        ValueRef::Union(..) => serde_json::json!(format!("{:?}", value)),
+/// Detach a previously attached database
+#[tauri::command]
+pub async fn duckdb_detach(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    alias: String,
+) -> Result<Vec<DuckDBDatabaseInfo>, DuckDBError> {
+    validate_identifier(&alias, "database alias")?;
+    let handle = get_connection(&state, &connection_id)?;
+
+    with_connection(handle.conn, move |conn| {
+        conn.execute_batch(&format!("DETACH {}", alias))
+            .map_err(|e| DuckDBError {
+                message: format!("Failed to detach '{}': {}", alias, e),
+                code: "DETACH_ERROR".to_string(),
+            })?;
+        query_databases(conn)
+    })
+    .await
+}
+
+/// Describe every catalog, schema, table, view, and column visible to a connection
+#[tauri::command]
+pub async fn duckdb_get_schema(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+) -> Result<Vec<DuckDBCatalog>, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+
+    with_read_connection(handle, load_schema).await
+}
+
+/// Return the query plan for a statement. With `analyze` the statement is
+/// actually executed, so DML will modify data.
+#[tauri::command]
+pub async fn duckdb_explain(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    sql: String,
+    analyze: bool,
+) -> Result<DuckDBExplainResult, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+
+    with_connection(handle.conn, move |conn| explain_plan(conn, &sql, analyze)).await
+}
+
+fn explain_plan(conn: &Connection, sql: &str, analyze: bool) -> Result<DuckDBExplainResult, DuckDBError> {
+    let text_plan = |outputs: Vec<String>| DuckDBExplainResult {
+        format: "text".to_string(),
+        plan: Vec::new(),
+        text: Some(outputs.join("\n")),
+    };
+
+    // Older DuckDB versions don't know FORMAT JSON and fail to prepare it
+    let prefix = if analyze { "EXPLAIN (ANALYZE, FORMAT JSON)" } else { "EXPLAIN (FORMAT JSON)" };
+    let Ok(mut stmt) = conn.prepare(&format!("{} {}", prefix, sql)) else {
+        let prefix = if analyze { "EXPLAIN ANALYZE" } else { "EXPLAIN" };
+        return query_explain_output(conn, &format!("{} {}", prefix, sql)).map(text_plan);
+    };
+
+    // Once it has run, the statement is never run again (with ANALYZE it
+    // may have changed data), so output that isn't JSON is shown as text
+    let outputs = explain_rows(&mut stmt)?;
+    let values = outputs
+        .iter()
+        .map(|output| serde_json::from_str::<serde_json::Value>(output).ok())
+        .collect::<Option<Vec<_>>>();
+    Ok(match values {
+        Some(values) => DuckDBExplainResult {
+            format: "json".to_string(),
+            plan: values.iter().flat_map(plan_nodes).collect(),
+            text: None,
+        },
+        None => text_plan(outputs),
+    })
+}
+
+/// Reconstruct the CREATE TABLE statement for a table from the catalog
+#[tauri::command]
+pub async fn duckdb_get_table_ddl(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    schema: String,
+    table: String,
+) -> Result<String, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+
+    with_read_connection(handle, move |conn| build_table_ddl(conn, &schema, &table)).await
+}
+
+/// The CREATE VIEW statement for a view, as stored by DuckDB
+#[tauri::command]
+pub async fn duckdb_get_view_ddl(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    schema: String,
+    view: String,
+) -> Result<String, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+
+    with_read_connection(handle, move |conn| {
+        let sql: Option<String> = conn
+            .query_row(
+                "SELECT sql FROM duckdb_views() \
+                 WHERE database_name = current_database() AND schema_name = ? AND view_name = ?",
+                [&schema, &view],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| query_error("Failed to read view definition", e, "QUERY_ERROR"))?;
+
+        let sql = sql.ok_or_else(|| DuckDBError {
+            message: format!("View not found: {}.{}", schema, view),
+            code: "VIEW_NOT_FOUND".to_string(),
+        })?;
+        let sql = sql.trim().trim_end_matches(';');
+        Ok(format!("{};", sql))
+    })
+    .await
+}
+
+/// Write the result of a query straight to a Parquet or CSV file
+#[tauri::command]
+pub async fn duckdb_export_query(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    sql: String,
+    path: String,
+    format: String,
+    options: Option<DuckDBExportOptions>,
+) -> Result<DuckDBExportResult, DuckDBError> {
+    let options = options.unwrap_or_default();
+    let path = if format.eq_ignore_ascii_case("csv") {
+        with_compression_extension(path, options.compression.as_deref())
+    } else {
+        path
+    };
+    let target = std::path::Path::new(&path);
+
+    if target.exists() && !options.overwrite.unwrap_or(false) {
+        return Err(DuckDBError {
+            message: format!("File already exists: {}", path),
+            code: "FILE_EXISTS".to_string(),
+        });
+    }
+    if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| DuckDBError {
+            message: format!("Failed to create directory {}: {}", parent.display(), e),
+            code: "EXPORT_ERROR".to_string(),
+        })?;
+    }
+
+    let copy_options = build_copy_options(&format, &options)?;
+    let copy_sql = format!(
+        "COPY ({}) TO {} ({})",
+        sql.trim().trim_end_matches(';'),
+        quote_literal(&path),
+        copy_options
+    );
+
+    let handle = get_connection(&state, &connection_id)?;
+    with_connection(handle.conn, move |conn| {
+        let started = Instant::now();
+        // COPY ... TO returns a single row holding the number of rows written
+        let rows_written: i64 = conn
+            .query_row(&copy_sql, [], |row| row.get(0))
+            .map_err(|e| query_error("Export failed", e, "EXPORT_ERROR"))?;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        let file_size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+
+        Ok(DuckDBExportResult {
+            path,
+            rows_written: rows_written.max(0) as u64,
+            elapsed_ms,
+            file_size,
+        })
+    })
+    .await
+}
+
+/// Create a new table from a CSV, Parquet, or JSON file
+#[tauri::command]
+pub async fn duckdb_import_file(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    path: String,
+    table_name: String,
+    options: Option<DuckDBImportOptions>,
+) -> Result<DuckDBImportResult, DuckDBError> {
+    let options = options.unwrap_or_default();
+    if !std::path::Path::new(&path).exists() {
+        return Err(DuckDBError {
+            message: format!("File not found: {}", path),
+            code: "FILE_NOT_FOUND".to_string(),
+        });
+    }
+
+    let reader = build_file_reader(&path, &options)?;
+    let create = if options.replace_existing.unwrap_or(false) {
+        "CREATE OR REPLACE TABLE"
+    } else {
+        "CREATE TABLE"
+    };
+    let sql = format!(
+        "{} {} AS SELECT * FROM {}",
+        create,
+        quote_identifier(&table_name),
+        reader
+    );
+
+    let handle = get_connection(&state, &connection_id)?;
+    with_connection(handle.conn, move |conn| {
+        // DuckDB's reader errors already name the offending line, so pass them through
+        conn.execute_batch(&sql)
+            .map_err(|e| query_error(&format!("Failed to import {}", path), e, "IMPORT_ERROR"))?;
+
+        let row_count: i64 = conn
+            .query_row(
+                &format!("SELECT count(*) FROM {}", quote_identifier(&table_name)),
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| DuckDBError {
+                message: format!("Failed to count imported rows: {}", e),
+                code: "IMPORT_ERROR".to_string(),
+            })?;
+
+        let columns = query_table_columns(conn, &table_name)?;
+
+        Ok(DuckDBImportResult {
+            table_name,
+            row_count,
+            columns,
+        })
+    })
+    .await
+}
+
+/// Detect a CSV file's dialect and column types and return the first few rows
+#[tauri::command]
+pub async fn duckdb_sniff_csv(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    path: String,
+    sample_rows: Option<u32>,
+) -> Result<CsvSniffResult, DuckDBError> {
+    if !std::path::Path::new(&path).exists() {
+        return Err(DuckDBError {
+            message: format!("File not found: {}", path),
+            code: "FILE_NOT_FOUND".to_string(),
+        });
+    }
+    let sample_rows = sample_rows.unwrap_or(50).max(1);
+
+    let handle = get_connection(&state, &connection_id)?;
+    with_connection(handle.conn, move |conn| {
+        let sniff_error = |e: duckdb::Error| DuckDBError {
+            message: format!("Failed to sniff {}: {}", path, e),
+            code: "SNIFF_ERROR".to_string(),
+        };
+
+        // Both the sniffer and the LIMITed preview only read the start of the
+        // file, so this stays cheap for multi-GB inputs
+        let (delimiter, quote, newline, has_header, columns_json): (String, String, String, bool, String) = conn
+            .query_row(
+                &format!(
+                    "SELECT Delimiter, Quote, NewLineDelimiter, HasHeader, to_json(Columns)::VARCHAR \
+                     FROM sniff_csv({}, sample_size = {})",
+                    quote_literal(&path),
+                    sample_rows.max(1024)
+                ),
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+            )
+            .map_err(sniff_error)?;
+
+        let columns: Vec<CsvSniffColumn> = serde_json::from_str(&columns_json).map_err(|e| DuckDBError {
+            message: format!("Failed to parse sniffed columns: {}", e),
+            code: "SNIFF_ERROR".to_string(),
+        })?;
+
+        let preview = run_query(
+            conn,
+            &format!(
+                "SELECT * FROM read_csv_auto({}) LIMIT {}",
+                quote_literal(&path),
+                sample_rows
+            ),
+            &[],
+            0,
+            DEFAULT_MAX_INLINE_BLOB_BYTES,
+        )?;
+
+        Ok(CsvSniffResult {
+            delimiter,
+            quote,
+            newline,
+            has_header,
+            columns,
+            preview_rows: preview.rows,
+        })
+    })
+    .await
+}
+
+/// Append many rows to a table in one transaction using DuckDB's Appender.
+/// Columns of the table that are not listed receive NULL.
+#[tauri::command]
+pub async fn duckdb_append_rows(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    table: String,
+    columns: Vec<String>,
+    rows: Vec<Vec<serde_json::Value>>,
+) -> Result<DuckDBAppendResult, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+    let in_transaction = handle.in_transaction.load(Ordering::SeqCst);
+
+    with_connection(handle.conn, move |conn| {
+        let table_columns = query_table_columns(conn, &table)?;
+        if table_columns.is_empty() {
+            return Err(DuckDBError {
+                message: format!("Table not found: {}", table),
+                code: "TABLE_NOT_FOUND".to_string(),
+            });
+        }
+
+        // Map each provided column onto its position in the table
+        let positions = columns
+            .iter()
+            .map(|name| {
+                table_columns
+                    .iter()
+                    .position(|c| &c.name == name)
+                    .ok_or(DuckDBError {
+                        message: format!("Column '{}' does not exist in {}", name, table),
+                        code: "APPEND_ERROR".to_string(),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Convert everything up front so a bad value is reported before anything is written
+        let mut values = Vec::with_capacity(rows.len());
+        for (row_index, row) in rows.iter().enumerate() {
+            if row.len() != columns.len() {
+                return Err(DuckDBError {
+                    message: format!(
+                        "Row {} has {} values but {} columns were given",
+                        row_index,
+                        row.len(),
+                        columns.len()
+                    ),
+                    code: "APPEND_ERROR".to_string(),
+                });
+            }
+            let mut converted = vec![Value::Null; table_columns.len()];
+            for (value, &position) in row.iter().zip(&positions) {
+                let column = &table_columns[position];
+                converted[position] = json_to_column_value(value, &column.data_type).map_err(|reason| DuckDBError {
+                    message: format!("Row {}, column '{}': {}", row_index, column.name, reason),
+                    code: "APPEND_ERROR".to_string(),
+                })?;
+            }
+            values.push(converted);
+        }
+
+        // Inside an explicit transaction the rows simply become part of it
+        if in_transaction {
+            append_values(conn, &table, &values)?;
+        } else {
+            conn.execute_batch("BEGIN TRANSACTION").map_err(|e| DuckDBError {
+                message: format!("Failed to begin transaction: {}", e),
+                code: "TRANSACTION_ERROR".to_string(),
+            })?;
+
+            match append_values(conn, &table, &values) {
+                Ok(()) => conn.execute_batch("COMMIT").map_err(|e| DuckDBError {
+                    message: format!("Failed to commit appended rows: {}", e),
+                    code: "TRANSACTION_ERROR".to_string(),
+                })?,
+                Err(e) => {
+                    let _ = conn.execute_batch("ROLLBACK");
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(DuckDBAppendResult {
+            rows_appended: values.len(),
+        })
+    })
+    .await
+}
+
+/// Run a multi-statement script, returning an outcome for each statement
+#[tauri::command]
+pub async fn duckdb_execute_batch(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    sql: String,
+    stop_on_error: Option<bool>,
+) -> Result<DuckDBBatchResult, DuckDBError> {
+    let stop_on_error = stop_on_error.unwrap_or(true);
+    let statements = split_sql_statements(&sql);
+    let handle = get_connection(&state, &connection_id)?;
+
+    with_connection(handle.conn, move |conn| {
+        let mut outcomes = Vec::with_capacity(statements.len());
+        let mut first_error_index = None;
+
+        for (index, statement) in statements.into_iter().enumerate() {
+            let mut outcome = DuckDBStatementOutcome {
+                index,
+                statement,
+                result: None,
+                rows_affected: None,
+                error: None,
+            };
+            let failed = if returns_rows(&outcome.statement) {
+                match run_query(conn, &outcome.statement, &[], DEFAULT_MAX_ROWS, DEFAULT_MAX_INLINE_BLOB_BYTES) {
+                    Ok(result) => {
+                        outcome.result = Some(result);
+                        false
+                    }
+                    Err(e) => {
+                        outcome.error = Some(e);
+                        true
+                    }
+                }
+            } else {
+                match run_execute(conn, &outcome.statement, &[]) {
+                    Ok(result) => {
+                        outcome.rows_affected = Some(result.rows_affected);
+                        false
+                    }
+                    Err(e) => {
+                        outcome.error = Some(e);
+                        true
+                    }
+                }
+            };
+            outcomes.push(outcome);
+
+            if failed {
+                first_error_index.get_or_insert(index);
+                if stop_on_error {
+                    break;
+                }
+            }
+        }
+
+        Ok(DuckDBBatchResult {
+            statements: outcomes,
+            first_error_index,
+        })
+    })
+    .await
+}
+
+/// Run every statement in the text and return a result for each, so running
+/// a selection with several SELECTs shows all of them. Stops at the first
+/// failing statement.
+#[tauri::command]
+pub async fn duckdb_query_multi(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    sql: String,
+) -> Result<Vec<DuckDBStatementResult>, DuckDBError> {
+    let statements = split_sql_statements(&sql);
+    let handle = get_connection(&state, &connection_id)?;
+
+    with_connection(handle.conn, move |conn| {
+        let mut results = Vec::with_capacity(statements.len());
+        for (index, statement) in statements.into_iter().enumerate() {
+            let (result, rows_affected) = if returns_rows(&statement) {
+                let result = run_query(conn, &statement, &[], DEFAULT_MAX_ROWS, DEFAULT_MAX_INLINE_BLOB_BYTES);
+                (Some(result), None)
+            } else {
+                (None, Some(run_execute(conn, &statement, &[]).map(|r| r.rows_affected)))
+            };
+            let failed = |e: DuckDBError| DuckDBError {
+                message: format!("Statement {} failed: {}", index + 1, e.message),
+                code: e.code,
+            };
+            results.push(DuckDBStatementResult {
+                result: result.transpose().map_err(failed)?,
+                rows_affected: rows_affected.transpose().map_err(failed)?,
+                statement,
+            });
+        }
+        Ok(results)
+    })
+    .await
+}
+
+/// Start an explicit transaction on a connection
+#[tauri::command]
+pub async fn duckdb_begin_transaction(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+) -> Result<DuckDBTransactionState, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+    if handle.in_transaction.load(Ordering::SeqCst) {
+        return Err(DuckDBError {
+            message: "A transaction is already open on this connection".to_string(),
+            code: "TRANSACTION_ALREADY_OPEN".to_string(),
+        });
+    }
+
+    let in_transaction = Arc::clone(&handle.in_transaction);
+    with_connection(handle.conn, move |conn| {
+        conn.execute_batch("BEGIN TRANSACTION").map_err(|e| DuckDBError {
+            message: format!("Failed to begin transaction: {}", e),
+            code: "TRANSACTION_ERROR".to_string(),
+        })?;
+        in_transaction.store(true, Ordering::SeqCst);
+        Ok(DuckDBTransactionState { in_transaction: true })
+    })
+    .await
+}
+
+/// Commit the open transaction on a connection
+#[tauri::command]
+pub async fn duckdb_commit(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+) -> Result<DuckDBTransactionState, DuckDBError> {
+    end_transaction(&state, &connection_id, "COMMIT").await
+}
+
+/// Roll back the open transaction on a connection
+#[tauri::command]
+pub async fn duckdb_rollback(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+) -> Result<DuckDBTransactionState, DuckDBError> {
+    end_transaction(&state, &connection_id, "ROLLBACK").await
+}
+
+/// Report whether a connection has an open explicit transaction
+#[tauri::command]
+pub fn duckdb_transaction_status(
+    state: State<DuckDBState>,
+    connection_id: String,
+) -> Result<DuckDBTransactionState, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+    Ok(DuckDBTransactionState {
+        in_transaction: handle.in_transaction.load(Ordering::SeqCst),
+    })
+}
+
+/// Run a query and keep its result set open for paging with duckdb_query_fetch.
+/// The cursor reads through its own clone of the connection, so it does not
+/// see uncommitted changes from an explicit transaction on the original.
+#[tauri::command]
+pub async fn duckdb_query_open(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    sql: String,
+    params: Option<Vec<serde_json::Value>>,
+    max_inline_blob_bytes: Option<usize>,
+) -> Result<DuckDBCursorInfo, DuckDBError> {
+    let params = json_params_to_values(&params.unwrap_or_default())?;
+    let blob_limit = max_inline_blob_bytes.unwrap_or(DEFAULT_MAX_INLINE_BLOB_BYTES);
+    let handle = get_connection(&state, &connection_id)?;
+    let cursor_conn = lock_unpoisoned(&handle.clone_source).try_clone().map_err(|e| DuckDBError {
+        message: format!("Failed to clone connection: {}", e),
+        code: "CONNECTION_ERROR".to_string(),
+    })?;
+
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let (requests_tx, requests_rx) = mpsc::channel();
+    std::thread::spawn(move || run_cursor(cursor_conn, sql, params, blob_limit, ready_tx, requests_rx));
+
+    let metadata = tauri::async_runtime::spawn_blocking(move || ready_rx.recv())
+        .await
+        .map_err(|e| DuckDBError {
+            message: format!("Cursor task failed: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })?
+        .map_err(|_| DuckDBError {
+            message: "Cursor thread exited before the query ran".to_string(),
+            code: "QUERY_ERROR".to_string(),
+        })??;
+
+    let cursor_id = format!("cursor-{}", Uuid::new_v4());
+    lock_unpoisoned(&state.cursors).insert(
+        cursor_id.clone(),
+        DuckDBCursor {
+            connection_id,
+            requests: requests_tx,
+        },
+    );
+
+    Ok(DuckDBCursorInfo {
+        cursor_id,
+        columns: metadata.names,
+        column_types: metadata.types,
+        column_hints: metadata.hints,
+    })
+}
+
+/// Fetch up to `batch_size` more rows from a cursor; `done` is set once the
+/// result set is exhausted
+#[tauri::command]
+pub async fn duckdb_query_fetch(
+    state: State<'_, DuckDBState>,
+    cursor_id: String,
+    batch_size: usize,
+) -> Result<DuckDBCursorBatch, DuckDBError> {
+    let requests = get_cursor(&state, &cursor_id)?;
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let closed = || DuckDBError {
+        message: format!("Cursor is closed: {}", cursor_id),
+        code: "CURSOR_CLOSED".to_string(),
+    };
+
+    requests.send((batch_size.max(1), reply_tx)).map_err(|_| closed())?;
+    tauri::async_runtime::spawn_blocking(move || reply_rx.recv())
+        .await
+        .map_err(|e| DuckDBError {
+            message: format!("Cursor task failed: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })?
+        .map_err(|_| closed())?
+}
+
+/// Close a cursor and release its result set
+#[tauri::command]
+pub fn duckdb_query_close(state: State<DuckDBState>, cursor_id: String) -> Result<(), DuckDBError> {
+    lock_unpoisoned(&state.cursors)
+        .remove(&cursor_id)
+        .map(|_| ())
+        .ok_or_else(|| DuckDBError {
+            message: format!("Cursor not found: {}", cursor_id),
+            code: "CURSOR_NOT_FOUND".to_string(),
+        })
+}
+
+/// Fetch the full value of one cell, base64 encoded. `sql_or_table` is either
+/// a table name or a query, and `row_filter` is a SQL predicate that must match
+/// exactly one row.
+#[tauri::command]
+pub async fn duckdb_get_cell_blob(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    sql_or_table: String,
+    row_filter: String,
+    column: String,
+) -> Result<String, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+
+    let bytes = with_read_connection(handle, move |conn| {
+        fetch_cell_blob(conn, &sql_or_table, &row_filter, &column)
+    })
+    .await?;
+    Ok(base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes))
+}
+
+/// Write the full value of one cell to a file, returning the number of bytes
+/// written. Arguments are the same as for duckdb_get_cell_blob.
+#[tauri::command]
+pub async fn duckdb_save_cell_blob(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    sql_or_table: String,
+    row_filter: String,
+    column: String,
+    path: String,
+) -> Result<usize, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+
+    let bytes = with_read_connection(handle, move |conn| {
+        fetch_cell_blob(conn, &sql_or_table, &row_filter, &column)
+    })
+    .await?;
+    std::fs::write(&path, &bytes).map_err(|e| DuckDBError {
+        message: format!("Failed to write {}: {}", path, e),
+        code: "IO_ERROR".to_string(),
+    })?;
+    Ok(bytes.len())
+}
+
+/// Back up the whole database with EXPORT DATABASE into an empty (or new)
+/// directory. Emits "duckdb-export-progress" as each table's data file appears.
+#[tauri::command]
+pub async fn duckdb_export_database(
+    app: tauri::AppHandle,
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    target_dir: String,
+    format: String,
+) -> Result<DuckDBDatabaseTransferResult, DuckDBError> {
+    let format_sql = match format.to_lowercase().as_str() {
+        "parquet" => "PARQUET",
+        "csv" => "CSV",
+        other => {
+            return Err(DuckDBError {
+                message: format!("Unsupported export format: {}", other),
+                code: "INVALID_FORMAT".to_string(),
+            })
+        }
+    };
+
+    let target = std::path::Path::new(&target_dir);
+    let target_has_files = std::fs::read_dir(target)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false);
+    if target_has_files {
+        return Err(DuckDBError {
+            message: format!("Target directory is not empty: {}", target_dir),
+            code: "DIRECTORY_NOT_EMPTY".to_string(),
+        });
+    }
+    std::fs::create_dir_all(target).map_err(|e| DuckDBError {
+        message: format!("Failed to create directory {}: {}", target_dir, e),
+        code: "EXPORT_ERROR".to_string(),
+    })?;
+
+    let handle = get_connection(&state, &connection_id)?;
+    let started = Instant::now();
+    let tables = with_connection(handle.conn.clone(), list_table_summaries).await?;
+
+    let export_sql = format!(
+        "EXPORT DATABASE {} (FORMAT {})",
+        quote_literal(&target_dir),
+        format_sql
+    );
+    let export = with_connection(handle.conn, move |conn| {
+        conn.execute_batch(&export_sql)
+            .map_err(|e| query_error("Export failed", e, "EXPORT_ERROR"))
+    });
+    tokio::pin!(export);
+
+    // DuckDB writes one data file per table in turn, so watching the directory
+    // gives per-table progress without giving up EXPORT DATABASE's schema.sql
+    // and load.sql, which IMPORT DATABASE needs
+    let data_extension = format.to_lowercase();
+    let mut seen_files: Vec<String> = Vec::new();
+    let mut progress = DuckDBExportProgress {
+        connection_id,
+        target_dir: target_dir.clone(),
+        file: None,
+        tables_done: 0,
+        tables_total: tables.len(),
+    };
+    let _ = app.emit("duckdb-export-progress", progress.clone());
+
+    loop {
+        tokio::select! {
+            result = &mut export => {
+                result?;
+                break;
+            }
+            _ = tokio::time::sleep(std::time::Duration::from_millis(250)) => {
+                for file in list_data_files(target, &data_extension) {
+                    if !seen_files.contains(&file) {
+                        seen_files.push(file.clone());
+                        progress.file = Some(file);
+                        progress.tables_done = seen_files.len().min(progress.tables_total);
+                        let _ = app.emit("duckdb-export-progress", progress.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    progress.file = None;
+    progress.tables_done = progress.tables_total;
+    let _ = app.emit("duckdb-export-progress", progress);
+
+    Ok(DuckDBDatabaseTransferResult {
+        path: target_dir,
+        tables,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// Restore a directory written by EXPORT DATABASE into the connected database
+#[tauri::command]
+pub async fn duckdb_import_database(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    source_dir: String,
+) -> Result<DuckDBDatabaseTransferResult, DuckDBError> {
+    let source = std::path::Path::new(&source_dir);
+    if !source.join("schema.sql").exists() || !source.join("load.sql").exists() {
+        return Err(DuckDBError {
+            message: format!("Not an exported DuckDB database: {}", source_dir),
+            code: "INVALID_EXPORT_DIRECTORY".to_string(),
+        });
+    }
+
+    let handle = get_connection(&state, &connection_id)?;
+    with_connection(handle.conn, move |conn| {
+        let started = Instant::now();
+        let existing: Vec<(String, String)> = list_table_summaries(conn)?
+            .into_iter()
+            .map(|table| (table.schema, table.name))
+            .collect();
+
+        conn.execute_batch(&format!("IMPORT DATABASE {}", quote_literal(&source_dir)))
+            .map_err(|e| query_error("Import failed", e, "IMPORT_ERROR"))?;
+
+        let tables = list_table_summaries(conn)?
+            .into_iter()
+            .filter(|table| !existing.contains(&(table.schema.clone(), table.name.clone())))
+            .collect();
+
+        Ok(DuckDBDatabaseTransferResult {
+            path: source_dir,
+            tables,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        })
+    })
+    .await
+}
+
+/// Flush the WAL into the database file. `force` aborts running transactions
+/// instead of waiting for them.
+#[tauri::command]
+pub async fn duckdb_checkpoint(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    force: Option<bool>,
+) -> Result<DuckDBCompactResult, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+    ensure_writable(&handle)?;
+    let sql = if force.unwrap_or(false) { "FORCE CHECKPOINT" } else { "CHECKPOINT" };
+
+    with_connection(handle.conn, move |conn| {
+        let path = current_database_path(conn)?;
+        let size_before = database_file_size(path.as_deref());
+        conn.execute_batch(sql)
+            .map_err(|e| query_error("Checkpoint failed", e, "CHECKPOINT_ERROR"))?;
+
+        Ok(DuckDBCompactResult {
+            size_after: database_file_size(path.as_deref()),
+            path,
+            size_before,
+        })
+    })
+    .await
+}
+
+/// Reclaim space in the database file. With `into_path` the database is
+/// copied into a new, compacted file instead and the original is left as is.
+#[tauri::command]
+pub async fn duckdb_vacuum(
+    state: State<'_, DuckDBState>,
+    connection_id: String,
+    into_path: Option<String>,
+) -> Result<DuckDBCompactResult, DuckDBError> {
+    let handle = get_connection(&state, &connection_id)?;
+    ensure_writable(&handle)?;
+    if let Some(target) = &into_path {
+        if std::path::Path::new(target).exists() {
+            return Err(DuckDBError {
+                message: format!("File already exists: {}", target),
+                code: "FILE_EXISTS".to_string(),
+            });
+        }
+    }
+
+    with_connection(handle.conn, move |conn| {
+        let path = current_database_path(conn)?;
+        let size_before = database_file_size(path.as_deref());
+
+        let Some(target) = into_path else {
+            conn.execute_batch("VACUUM; CHECKPOINT;")
+                .map_err(|e| query_error("Vacuum failed", e, "VACUUM_ERROR"))?;
+            return Ok(DuckDBCompactResult {
+                size_after: database_file_size(path.as_deref()),
+                path,
+                size_before,
+            });
+        };
+
+        let source: String = conn
+            .query_row("SELECT current_database()", [], |row| row.get(0))
+            .map_err(|e| query_error("Vacuum failed", e, "VACUUM_ERROR"))?;
+        let alias = "seaquel_vacuum_target";
+        conn.execute_batch(&format!(
+            "ATTACH {} AS {}; COPY FROM DATABASE {} TO {};",
+            quote_literal(&target),
+            alias,
+            quote_identifier(&source),
+            alias
+        ))
+        .map_err(|e| query_error("Vacuum failed", e, "VACUUM_ERROR"))?;
+        conn.execute_batch(&format!("DETACH {}", alias))
+            .map_err(|e| query_error("Failed to detach compacted database", e, "VACUUM_ERROR"))?;
+
+        Ok(DuckDBCompactResult {
+            size_after: database_file_size(Some(&target)),
+            path: Some(target),
+            size_before,
+        })
+    })
+    .await
+}
+
+/// Open a file or in-memory database, optionally in read-only mode. Settings
+/// that DuckDB only accepts at startup are applied here; the rest are applied
+/// by apply_settings.
+fn open_connection(
+    path: &str,
+    read_only: bool,
+    motherduck_token: Option<&str>,
+    settings: &DuckDBSettings,
+) -> Result<Connection, DuckDBError> {
+    let config_error = |e: duckdb::Error| DuckDBError {
+        message: format!("Invalid connection setting: {}", e),
+        code: "CONFIG_ERROR".to_string(),
+    };
+
+    let mut config = Config::default();
+    if read_only {
+        config = config.access_mode(AccessMode::ReadOnly).map_err(|e| DuckDBError {
+            message: format!("Failed to configure read-only access: {}", e),
+            code: "CONFIG_ERROR".to_string(),
+        })?;
+    }
+    if let Some(dir) = &settings.extension_directory {
+        config = config.with("extension_directory", dir).map_err(config_error)?;
+    }
+    if settings.allow_unsigned_extensions.unwrap_or(false) {
+        config = config.with("allow_unsigned_extensions", "true").map_err(config_error)?;
+    }
+    // Passed as config rather than in the path so the token never shows up in
+    // SQL or in the connection string the frontend stores
+    if let Some(token) = motherduck_token.filter(|t| !t.is_empty()) {
+        config = config.with("motherduck_token", token).map_err(|e| DuckDBError {
+            message: format!("Failed to configure MotherDuck token: {}", e),
+            code: "CONFIG_ERROR".to_string(),
+        })?;
+    }
+
+    if path == ":memory:" || path.is_empty() {
+        Connection::open_in_memory_with_flags(config)
+    } else {
+        Connection::open_with_flags(path, config)
+    }
+    .map_err(|e| {
+        if is_motherduck_path(path) {
+            motherduck_error(&e.to_string())
+        } else {
+            open_error(&e.to_string())
+        }
+    })
+}
+
+/// Give the common reasons a database file fails to open their own codes so
+/// the frontend can suggest a fix
+fn open_error(message: &str) -> DuckDBError {
+    let lower = message.to_lowercase();
+
+    let (code, message) = if is_lock_error(message) {
+        let holder = match digits_after(message, "PID ") {
+            Some(pid) => format!("another process (PID {})", pid),
+            None => "another process".to_string(),
+        };
+        ("FILE_LOCKED", format!("Database file is locked by {}: {}", holder, message))
+    } else if lower.contains("version number")
+        || lower.contains("storage version")
+        || lower.contains("newer version of duckdb")
+        || lower.contains("older version of duckdb")
+    {
+        (
+            "STORAGE_VERSION_MISMATCH",
+            format!(
+                "Database file has storage version {}, which DuckDB {} bundled with Seaquel cannot read: {}",
+                digits_after(&lower, "version number ").unwrap_or("unknown"),
+                library_version(),
+                message
+            ),
+        )
+    } else if lower.contains("not a valid duckdb database file") {
+        ("NOT_A_DUCKDB_FILE", format!("File is not a DuckDB database: {}", message))
+    } else if lower.contains("permission denied") {
+        ("PERMISSION_DENIED", format!("Permission denied opening database: {}", message))
+    } else {
+        ("CONNECTION_ERROR", format!("Failed to open connection: {}", message))
+    };
+
+    DuckDBError {
+        message,
+        code: code.to_string(),
+    }
+}
+
+/// The run of digits directly after `marker` in `text`
+fn digits_after<'a>(text: &'a str, marker: &str) -> Option<&'a str> {
+    let start = text.find(marker)? + marker.len();
+    let rest = &text[start..];
+    let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    (end > 0).then(|| &rest[..end])
+}
+
+/// Version string of the bundled DuckDB library, e.g. "v1.4.3"
+fn library_version() -> String {
+    Connection::open_in_memory()
+        .and_then(|conn| conn.query_row("SELECT version()", [], |row| row.get::<_, String>(0)))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Clone a connection to the named in-memory database, creating the database
+/// if this is the first connection to use the name. Startup settings only take
+/// effect for that first connection.
+fn join_shared_memory(
+    state: &DuckDBState,
+    name: &str,
+    connection_id: &str,
+    read_only: bool,
+    settings: &DuckDBSettings,
+) -> Result<Connection, DuckDBError> {
+    if read_only {
+        return Err(DuckDBError {
+            message: "In-memory databases cannot be opened read-only".to_string(),
+            code: "CONFIG_ERROR".to_string(),
+        });
+    }
+
+    let mut shared = lock_unpoisoned(&state.shared_memory);
+    let database = match shared.entry(name.to_string()) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(SharedMemoryDatabase {
+            base: open_connection(":memory:", false, None, settings)?,
+            connection_ids: HashSet::new(),
+        }),
+    };
+
+    let conn = database.base.try_clone().map_err(|e| DuckDBError {
+        message: format!("Failed to clone connection: {}", e),
+        code: "CONNECTION_ERROR".to_string(),
+    })?;
+    database.connection_ids.insert(connection_id.to_string());
+    Ok(conn)
+}
+
+/// Remove a connection from a shared in-memory database, dropping the
+/// database when no connections are left
+fn leave_shared_memory(state: &DuckDBState, name: &str, connection_id: &str) {
+    let mut shared = lock_unpoisoned(&state.shared_memory);
+    if let Some(database) = shared.get_mut(name) {
+        database.connection_ids.remove(connection_id);
+        if database.connection_ids.is_empty() {
+            shared.remove(name);
+        }
+    }
+}
+
+fn is_motherduck_path(path: &str) -> bool {
+    path.starts_with("md:") || path.starts_with("motherduck:")
+}
+
+/// Tell a rejected MotherDuck token apart from the service being unreachable
+fn motherduck_error(message: &str) -> DuckDBError {
+    let lower = message.to_lowercase();
+    let code = if ["token", "authenticat", "unauthorized", "forbidden"]
+        .iter()
+        .any(|needle| lower.contains(needle))
+    {
+        "AUTH_FAILED"
+    } else if ["network", "connect", "timed out", "timeout", "resolve", "unreachable"]
+        .iter()
+        .any(|needle| lower.contains(needle))
+    {
+        "NETWORK_ERROR"
+    } else {
+        "CONNECTION_ERROR"
+    };
+
+    DuckDBError {
+        message: format!("Failed to connect to MotherDuck: {}", message),
+        code: code.to_string(),
+    }
+}
+
+/// Apply resource settings with `SET` and read back the values in effect
+fn apply_settings(
+    conn: &Connection,
+    settings: &DuckDBSettings,
+) -> Result<DuckDBEffectiveSettings, DuckDBError> {
+    let mut statements = Vec::new();
+    if let Some(memory_limit) = &settings.memory_limit {
+        statements.push(format!("SET memory_limit = {}", quote_literal(memory_limit)));
+    }
+    if let Some(threads) = settings.threads {
+        statements.push(format!("SET threads = {}", threads));
+    }
+    if let Some(temp_directory) = &settings.temp_directory {
+        statements.push(format!("SET temp_directory = {}", quote_literal(temp_directory)));
+    }
+
+    for statement in &statements {
+        conn.execute_batch(statement).map_err(|e| DuckDBError {
+            message: format!("Invalid connection setting ({}): {}", statement, e),
+            code: "CONFIG_ERROR".to_string(),
+        })?;
+    }
+    if let Some(proxy) = settings.http_proxy() {
+        apply_http_proxy(conn, &proxy)?;
+    }
+
+    conn.query_row(
+        "SELECT current_setting('memory_limit')::VARCHAR, \
+                current_setting('threads')::BIGINT, \
+                current_setting('temp_directory')::VARCHAR",
+        [],
+        |row| {
+            Ok(DuckDBEffectiveSettings {
+                memory_limit: row.get(0)?,
+                threads: row.get(1)?,
+                temp_directory: row.get(2)?,
+            })
+        },
+    )
+    .map_err(|e| DuckDBError {
+        message: format!("Failed to read connection settings: {}", e),
+        code: "CONFIG_ERROR".to_string(),
+    })
+}
+
+/// Point httpfs at a proxy. Before httpfs is loaded DuckDB may not know these
+/// settings, so that error is ignored and the proxy is applied again on LOAD.
+fn apply_http_proxy(conn: &Connection, proxy: &HttpProxy) -> Result<(), DuckDBError> {
+    let mut settings = vec![("http_proxy", &proxy.url)];
+    if let Some(username) = &proxy.username {
+        settings.push(("http_proxy_username", username));
+    }
+    if let Some(password) = &proxy.password {
+        settings.push(("http_proxy_password", password));
+    }
+
+    for (name, value) in settings {
+        if let Err(e) = conn.execute_batch(&format!("SET {} = {}", name, quote_literal(value))) {
+            let message = e.to_string();
+            if message.contains("unrecognized configuration parameter")
+                || message.contains("exists in the httpfs extension")
+            {
+                return Ok(());
+            }
+            // Never echo the statement itself, it may carry the password
+            return Err(DuckDBError {
+                message: redact_proxy_password(
+                    &format!("Invalid proxy setting ({}): {}", name, message),
+                    proxy,
+                ),
+                code: "CONFIG_ERROR".to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn redact_proxy_password(message: &str, proxy: &HttpProxy) -> String {
+    match proxy.password.as_deref().filter(|p| !p.is_empty()) {
+        Some(password) => message.replace(password, "***"),
+        None => message.to_string(),
+    }
+}
+
+/// Extension names are interpolated into INSTALL/LOAD, so only allow identifiers
+fn validate_extension_name(name: &str) -> Result<(), DuckDBError> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(DuckDBError {
+            message: format!("Invalid extension name: {}", name),
+            code: "INVALID_EXTENSION_NAME".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Names used unquoted in DDL must be plain identifiers
+fn validate_identifier(name: &str, kind: &str) -> Result<(), DuckDBError> {
+    let mut chars = name.chars();
+    let valid_start = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    if !valid_start || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(DuckDBError {
+            message: format!("Invalid {} name: {}", kind, name),
+            code: "INVALID_NAME".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Build a `CREATE OR REPLACE SECRET` statement with every value quoted
+fn build_create_secret_sql(secret: &DuckDBSecretConfig) -> Result<String, DuckDBError> {
+    validate_identifier(&secret.name, "secret")?;
+
+    let secret_type = secret.secret_type.to_lowercase();
+    let mut options = vec![format!("TYPE {}", secret_type)];
+    let mut push = |key: &str, value: &Option<String>| {
+        if let Some(value) = value.as_deref().filter(|v| !v.is_empty()) {
+            options.push(format!("{} {}", key, quote_literal(value)));
+        }
+    };
+
+    match secret_type.as_str() {
+        "s3" | "r2" | "gcs" => {
+            push("KEY_ID", &secret.key_id);
+            push("SECRET", &secret.secret);
+            push("SESSION_TOKEN", &secret.session_token);
+            push("REGION", &secret.region);
+            push("ENDPOINT", &secret.endpoint);
+            push("URL_STYLE", &secret.url_style);
+        }
+        "azure" => {
+            push("CONNECTION_STRING", &secret.connection_string);
+            push("ACCOUNT_NAME", &secret.account_name);
+        }
+        _ => {
+            return Err(DuckDBError {
+                message: format!("Unsupported secret type: {}", secret.secret_type),
+                code: "SECRET_ERROR".to_string(),
+            });
+        }
+    }
+    push("SCOPE", &secret.scope);
+
+    let persistence = if secret.persistent.unwrap_or(false) { "PERSISTENT " } else { "" };
+    Ok(format!(
+        "CREATE OR REPLACE {}SECRET {} ({})",
+        persistence,
+        secret.name,
+        options.join(", ")
+    ))
+}
+
+/// Strip credential values from a message before it is returned to the frontend
+fn redact_secret_values(message: &str, secret: &DuckDBSecretConfig) -> String {
+    let mut redacted = message.to_string();
+    for value in [&secret.secret, &secret.session_token, &secret.connection_string]
+        .into_iter()
+        .flatten()
+        .filter(|v| !v.is_empty())
+    {
+        redacted = redacted.replace(value.as_str(), "***");
+    }
+    redacted
+}
+
+/// Query `duckdb_secrets()`, leaving out the (redacted) secret string entirely
+fn query_secrets(conn: &Connection) -> Result<Vec<DuckDBSecretInfo>, DuckDBError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT name, type, provider, persistent, storage, to_json(scope)::VARCHAR \
+             FROM duckdb_secrets(redact := true) \
+             ORDER BY name",
+        )
+        .map_err(|e| DuckDBError {
+            message: format!("Failed to list secrets: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            let scope_json: String = row.get(5)?;
+            Ok(DuckDBSecretInfo {
+                name: row.get(0)?,
+                secret_type: row.get(1)?,
+                provider: row.get(2)?,
+                persistent: row.get(3)?,
+                storage: row.get(4)?,
+                scope: serde_json::from_str(&scope_json).unwrap_or_default(),
+            })
+        })
+        .map_err(|e| DuckDBError {
+            message: format!("Failed to list secrets: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| DuckDBError {
+        message: format!("Failed to read secret info: {}", e),
+        code: "QUERY_ERROR".to_string(),
+    })
+}
+
+/// Query `duckdb_extensions()`, optionally restricted to a single extension
+fn query_extensions(conn: &Connection, name: Option<&str>) -> Result<Vec<DuckDBExtension>, DuckDBError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT extension_name, installed, loaded, coalesce(description, ''), \
+                    extension_version, coalesce(installed_from, '') \
+             FROM duckdb_extensions() \
+             WHERE ?::VARCHAR IS NULL OR extension_name = ? \
+             ORDER BY extension_name",
+        )
+        .map_err(|e| DuckDBError {
+            message: format!("Failed to list extensions: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })?;
+
+    let rows = stmt
+        .query_map([name, name], |row| {
+            let installed_from: String = row.get(5)?;
+            let source = match installed_from.as_str() {
+                // Built-in and not-yet-installed extensions come from the core repository
+                "" | "core" | "core_nightly" => "core",
+                "community" => "community",
+                _ => "custom",
+            };
+            Ok(DuckDBExtension {
+                name: row.get(0)?,
+                installed: row.get(1)?,
+                loaded: row.get(2)?,
+                description: row.get(3)?,
+                version: row.get(4)?,
+                source: source.to_string(),
+            })
+        })
+        .map_err(|e| DuckDBError {
+            message: format!("Failed to list extensions: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| DuckDBError {
+        message: format!("Failed to read extension info: {}", e),
+        code: "QUERY_ERROR".to_string(),
+    })
+}
+
+/// Look up a single extension's status after installing or loading it
+fn find_extension(conn: &Connection, name: &str) -> Result<DuckDBExtension, DuckDBError> {
+    query_extensions(conn, Some(name))?
+        .into_iter()
+        .next()
+        .ok_or(DuckDBError {
+            message: format!("Extension not found: {}", name),
+            code: "EXTENSION_NOT_FOUND".to_string(),
+        })
+}
+
+/// List the user-visible catalogs attached to a connection
+fn query_databases(conn: &Connection) -> Result<Vec<DuckDBDatabaseInfo>, DuckDBError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT database_name, path, type, readonly \
+             FROM duckdb_databases() \
+             WHERE NOT internal \
+             ORDER BY database_name",
+        )
+        .map_err(|e| DuckDBError {
+            message: format!("Failed to list databases: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(DuckDBDatabaseInfo {
+                name: row.get(0)?,
+                path: row.get(1)?,
+                database_type: row.get(2)?,
+                read_only: row.get(3)?,
+            })
+        })
+        .map_err(|e| DuckDBError {
+            message: format!("Failed to list databases: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| DuckDBError {
+        message: format!("Failed to read database info: {}", e),
+        code: "QUERY_ERROR".to_string(),
+    })
+}
+
+/// Build the schema tree with two set-based queries: one for schemas, tables,
+/// and views, and one for all of their columns
+fn load_schema(conn: &Connection) -> Result<Vec<DuckDBCatalog>, DuckDBError> {
+    let schema_error = |e: duckdb::Error| DuckDBError {
+        message: format!("Failed to load schema: {}", e),
+        code: "SCHEMA_ERROR".to_string(),
+    };
+
+    let mut catalogs: BTreeMap<String, BTreeMap<String, DuckDBSchema>> = BTreeMap::new();
+    // (catalog, schema, object) -> (is_view, index into tables/views)
+    let mut object_index: HashMap<(String, String, String), (bool, usize)> = HashMap::new();
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT database_name, schema_name, NULL AS object_name, 'schema' AS kind, false AS temporary \
+             FROM duckdb_schemas() WHERE NOT internal \
+             UNION ALL \
+             SELECT database_name, schema_name, table_name, 'table', temporary \
+             FROM duckdb_tables() WHERE NOT internal \
+             UNION ALL \
+             SELECT database_name, schema_name, view_name, 'view', temporary \
+             FROM duckdb_views() WHERE NOT internal \
+             ORDER BY 1, 2, 3",
+        )
+        .map_err(schema_error)?;
+    let mut rows = stmt.query([]).map_err(schema_error)?;
+    while let Some(row) = rows.next().map_err(schema_error)? {
+        let catalog: String = row.get(0).map_err(schema_error)?;
+        let schema_name: String = row.get(1).map_err(schema_error)?;
+        let object_name: Option<String> = row.get(2).map_err(schema_error)?;
+        let kind: String = row.get(3).map_err(schema_error)?;
+        let temporary: bool = row.get(4).map_err(schema_error)?;
+
+        // Temp objects can live in schemas that duckdb_schemas() reports as internal
+        let schema = catalogs
+            .entry(catalog.clone())
+            .or_default()
+            .entry(schema_name.clone())
+            .or_insert_with(|| DuckDBSchema {
+                name: schema_name.clone(),
+                tables: Vec::new(),
+                views: Vec::new(),
+            });
+
+        let Some(object_name) = object_name else {
+            continue;
+        };
+        let table = DuckDBTable {
+            name: object_name.clone(),
+            temporary,
+            columns: Vec::new(),
+        };
+        let key = (catalog, schema_name, object_name);
+        if kind == "view" {
+            object_index.insert(key, (true, schema.views.len()));
+            schema.views.push(table);
+        } else {
+            object_index.insert(key, (false, schema.tables.len()));
+            schema.tables.push(table);
+        }
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT database_name, schema_name, table_name, column_name, data_type, \
+                    is_nullable, column_default \
+             FROM duckdb_columns() WHERE NOT internal \
+             ORDER BY database_name, schema_name, table_name, column_index",
+        )
+        .map_err(schema_error)?;
+    let mut rows = stmt.query([]).map_err(schema_error)?;
+    while let Some(row) = rows.next().map_err(schema_error)? {
+        let key: (String, String, String) = (
+            row.get(0).map_err(schema_error)?,
+            row.get(1).map_err(schema_error)?,
+            row.get(2).map_err(schema_error)?,
+        );
+        let Some(&(is_view, index)) = object_index.get(&key) else {
+            continue;
+        };
+        let Some(schema) = catalogs.get_mut(&key.0).and_then(|c| c.get_mut(&key.1)) else {
+            continue;
+        };
+        let table = if is_view { &mut schema.views[index] } else { &mut schema.tables[index] };
+        table.columns.push(DuckDBColumn {
+            name: row.get(3).map_err(schema_error)?,
+            data_type: row.get(4).map_err(schema_error)?,
+            nullable: row.get(5).map_err(schema_error)?,
+            default: row.get(6).map_err(schema_error)?,
+        });
+    }
+
+    Ok(catalogs
+        .into_iter()
+        .map(|(name, schemas)| DuckDBCatalog {
+            name,
+            schemas: schemas.into_values().collect(),
+        })
+        .collect())
+}
+
+/// Build the option list for `COPY ... TO` from the requested format and options
+fn build_copy_options(format: &str, options: &DuckDBExportOptions) -> Result<String, DuckDBError> {
+    let mut copy_options = Vec::new();
+    match format.to_lowercase().as_str() {
+        "parquet" => {
+            copy_options.push("FORMAT PARQUET".to_string());
+            if let Some(compression) = &options.compression {
+                let compression = compression.to_lowercase();
+                if !matches!(
+                    compression.as_str(),
+                    "snappy" | "zstd" | "gzip" | "lz4" | "brotli" | "uncompressed"
+                ) {
+                    return Err(DuckDBError {
+                        message: format!("Unsupported Parquet compression: {}", compression),
+                        code: "INVALID_OPTION".to_string(),
+                    });
+                }
+                copy_options.push(format!("COMPRESSION {}", compression));
+            }
+        }
+        "csv" => {
+            copy_options.push("FORMAT CSV".to_string());
+            copy_options.push(format!("HEADER {}", options.header.unwrap_or(true)));
+            if let Some(delimiter) = &options.delimiter {
+                copy_options.push(format!("DELIMITER {}", quote_literal(delimiter)));
+            }
+            if let Some(compression) = &options.compression {
+                let compression = compression.to_lowercase();
+                if !matches!(compression.as_str(), "gzip" | "zstd" | "none") {
+                    return Err(DuckDBError {
+                        message: format!("Unsupported CSV compression: {}", compression),
+                        code: "INVALID_OPTION".to_string(),
+                    });
+                }
+                copy_options.push(format!("COMPRESSION {}", compression));
+            }
+        }
+        _ => {
+            return Err(DuckDBError {
+                message: format!("Unsupported export format: {}", format),
+                code: "INVALID_OPTION".to_string(),
+            });
+        }
+    }
+    Ok(copy_options.join(", "))
+}
+
+/// Add `.gz`/`.zst` to a CSV export path for the chosen compression, unless
+/// the path already ends with it
+fn with_compression_extension(path: String, compression: Option<&str>) -> String {
+    let extension = match compression.map(str::to_lowercase).as_deref() {
+        Some("gzip") => ".gz",
+        Some("zstd") => ".zst",
+        _ => return path,
+    };
+    if path.to_lowercase().ends_with(extension) {
+        path
+    } else {
+        format!("{}{}", path, extension)
+    }
+}
+
+/// Pick the table function used to read a data file, from the explicit format
+/// or else the file extension
+fn build_file_reader(path: &str, options: &DuckDBImportOptions) -> Result<String, DuckDBError> {
+    let format = match &options.format {
+        Some(format) => format.to_lowercase(),
+        None => {
+            let lower = path.to_lowercase();
+            let lower = lower
+                .strip_suffix(".gz")
+                .or_else(|| lower.strip_suffix(".zst"))
+                .unwrap_or(&lower);
+            match std::path::Path::new(lower).extension().and_then(|e| e.to_str()) {
+                Some("parquet") => "parquet".to_string(),
+                Some("json") | Some("jsonl") | Some("ndjson") => "json".to_string(),
+                Some("csv") | Some("tsv") | Some("txt") => "csv".to_string(),
+                _ => {
+                    return Err(DuckDBError {
+                        message: format!("Cannot infer the file format of {}; specify it explicitly", path),
+                        code: "INVALID_OPTION".to_string(),
+                    });
+                }
+            }
+        }
+    };
+
+    let mut args = vec![quote_literal(path)];
+    match format.as_str() {
+        "csv" => {
+            if let Some(header) = options.header {
+                args.push(format!("header = {}", header));
+            }
+            if let Some(delimiter) = &options.delimiter {
+                args.push(format!("delim = {}", quote_literal(delimiter)));
+            }
+            if let Some(sample_size) = options.sample_size {
+                args.push(format!("sample_size = {}", sample_size));
+            }
+            Ok(format!("read_csv_auto({})", args.join(", ")))
+        }
+        "parquet" => Ok(format!("read_parquet({})", args.join(", "))),
+        "json" => {
+            if let Some(sample_size) = options.sample_size {
+                args.push(format!("sample_size = {}", sample_size));
+            }
+            Ok(format!("read_json_auto({})", args.join(", ")))
+        }
+        _ => Err(DuckDBError {
+            message: format!("Unsupported import format: {}", format),
+            code: "INVALID_OPTION".to_string(),
+        }),
+    }
+}
+
+/// CREATE TABLE statement rebuilt from duckdb_columns() and duckdb_constraints()
+fn build_table_ddl(conn: &Connection, schema: &str, table: &str) -> Result<String, DuckDBError> {
+    let ddl_error = |e| query_error("Failed to read table definition", e, "QUERY_ERROR");
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT column_name, data_type, column_default, is_nullable FROM duckdb_columns() \
+             WHERE database_name = current_database() AND schema_name = ? AND table_name = ? \
+             ORDER BY column_index",
+        )
+        .map_err(ddl_error)?;
+    let mut lines = stmt
+        .query_map([schema, table], |row| {
+            let name: String = row.get(0)?;
+            let data_type: String = row.get(1)?;
+            let default: Option<String> = row.get(2)?;
+            let nullable: bool = row.get(3)?;
+
+            let mut line = format!("{} {}", quote_identifier(&name), data_type);
+            if let Some(default) = default {
+                line.push_str(&format!(" DEFAULT {}", default));
+            }
+            if !nullable {
+                line.push_str(" NOT NULL");
+            }
+            Ok(line)
+        })
+        .map_err(ddl_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(ddl_error)?;
+
+    if lines.is_empty() {
+        return Err(DuckDBError {
+            message: format!("Table not found: {}.{}", schema, table),
+            code: "TABLE_NOT_FOUND".to_string(),
+        });
+    }
+
+    // NOT NULL constraints are already on the columns
+    let mut stmt = conn
+        .prepare(
+            "SELECT constraint_type, constraint_text, to_json(constraint_column_names)::VARCHAR \
+             FROM duckdb_constraints() \
+             WHERE database_name = current_database() AND schema_name = ? AND table_name = ? \
+               AND constraint_type <> 'NOT NULL' \
+             ORDER BY constraint_index",
+        )
+        .map_err(ddl_error)?;
+    let constraints = stmt
+        .query_map([schema, table], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })
+        .map_err(ddl_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(ddl_error)?;
+
+    for (constraint_type, text, columns_json) in constraints {
+        let columns: Vec<String> = serde_json::from_str(&columns_json).unwrap_or_default();
+        let quoted = columns.iter().map(|c| quote_identifier(c)).collect::<Vec<_>>().join(", ");
+        match constraint_type.as_str() {
+            "PRIMARY KEY" | "UNIQUE" if !columns.is_empty() => {
+                lines.push(format!("{} ({})", constraint_type, quoted));
+            }
+            _ => lines.push(text),
+        }
+    }
+
+    Ok(format!(
+        "CREATE TABLE {}.{} (\n    {}\n);",
+        quote_identifier(schema),
+        quote_identifier(table),
+        lines.join(",\n    ")
+    ))
+}
+
+fn ensure_writable(handle: &DuckDBHandle) -> Result<(), DuckDBError> {
+    if handle.read_only {
+        return Err(DuckDBError {
+            message: "Connection is read-only".to_string(),
+            code: "READ_ONLY".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// File backing the current database, or None for in-memory databases
+fn current_database_path(conn: &Connection) -> Result<Option<String>, DuckDBError> {
+    conn.query_row(
+        "SELECT path FROM duckdb_databases() WHERE database_name = current_database()",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| query_error("Failed to read database path", e, "QUERY_ERROR"))
+}
+
+/// Size of a database file plus its write-ahead log, 0 if there is no file
+fn database_file_size(path: Option<&str>) -> u64 {
+    let Some(path) = path else {
+        return 0;
+    };
+    let size = |p: &str| std::fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+    size(path) + size(&format!("{}.wal", path))
+}
+
+/// Every base table in the current database with its exact row count
+fn list_table_summaries(conn: &Connection) -> Result<Vec<DuckDBTableSummary>, DuckDBError> {
+    let list_error = |e| query_error("Failed to list tables", e, "QUERY_ERROR");
+    let mut stmt = conn
+        .prepare(
+            "SELECT schema_name, table_name FROM duckdb_tables() \
+             WHERE database_name = current_database() AND NOT temporary \
+             ORDER BY schema_name, table_name",
+        )
+        .map_err(list_error)?;
+    let names = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+        .map_err(list_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(list_error)?;
+
+    names
+        .into_iter()
+        .map(|(schema, name)| {
+            let rows: i64 = conn
+                .query_row(
+                    &format!("SELECT count(*) FROM {}.{}", quote_identifier(&schema), quote_identifier(&name)),
+                    [],
+                    |row| row.get(0),
+                )
+                .map_err(list_error)?;
+            Ok(DuckDBTableSummary {
+                schema,
+                name,
+                rows: rows.max(0) as u64,
+            })
+        })
+        .collect()
+}
+
+/// Data files with the given extension in an export directory
+fn list_data_files(dir: &std::path::Path, extension: &str) -> Vec<String> {
+    let mut files: Vec<String> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().and_then(|e| e.to_str()) == Some(extension))
+                .filter_map(|path| path.file_name().and_then(|n| n.to_str()).map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// Column definitions for a table in the current schema
+fn query_table_columns(conn: &Connection, table_name: &str) -> Result<Vec<DuckDBColumn>, DuckDBError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT column_name, data_type, is_nullable, column_default \
+             FROM duckdb_columns() \
+             WHERE database_name = current_database() \
+               AND schema_name = current_schema() \
+               AND table_name = ? \
+             ORDER BY column_index",
+        )
+        .map_err(|e| DuckDBError {
+            message: format!("Failed to read columns: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })?;
+
+    let rows = stmt
+        .query_map([table_name], |row| {
+            Ok(DuckDBColumn {
+                name: row.get(0)?,
+                data_type: row.get(1)?,
+                nullable: row.get(2)?,
+                default: row.get(3)?,
+            })
+        })
+        .map_err(|e| DuckDBError {
+            message: format!("Failed to read columns: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| DuckDBError {
+        message: format!("Failed to read columns: {}", e),
+        code: "QUERY_ERROR".to_string(),
+    })
+}
+
+/// Quote an identifier, escaping embedded double quotes
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Whether a DuckDB error was caused by another process holding the file lock
+fn is_lock_error(message: &str) -> bool {
+    message.contains("Could not set lock") || message.contains("Conflicting lock")
+}
+
+/// Quote a string as a SQL literal, escaping embedded single quotes
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Lock a mutex, recovering the data if an earlier holder panicked. Panics in
+/// query code are caught before they reach shared state, so the maps are
+/// still consistent.
+fn lock_unpoisoned<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Look up a connection, holding the map lock only for the lookup itself
+fn get_connection(state: &DuckDBState, connection_id: &str) -> Result<DuckDBHandle, DuckDBError> {
+    lock_unpoisoned(&state.connections)
+        .get(connection_id)
+        .cloned()
+        .ok_or(DuckDBError {
+            message: format!("Connection not found: {}", connection_id),
+            code: "CONNECTION_NOT_FOUND".to_string(),
+        })
+}
+
+/// Run `f` against a connection on a blocking thread so a long query doesn't
+/// stall the async runtime or queries on other connections
+async fn with_connection<T, F>(conn: Arc<Mutex<Connection>>, f: F) -> Result<T, DuckDBError>
+where
+    T: Send + 'static,
+    F: FnOnce(&Connection) -> Result<T, DuckDBError> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = lock_unpoisoned(&conn);
+        catch_panic(|| f(&conn))
+    })
+    .await
+    .map_err(|e| DuckDBError {
+        message: format!("Query task failed: {}", e),
+        code: "INTERNAL_ERROR".to_string(),
+    })?
+}
+
+/// Like with_connection, but for work that only reads. If the connection is
+/// busy, the work runs on a fresh clone instead of queueing behind it. Inside
+/// an explicit transaction it always waits, since a clone would not see the
+/// transaction's uncommitted changes.
+async fn with_read_connection<T, F>(handle: DuckDBHandle, f: F) -> Result<T, DuckDBError>
+where
+    T: Send + 'static,
+    F: FnOnce(&Connection) -> Result<T, DuckDBError> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(move || {
+        let guard = match handle.conn.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) if handle.in_transaction.load(Ordering::SeqCst) => {
+                Some(lock_unpoisoned(&handle.conn))
+            }
+            Err(TryLockError::WouldBlock) => None,
+        };
+
+        match guard {
+            Some(conn) => catch_panic(|| f(&conn)),
+            None => {
+                let clone = ActiveClone::new(&handle)?;
+                catch_panic(|| f(&clone.conn))
+            }
+        }
+    })
+    .await
+    .map_err(|e| DuckDBError {
+        message: format!("Query task failed: {}", e),
+        code: "INTERNAL_ERROR".to_string(),
+    })?
+}
+
+/// Run query work, turning a panic inside duckdb-rs (e.g. on a malformed
+/// file) into an error instead of poisoning the connection for later queries
+fn catch_panic<T>(f: impl FnOnce() -> Result<T, DuckDBError>) -> Result<T, DuckDBError> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        Err(DuckDBError {
+            message: format!("DuckDB panicked: {}", panic_message(payload.as_ref())),
+            code: "INTERNAL_PANIC".to_string(),
+        })
+    })
+}
+
+/// Run a query and convert up to `max_rows` rows to JSON (0 means unlimited).
+/// Rows past the limit are counted but not converted, up to ROW_COUNT_SCAN_LIMIT.
+fn run_query(
+    conn: &Connection,
+    sql: &str,
+    params: &[Value],
+    max_rows: usize,
+    blob_limit: usize,
+) -> Result<DuckDBQueryResult, DuckDBError> {
+    let mut stmt = conn.prepare(sql).map_err(|e| DuckDBError {
+        message: format!("Failed to prepare query: {}", e),
+        code: "QUERY_ERROR".to_string(),
+    })?;
+
+    // Execute query first - column metadata is only available after execution
+    let started = Instant::now();
+    let mut result_rows = stmt
+        .query(params_from_iter(params.iter()))
+        .map_err(|e| query_error("Failed to execute query", e, "QUERY_ERROR"))?;
+    let mut execution = started.elapsed();
+
+    let metadata = column_metadata(&result_rows);
+
+    let mut rows: Vec<Vec<serde_json::Value>> = Vec::new();
+    let mut truncated = false;
+    let mut skipped_rows = 0usize;
+    let mut fully_counted = true;
+
+    loop {
+        // Only time the fetch itself; value conversion below is not execution
+        let fetch_started = Instant::now();
+        let next = result_rows.next();
+        execution += fetch_started.elapsed();
+        let Some(row) = next.map_err(|e| query_error("Failed to read row", e, "QUERY_ERROR"))? else {
+            break;
+        };
+
+        if max_rows > 0 && rows.len() >= max_rows {
+            truncated = true;
+            skipped_rows += 1;
+            if skipped_rows >= ROW_COUNT_SCAN_LIMIT {
+                fully_counted = false;
+                break;
+            }
+            continue;
+        }
+
+        rows.push(convert_row(row, &metadata.hints, blob_limit)?);
+    }
+
+    let total_rows_estimate = fully_counted.then(|| rows.len() + skipped_rows);
+
+    Ok(DuckDBQueryResult {
+        columns: metadata.names,
+        column_types: metadata.types,
+        column_hints: metadata.hints,
+        row_count: rows.len(),
+        rows,
+        execution_ms: execution.as_millis() as u64,
+        truncated,
+        total_rows_estimate,
+    })
+}
+
+/// The explain_value column of every row an EXPLAIN statement returns
+fn query_explain_output(conn: &Connection, sql: &str) -> Result<Vec<String>, DuckDBError> {
+    let mut stmt = conn.prepare(sql).map_err(|e| DuckDBError {
+        message: format!("Failed to explain query: {}", e),
+        code: "QUERY_ERROR".to_string(),
+    })?;
+    explain_rows(&mut stmt)
+}
+
+/// Run a prepared EXPLAIN and collect its explain_value column
+fn explain_rows(stmt: &mut duckdb::Statement<'_>) -> Result<Vec<String>, DuckDBError> {
+    let rows = stmt
+        .query_map([], |row| row.get::<_, String>(1))
+        .map_err(|e| query_error("Failed to explain query", e, "QUERY_ERROR"))?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| query_error("Failed to read query plan", e, "QUERY_ERROR"))
+}
+
+/// Plan nodes from DuckDB's JSON output. EXPLAIN yields an array of operators;
+/// EXPLAIN ANALYZE yields a query-level object whose children are the operators.
+fn plan_nodes(value: &serde_json::Value) -> Vec<DuckDBPlanNode> {
+    match value {
+        serde_json::Value::Array(items) => items.iter().flat_map(plan_nodes).collect(),
+        serde_json::Value::Object(obj)
+            if ["name", "operator_name", "operator_type"].iter().any(|key| obj.contains_key(*key)) =>
+        {
+            vec![plan_node(obj)]
+        }
+        serde_json::Value::Object(obj) => obj.get("children").map(plan_nodes).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+fn plan_node(obj: &serde_json::Map<String, serde_json::Value>) -> DuckDBPlanNode {
+    let name = ["name", "operator_name", "operator_type"]
+        .iter()
+        .find_map(|key| obj.get(*key).and_then(|v| v.as_str()))
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let extra_info = obj.get("extra_info").cloned().unwrap_or(serde_json::Value::Null);
+
+    // Estimated cardinality is reported as a string such as "~42"
+    let cardinality = obj
+        .get("operator_cardinality")
+        .and_then(|v| v.as_u64())
+        .or_else(|| match extra_info.get("Estimated Cardinality") {
+            Some(serde_json::Value::String(s)) => s.trim_start_matches('~').trim().parse().ok(),
+            Some(v) => v.as_u64(),
+            None => None,
+        });
+
+    DuckDBPlanNode {
+        name,
+        cardinality,
+        timing_ms: obj
+            .get("operator_timing")
+            .and_then(|v| v.as_f64())
+            .map(|seconds| seconds * 1000.0),
+        extra_info,
+        children: obj.get("children").map(plan_nodes).unwrap_or_default(),
+    }
+}
+
+/// Read the raw bytes of a single cell for duckdb_get_cell_blob
+fn fetch_cell_blob(
+    conn: &Connection,
+    sql_or_table: &str,
+    row_filter: &str,
+    column: &str,
+) -> Result<Vec<u8>, DuckDBError> {
+    let source = if returns_rows(sql_or_table) {
+        format!("({}) AS source", sql_or_table.trim().trim_end_matches(';'))
+    } else {
+        quote_identifier(sql_or_table)
+    };
+    let filter = if row_filter.trim().is_empty() { "true" } else { row_filter };
+    let sql = format!(
+        "SELECT {} FROM {} WHERE {} LIMIT 2",
+        quote_identifier(column),
+        source,
+        filter
+    );
+
+    let mut stmt = conn.prepare(&sql).map_err(|e| DuckDBError {
+        message: format!("Failed to prepare cell query: {}", e),
+        code: "QUERY_ERROR".to_string(),
+    })?;
+    let mut rows = stmt
+        .query([])
+        .map_err(|e| query_error("Failed to read cell", e, "QUERY_ERROR"))?;
+
+    let read_error = |e| query_error("Failed to read cell", e, "QUERY_ERROR");
+    let bytes = match rows.next().map_err(read_error)? {
+        Some(row) => match row.get_ref(0).map_err(read_error)? {
+            ValueRef::Blob(b) => b.to_vec(),
+            ValueRef::Text(t) => t.to_vec(),
+            ValueRef::Null => {
+                return Err(DuckDBError {
+                    message: format!("Cell {} is NULL", column),
+                    code: "CELL_NULL".to_string(),
+                })
+            }
+            _ => {
+                return Err(DuckDBError {
+                    message: format!("Column {} is not a BLOB or text column", column),
+                    code: "INVALID_COLUMN_TYPE".to_string(),
+                })
+            }
+        },
+        None => {
+            return Err(DuckDBError {
+                message: "No row matches the filter".to_string(),
+                code: "ROW_NOT_FOUND".to_string(),
+            })
+        }
+    };
+
+    if rows.next().map_err(read_error)?.is_some() {
+        return Err(DuckDBError {
+            message: "More than one row matches the filter".to_string(),
+            code: "AMBIGUOUS_ROW".to_string(),
+        });
+    }
+    Ok(bytes)
+}
+
+/// Names, DuckDB type names, and conversion hints of a result's columns
+struct ColumnMetadata {
+    names: Vec<String>,
+    types: Vec<String>,
+    hints: Vec<Option<String>>,
+}
+
+/// Column metadata of an executed statement, with type names read from the
+/// Arrow schema of its result
+fn column_metadata(rows: &duckdb::Rows<'_>) -> ColumnMetadata {
+    let column_count = rows.as_ref().map(|s| s.column_count()).unwrap_or(0);
+    let schema = rows.as_ref().map(|s| s.schema());
+
+    let mut metadata = ColumnMetadata {
+        names: Vec::with_capacity(column_count),
+        types: Vec::with_capacity(column_count),
+        hints: Vec::with_capacity(column_count),
+    };
+    for i in 0..column_count {
+        let field = schema.as_ref().and_then(|schema| schema.fields().get(i));
+        let type_name = field.and_then(|field| duckdb_type_name(field));
+
+        metadata.names.push(
+            rows.as_ref()
+                .and_then(|s| s.column_name(i).ok())
+                .map(|s| s.to_string())
+                .unwrap_or_default(),
+        );
+        metadata
+            .hints
+            .push(field.and_then(|field| column_hint(field.data_type(), type_name.as_deref())));
+        metadata.types.push(type_name.unwrap_or_else(|| "UNKNOWN".to_string()));
+    }
+    metadata
+}
+
+/// DuckDB's name for the type of a result column, rebuilt from the Arrow
+/// field DuckDB exported it as. HUGEINT and DECIMAL(38,0) look the same there.
+fn duckdb_type_name(field: &Field) -> Option<String> {
+    if field.metadata().get("ARROW:extension:name").map(String::as_str) == Some("arrow.uuid") {
+        return Some("UUID".to_string());
+    }
+    let name = match field.data_type() {
+        DataType::Null => "NULL",
+        DataType::Boolean => "BOOLEAN",
+        DataType::Int8 => "TINYINT",
+        DataType::Int16 => "SMALLINT",
+        DataType::Int32 => "INTEGER",
+        DataType::Int64 => "BIGINT",
+        DataType::UInt8 => "UTINYINT",
+        DataType::UInt16 => "USMALLINT",
+        DataType::UInt32 => "UINTEGER",
+        DataType::UInt64 => "UBIGINT",
+        DataType::Float32 => "FLOAT",
+        DataType::Float64 => "DOUBLE",
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => "VARCHAR",
+        DataType::Binary | DataType::LargeBinary | DataType::BinaryView => "BLOB",
+        DataType::Date32 => "DATE",
+        DataType::Time64(_) => "TIME",
+        DataType::Timestamp(_, None) => "TIMESTAMP",
+        DataType::Timestamp(_, Some(_)) => "TIMESTAMP WITH TIME ZONE",
+        DataType::Interval(_) => "INTERVAL",
+        // ENUMs are exported as dictionaries of their labels
+        DataType::Dictionary(..) => "ENUM",
+        DataType::Decimal128(38, 0) => "HUGEINT",
+        DataType::Decimal128(precision, scale) => return Some(format!("DECIMAL({},{})", precision, scale)),
+        DataType::List(item) | DataType::LargeList(item) => return Some(format!("{}[]", duckdb_type_name(item)?)),
+        DataType::FixedSizeList(item, size) => return Some(format!("{}[{}]", duckdb_type_name(item)?, size)),
+        DataType::Struct(fields) => return Some(format!("STRUCT({})", member_types(fields.iter())?)),
+        DataType::Union(fields, _) => {
+            return Some(format!("UNION({})", member_types(fields.iter().map(|(_, field)| field))?));
+        }
+        DataType::Map(entries, _) => {
+            let DataType::Struct(key_value) = entries.data_type() else {
+                return None;
+            };
+            return Some(format!(
+                "MAP({}, {})",
+                duckdb_type_name(key_value.first()?)?,
+                duckdb_type_name(key_value.get(1)?)?
+            ));
+        }
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+/// `name TYPE` pairs of STRUCT and UNION members, quoting names as DuckDB does
+fn member_types<'a>(fields: impl Iterator<Item = &'a Arc<Field>>) -> Option<String> {
+    let members = fields
+        .map(|field| {
+            let name = field.name();
+            let plain = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !name.starts_with(|c: char| c.is_ascii_digit());
+            let name = if plain { name.to_string() } else { quote_identifier(name) };
+            Some(format!("{} {}", name, duckdb_type_name(field)?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(members.join(", "))
+}
+
+/// Convert one result row. Blobs over `blob_limit` bytes (0 means no limit)
+/// become a placeholder the UI can resolve with duckdb_get_cell_blob.
+fn convert_row(
+    row: &duckdb::Row<'_>,
+    hints: &[Option<String>],
+    blob_limit: usize,
+) -> Result<Vec<serde_json::Value>, DuckDBError> {
+    hints
+        .iter()
+        .enumerate()
+        .map(|(i, hint)| {
+            let value = row.get_ref(i).map_err(|e| DuckDBError {
+                message: format!("Failed to get column value: {}", e),
+                code: "QUERY_ERROR".to_string(),
+            })?;
+            match (value, hint.as_deref()) {
+                // Keep wide decimal columns uniformly textual, even for short values
+                (ValueRef::Decimal(d), Some("decimal")) => Ok(serde_json::json!(d.to_string())),
+                (value, Some("uuid")) => uuid_to_json(value),
+                (ValueRef::Blob(b), _) if blob_limit > 0 && b.len() > blob_limit => {
+                    Ok(serde_json::json!({ "__blob_truncated": true, "size": b.len() }))
+                }
+                (value, _) => convert_value_to_json(value),
+            }
+        })
+        .collect()
+}
+
+/// Body of a cursor thread. The statement and its result rows borrow the
+/// connection, so they live on this thread until the request channel closes.
+fn run_cursor(
+    conn: Connection,
+    sql: String,
+    params: Vec<Value>,
+    blob_limit: usize,
+    ready: mpsc::Sender<Result<ColumnMetadata, DuckDBError>>,
+    requests: mpsc::Receiver<CursorRequest>,
+) {
+    let mut stmt = match conn.prepare(&sql) {
+        Ok(stmt) => stmt,
+        Err(e) => {
+            let _ = ready.send(Err(DuckDBError {
+                message: format!("Failed to prepare query: {}", e),
+                code: "QUERY_ERROR".to_string(),
+            }));
+            return;
+        }
+    };
+    let mut rows = match stmt.query(params_from_iter(params.iter())) {
+        Ok(rows) => rows,
+        Err(e) => {
+            let _ = ready.send(Err(query_error("Failed to execute query", e, "QUERY_ERROR")));
+            return;
+        }
+    };
+
+    let metadata = column_metadata(&rows);
+    let column_hints = metadata.hints.clone();
+    if ready.send(Ok(metadata)).is_err() {
+        return;
+    }
+
+    let mut done = false;
+    while let Ok((batch_size, reply)) = requests.recv() {
+        let batch = fetch_batch(&mut rows, &column_hints, blob_limit, batch_size, &mut done)
+            .map(|rows| DuckDBCursorBatch { rows, done });
+        let _ = reply.send(batch);
+    }
+}
+
+fn fetch_batch(
+    rows: &mut duckdb::Rows<'_>,
+    column_hints: &[Option<String>],
+    blob_limit: usize,
+    batch_size: usize,
+    done: &mut bool,
+) -> Result<Vec<Vec<serde_json::Value>>, DuckDBError> {
+    let mut batch = Vec::new();
+    while !*done && batch.len() < batch_size {
+        match rows
+            .next()
+            .map_err(|e| query_error("Failed to read row", e, "QUERY_ERROR"))?
+        {
+            Some(row) => batch.push(convert_row(row, column_hints, blob_limit)?),
+            None => *done = true,
+        }
+    }
+    Ok(batch)
+}
+
+fn get_cursor(state: &DuckDBState, cursor_id: &str) -> Result<mpsc::Sender<CursorRequest>, DuckDBError> {
+    lock_unpoisoned(&state.cursors)
+        .get(cursor_id)
+        .map(|cursor| cursor.requests.clone())
+        .ok_or_else(|| DuckDBError {
+            message: format!("Cursor not found: {}", cursor_id),
+            code: "CURSOR_NOT_FOUND".to_string(),
+        })
+}
+
+/// Run a statement and report how many rows it affected
+fn run_execute(conn: &Connection, sql: &str, params: &[Value]) -> Result<DuckDBExecuteResult, DuckDBError> {
+    let started = Instant::now();
+    let rows_affected = conn
+        .execute(sql, params_from_iter(params.iter()))
+        .map_err(|e| query_error("Failed to execute statement", e, "EXECUTE_ERROR"))?;
+
+    Ok(DuckDBExecuteResult {
+        rows_affected,
+        execution_ms: started.elapsed().as_millis() as u64,
+        statement_type: leading_keyword(sql),
+    })
+}
+
+/// Map a DuckDB execution error, reporting interrupted queries as cancelled
+fn query_error(context: &str, e: duckdb::Error, code: &str) -> DuckDBError {
+    let message = e.to_string();
+    if message.contains("INTERRUPT") || message.contains("Interrupted") {
+        DuckDBError {
+            message: "Query was cancelled".to_string(),
+            code: "QUERY_CANCELLED".to_string(),
+        }
+    } else {
+        DuckDBError {
+            message: format!("{}: {}", context, message),
+            code: code.to_string(),
+        }
+    }
+}
+
+/// Convert JSON query parameters into DuckDB values for positional `?` binding.
+///
+/// Scalars map onto the matching DuckDB type. `{"$blob": "<base64>"}` binds a
+/// BLOB, and any other array or object is bound as JSON text so it can be cast
+/// in SQL (e.g. `?::INTEGER[]`).
+fn json_params_to_values(params: &[serde_json::Value]) -> Result<Vec<Value>, DuckDBError> {
+    params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| match param {
+            serde_json::Value::Null => Ok(Value::Null),
+            serde_json::Value::Bool(b) => Ok(Value::Boolean(*b)),
+            serde_json::Value::Number(n) => {
+                if let Some(v) = n.as_i64() {
+                    Ok(Value::BigInt(v))
+                } else if let Some(v) = n.as_u64() {
+                    Ok(Value::UBigInt(v))
+                } else {
+                    Ok(Value::Double(n.as_f64().unwrap_or(f64::NAN)))
+                }
+            }
+            serde_json::Value::String(s) => Ok(Value::Text(s.clone())),
+            serde_json::Value::Object(obj) if obj.len() == 1 && obj.contains_key("$blob") => {
+                let encoded = obj["$blob"].as_str().ok_or(DuckDBError {
+                    message: format!("Parameter {}: $blob must be a base64 string", i + 1),
+                    code: "PARAM_ERROR".to_string(),
+                })?;
+                base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encoded)
+                    .map(Value::Blob)
+                    .map_err(|e| DuckDBError {
+                        message: format!("Parameter {}: invalid base64 blob: {}", i + 1, e),
+                        code: "PARAM_ERROR".to_string(),
+                    })
+            }
+            serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
+                Ok(Value::Text(param.to_string()))
+            }
+        })
+        .collect()
+}
+
+/// Split a SQL script on top-level semicolons. Semicolons inside string
+/// literals, quoted identifiers, comments, and dollar-quoted blocks are kept,
+/// and statements consisting only of comments are dropped.
+fn split_sql_statements(sql: &str) -> Vec<String> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut has_content = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        if c == '\'' || c == '"' {
+            // Quoted text runs to the matching quote; a doubled quote is an escape
+            has_content = true;
+            current.push(c);
+            i += 1;
+            while i < chars.len() {
+                current.push(chars[i]);
+                if chars[i] == c {
+                    if chars.get(i + 1) == Some(&c) {
+                        current.push(c);
+                        i += 2;
+                        continue;
+                    }
+                    i += 1;
+                    break;
+                }
+                i += 1;
+            }
+            continue;
+        }
+
+        if c == '-' && next == Some('-') {
+            while i < chars.len() && chars[i] != '\n' {
+                current.push(chars[i]);
+                i += 1;
+            }
+            continue;
+        }
+
+        if c == '/' && next == Some('*') {
+            current.push_str("/*");
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                current.push(chars[i]);
+                i += 1;
+            }
+            if i < chars.len() {
+                current.push_str("*/");
+                i += 2;
+            }
+            continue;
+        }
+
+        if c == '$' {
+            // Dollar quoting: $$...$$ or $tag$...$tag$
+            let tag_end = chars[i + 1..]
+                .iter()
+                .position(|&ch| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                .map(|p| i + 1 + p);
+            let starts_with_digit = next.is_some_and(|ch| ch.is_ascii_digit());
+            if let Some(end) = tag_end.filter(|&end| chars[end] == '$' && !starts_with_digit) {
+                let tag: String = chars[i..=end].iter().collect();
+                let tag_chars: Vec<char> = tag.chars().collect();
+                has_content = true;
+                current.push_str(&tag);
+                i = end + 1;
+                while i < chars.len() && !chars[i..].starts_with(&tag_chars) {
+                    current.push(chars[i]);
+                    i += 1;
+                }
+                if i < chars.len() {
+                    current.push_str(&tag);
+                    i += tag_chars.len();
+                }
+                continue;
+            }
+        }
+
+        if c == ';' {
+            if has_content {
+                statements.push(current.trim().to_string());
+            }
+            current.clear();
+            has_content = false;
+            i += 1;
+            continue;
+        }
+
+        if !c.is_whitespace() {
+            has_content = true;
+        }
+        current.push(c);
+        i += 1;
+    }
+
+    if has_content {
+        statements.push(current.trim().to_string());
+    }
+    statements
+}
+
+/// The first keyword of a statement, upper-cased, skipping leading comments
+fn leading_keyword(sql: &str) -> String {
+    let mut rest = sql.trim_start();
+    loop {
+        if let Some(after) = rest.strip_prefix("--") {
+            rest = after.split_once('\n').map(|(_, tail)| tail).unwrap_or("").trim_start();
+        } else if let Some(after) = rest.strip_prefix("/*") {
+            rest = after.split_once("*/").map(|(_, tail)| tail).unwrap_or("").trim_start();
+        } else {
+            break;
+        }
+    }
+    rest.chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// Whether a statement produces a result set rather than a row count
+fn returns_rows(sql: &str) -> bool {
+    matches!(
+        leading_keyword(sql).as_str(),
+        "SELECT"
+            | "WITH"
+            | "VALUES"
+            | "FROM"
+            | "TABLE"
+            | "SHOW"
+            | "DESCRIBE"
+            | "SUMMARIZE"
+            | "EXPLAIN"
+            | "PRAGMA"
+            | "CALL"
+            | "PIVOT"
+            | "UNPIVOT"
+    )
+}
+
+/// Finish the open transaction with COMMIT or ROLLBACK
+async fn end_transaction(
+    state: &DuckDBState,
+    connection_id: &str,
+    statement: &'static str,
+) -> Result<DuckDBTransactionState, DuckDBError> {
+    let handle = get_connection(state, connection_id)?;
+    if !handle.in_transaction.load(Ordering::SeqCst) {
+        return Err(DuckDBError {
+            message: "No transaction is open on this connection".to_string(),
+            code: "NO_TRANSACTION".to_string(),
+        });
+    }
+
+    let in_transaction = Arc::clone(&handle.in_transaction);
+    with_connection(handle.conn, move |conn| {
+        let result = conn.execute_batch(statement);
+        // A failed COMMIT still ends the transaction in DuckDB
+        in_transaction.store(false, Ordering::SeqCst);
+        result.map_err(|e| DuckDBError {
+            message: format!("Failed to {}: {}", statement.to_lowercase(), e),
+            code: "TRANSACTION_ERROR".to_string(),
+        })?;
+        Ok(DuckDBTransactionState { in_transaction: false })
+    })
+    .await
+}
+
+/// Push pre-converted rows through an Appender and flush it
+fn append_values(conn: &Connection, table: &str, values: &[Vec<Value>]) -> Result<(), DuckDBError> {
+    let mut appender = conn.appender(table).map_err(|e| DuckDBError {
+        message: format!("Failed to create appender for {}: {}", table, e),
+        code: "APPEND_ERROR".to_string(),
+    })?;
+    for (row_index, row) in values.iter().enumerate() {
+        appender
+            .append_row(appender_params_from_iter(row.iter()))
+            .map_err(|e| DuckDBError {
+                message: format!("Row {}: {}", row_index, e),
+                code: "APPEND_ERROR".to_string(),
+            })?;
+    }
+    appender.flush().map_err(|e| DuckDBError {
+        message: format!("Failed to flush appended rows: {}", e),
+        code: "APPEND_ERROR".to_string(),
+    })
+}
+
+/// Convert a JSON cell into a value suitable for a column of the given DuckDB type.
+/// Values are checked against numeric and boolean columns; anything else is passed
+/// as text and cast by DuckDB.
+fn json_to_column_value(value: &serde_json::Value, data_type: &str) -> Result<Value, String> {
+    let data_type = data_type.to_uppercase();
+    let base_type = data_type.split('(').next().unwrap_or("").trim();
+    let mismatch = || format!("cannot convert {} to {}", value, data_type);
+
+    match (base_type, value) {
+        (_, serde_json::Value::Null) => Ok(Value::Null),
+        ("TINYINT" | "SMALLINT" | "INTEGER" | "BIGINT" | "UTINYINT" | "USMALLINT" | "UINTEGER", _) => {
+            match value {
+                serde_json::Value::Number(n) => n.as_i64().map(Value::BigInt).ok_or_else(mismatch),
+                serde_json::Value::String(s) => s.trim().parse().map(Value::BigInt).map_err(|_| mismatch()),
+                _ => Err(mismatch()),
+            }
+        }
+        ("UBIGINT", _) => match value {
+            serde_json::Value::Number(n) => n.as_u64().map(Value::UBigInt).ok_or_else(mismatch),
+            serde_json::Value::String(s) => s.trim().parse().map(Value::UBigInt).map_err(|_| mismatch()),
+            _ => Err(mismatch()),
+        },
+        ("HUGEINT" | "UHUGEINT", _) => match value {
+            serde_json::Value::Number(n) if n.is_i64() || n.is_u64() => Ok(Value::Text(n.to_string())),
+            serde_json::Value::String(s) if s.trim().parse::<i128>().is_ok() => Ok(Value::Text(s.trim().to_string())),
+            _ => Err(mismatch()),
+        },
+        ("FLOAT" | "DOUBLE" | "REAL", _) => match value {
+            serde_json::Value::Number(n) => n.as_f64().map(Value::Double).ok_or_else(mismatch),
+            serde_json::Value::String(s) => s.trim().parse().map(Value::Double).map_err(|_| mismatch()),
+            _ => Err(mismatch()),
+        },
+        // Decimals go through text so no precision is lost to f64
+        ("DECIMAL" | "NUMERIC", _) => match value {
+            serde_json::Value::Number(n) => Ok(Value::Text(n.to_string())),
+            serde_json::Value::String(s) if s.trim().parse::<f64>().is_ok() => Ok(Value::Text(s.trim().to_string())),
+            _ => Err(mismatch()),
+        },
+        ("BOOLEAN", _) => match value {
+            serde_json::Value::Bool(b) => Ok(Value::Boolean(*b)),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(0) => Ok(Value::Boolean(false)),
+                Some(1) => Ok(Value::Boolean(true)),
+                _ => Err(mismatch()),
+            },
+            serde_json::Value::String(s) => match s.trim().to_lowercase().as_str() {
+                "true" | "t" | "1" => Ok(Value::Boolean(true)),
+                "false" | "f" | "0" => Ok(Value::Boolean(false)),
+                _ => Err(mismatch()),
+            },
+            _ => Err(mismatch()),
+        },
+        _ => json_params_to_values(std::slice::from_ref(value))
+            .map(|mut values| values.remove(0))
+            .map_err(|e| e.message),
+    }
+}
+
+/// Convert a DuckDB ValueRef to a serde_json::Value
+fn convert_value_to_json(value: ValueRef) -> Result<serde_json::Value, DuckDBError> {
+    let json = match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Boolean(b) => serde_json::json!(b),
+        ValueRef::TinyInt(i) => serde_json::json!(i),
+        ValueRef::SmallInt(i) => serde_json::json!(i),
+        ValueRef::Int(i) => serde_json::json!(i),
+        ValueRef::BigInt(i) => serde_json::json!(i),
+        ValueRef::HugeInt(i) => integer_to_json(i),
+        ValueRef::UTinyInt(i) => serde_json::json!(i),
+        ValueRef::USmallInt(i) => serde_json::json!(i),
+        ValueRef::UInt(i) => serde_json::json!(i),
+        ValueRef::UBigInt(i) => serde_json::json!(i),
+        ValueRef::Float(f) => serde_json::json!(f),
+        ValueRef::Double(f) => serde_json::json!(f),
+        ValueRef::Decimal(d) => decimal_to_json(d.mantissa(), d.scale(), &d.to_string()),
+        ValueRef::Text(s) => serde_json::json!(String::from_utf8_lossy(s)),
+        ValueRef::Blob(b) => serde_json::json!(base64::Engine::encode(&base64::engine::general_purpose::STANDARD, b)),
+        ValueRef::Date32(d) => serde_json::json!(format_date(d as i64)),
+        ValueRef::Time64(unit, t) => serde_json::json!(format_time(unit, t)),
+        ValueRef::Timestamp(unit, t) => serde_json::json!(format_timestamp(unit, t)),
+        ValueRef::Interval { months, days, nanos } => interval_to_json(months, days, nanos),
+        ValueRef::Enum(enum_type, idx) => match resolve_enum_label(enum_type, idx)? {
+            Some(label) => serde_json::json!(label),
+            None => serde_json::Value::Null,
+        },
+        // Nested values are materialized so their children can be walked recursively
+        ValueRef::List(..)
+        | ValueRef::Struct(..)
+        | ValueRef::Map(..)
+        | ValueRef::Array(..)
+        | ValueRef::Union(..) => convert_owned_value_to_json(&value.to_owned()),
+    };
+    Ok(json)
+}
+
+/// Resolve an ENUM dictionary index to its string label
+fn resolve_enum_label(enum_type: EnumType, idx: usize) -> Result<Option<String>, DuckDBError> {
+    let (key, dictionary) = match enum_type {
+        EnumType::UInt8(array) => (array.key(idx), array.values()),
+        EnumType::UInt16(array) => (array.key(idx), array.values()),
+        EnumType::UInt32(array) => (array.key(idx), array.values()),
+    };
+
+    // A missing key means the enum value itself is NULL
+    let Some(key) = key else {
+        return Ok(None);
+    };
+
+    let labels = dictionary
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .ok_or(DuckDBError {
+            message: "ENUM dictionary does not contain string labels".to_string(),
+            code: "CONVERSION_ERROR".to_string(),
+        })?;
+
+    if key >= labels.len() {
+        return Err(DuckDBError {
+            message: format!(
+                "ENUM index {} is outside the dictionary ({} labels)",
+                key,
+                labels.len()
+            ),
+            code: "CONVERSION_ERROR".to_string(),
+        });
+    }
+
+    Ok(Some(labels.value(key).to_string()))
+}
+
+/// Canonical lowercase hyphenated text for a UUID column value. DuckDB stores
+/// UUIDs as a HUGEINT with the top bit flipped so they sort correctly, and
+/// depending on the Arrow export they arrive as that integer, as 16 raw bytes,
+/// or already as text.
+fn uuid_to_json(value: ValueRef) -> Result<serde_json::Value, DuckDBError> {
+    let bits = match value {
+        ValueRef::HugeInt(i) => (i as u128) ^ (1u128 << 127),
+        ValueRef::Decimal(d) if d.scale() == 0 => (d.mantissa() as u128) ^ (1u128 << 127),
+        ValueRef::Blob(b) if b.len() == 16 => u128::from_be_bytes(b.try_into().unwrap_or_default()),
+        ValueRef::Text(t) => return Ok(serde_json::json!(String::from_utf8_lossy(t).to_lowercase())),
+        other => return convert_value_to_json(other),
+    };
+    Ok(serde_json::json!(Uuid::from_u128(bits).to_string()))
+}
+
+/// A JSON number when the integer fits in i64, otherwise its decimal string
+fn integer_to_json(i: i128) -> serde_json::Value {
+    i64::try_from(i)
+        .map(|i| serde_json::json!(i))
+        .unwrap_or_else(|_| serde_json::json!(i.to_string()))
+}
+
+/// A JSON number when the decimal has few enough digits to survive an f64
+/// round trip, otherwise its exact text
+fn decimal_to_json(mantissa: i128, scale: u32, text: &str) -> serde_json::Value {
+    if scale == 0 {
+        return integer_to_json(mantissa);
+    }
+    if mantissa.unsigned_abs() < MAX_EXACT_DECIMAL_MANTISSA {
+        if let Ok(f) = text.parse::<f64>() {
+            return serde_json::json!(f);
+        }
+    }
+    serde_json::json!(text)
+}
+
+/// Hint telling the frontend how a column's values are represented: "decimal"
+/// and "hugeint" when JSON numbers can't hold them exactly, "uuid" for UUIDs
+fn column_hint(data_type: &DataType, type_name: Option<&str>) -> Option<String> {
+    if type_name == Some("UUID") {
+        return Some("uuid".to_string());
+    }
+    match data_type {
+        DataType::Decimal128(precision, 0) if *precision > 18 => Some("hugeint".to_string()),
+        DataType::Decimal128(precision, _) if u32::from(*precision) > MAX_EXACT_DECIMAL_DIGITS => {
+            Some("decimal".to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Convert an owned DuckDB Value (as produced for nested types) to a serde_json::Value
+fn convert_owned_value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Boolean(b) => serde_json::json!(b),
+        Value::TinyInt(i) => serde_json::json!(i),
+        Value::SmallInt(i) => serde_json::json!(i),
+        Value::Int(i) => serde_json::json!(i),
+        Value::BigInt(i) => serde_json::json!(i),
+        Value::HugeInt(i) => integer_to_json(*i),
+        Value::UTinyInt(i) => serde_json::json!(i),
+        Value::USmallInt(i) => serde_json::json!(i),
+        Value::UInt(i) => serde_json::json!(i),
+        Value::UBigInt(i) => serde_json::json!(i),
+        Value::Float(f) => serde_json::json!(f),
+        Value::Double(f) => serde_json::json!(f),
+        Value::Decimal(d) => decimal_to_json(d.mantissa(), d.scale(), &d.to_string()),
+        Value::Text(s) => serde_json::json!(s),
+        Value::Blob(b) => serde_json::json!(base64::Engine::encode(&base64::engine::general_purpose::STANDARD, b)),
+        Value::Date32(d) => serde_json::json!(format_date(*d as i64)),
+        Value::Time64(unit, t) => serde_json::json!(format_time(*unit, *t)),
+        Value::Timestamp(unit, t) => serde_json::json!(format_timestamp(*unit, *t)),
+        Value::Interval { months, days, nanos } => interval_to_json(*months, *days, *nanos),
+        Value::Enum(label) => serde_json::json!(label),
+        Value::List(items) | Value::Array(items) => {
+            serde_json::Value::Array(items.iter().map(convert_owned_value_to_json).collect())
+        }
+        Value::Struct(fields) => {
+            let mut obj = serde_json::Map::new();
+            for (name, field) in fields.iter() {
+                obj.insert(name.clone(), convert_owned_value_to_json(field));
+            }
+            serde_json::Value::Object(obj)
+        }
+        Value::Map(entries) => {
+            // JSON object keys must be strings, so non-text keys are stringified
+            let mut obj = serde_json::Map::new();
+            for (key, entry) in entries.iter() {
+                let key = match convert_owned_value_to_json(key) {
+                    serde_json::Value::String(s) => s,
+                    other => other.to_string(),
+                };
+                obj.insert(key, convert_owned_value_to_json(entry));
+            }
+            serde_json::Value::Object(obj)
+        }
+        Value::Union(inner) => convert_owned_value_to_json(inner),
+    }
+}
+
+/// Split a value stored in the given unit into whole seconds and the
+/// nanosecond remainder, rounding towards negative infinity so that
+/// pre-1970 values keep a positive sub-second part.
+fn split_time_unit(unit: TimeUnit, value: i64) -> (i64, u32) {
+    let per_second: i64 = match unit {
+        TimeUnit::Second => 1,
+        TimeUnit::Millisecond => 1_000,
+        TimeUnit::Microsecond => 1_000_000,
+        TimeUnit::Nanosecond => 1_000_000_000,
+    };
+    let secs = value.div_euclid(per_second);
+    let nanos = value.rem_euclid(per_second) * (1_000_000_000 / per_second);
+    (secs, nanos as u32)
+}
+
+/// Convert days since 1970-01-01 into a (year, month, day) civil date.
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Format a fractional second, using millisecond precision unless the value
+/// carries finer detail
+fn format_fraction(nanos: u32) -> String {
+    if nanos % 1_000_000 == 0 {
+        format!("{:03}", nanos / 1_000_000)
+    } else if nanos % 1_000 == 0 {
+        format!("{:06}", nanos / 1_000)
+    } else {
+        format!("{:09}", nanos)
+    }
+}
+
+fn format_clock(secs_of_day: i64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs_of_day / 3_600,
+        (secs_of_day % 3_600) / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Format days since the epoch as an ISO-8601 date (`2024-06-03`)
+fn format_date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Format a time of day as `14:00:00`, appending fractional seconds when present
+fn format_time(unit: TimeUnit, value: i64) -> String {
+    let (secs, nanos) = split_time_unit(unit, value);
+    let clock = format_clock(secs.rem_euclid(86_400));
+    if nanos == 0 {
+        clock
+    } else {
+        format!("{}.{}", clock, format_fraction(nanos))
+    }
+}
+
+/// Format a UTC timestamp as ISO-8601 (`2024-06-03T14:00:00.000Z`)
+fn format_timestamp(unit: TimeUnit, value: i64) -> String {
+    let (secs, nanos) = split_time_unit(unit, value);
+    format!(
+        "{}T{}.{}Z",
+        format_date(secs.div_euclid(86_400)),
+        format_clock(secs.rem_euclid(86_400)),
+        format_fraction(nanos)
+    )
+}
+
+/// Intervals keep their three components (DuckDB's own precision is
+/// microseconds) so the UI can do arithmetic, plus a display string
+fn interval_to_json(months: i32, days: i32, nanos: i64) -> serde_json::Value {
+    serde_json::json!({
+        "months": months,
+        "days": days,
+        "micros": nanos / 1_000,
+        "text": format_interval(months, days, nanos),
+    })
+}
+
+/// Format an interval the way PostgreSQL does (`1 year 2 mons 3 days 04:05:06`)
+fn format_interval(months: i32, days: i32, nanos: i64) -> String {
+    fn unit(value: i64, singular: &str, plural: &str) -> String {
+        format!("{} {}", value, if value.abs() == 1 { singular } else { plural })
+    }
+
+    let mut parts = Vec::new();
+    let years = (months / 12) as i64;
+    let months = (months % 12) as i64;
+    if years != 0 {
+        parts.push(unit(years, "year", "years"));
+    }
+    if months != 0 {
+        parts.push(unit(months, "mon", "mons"));
+    }
+    if days != 0 {
+        parts.push(unit(days as i64, "day", "days"));
+    }
+    if nanos != 0 || parts.is_empty() {
+        let sign = if nanos < 0 { "-" } else { "" };
+        let abs = nanos.unsigned_abs();
+        let secs = (abs / 1_000_000_000) as i64;
+        let frac = (abs % 1_000_000_000) as u32;
+        // Hours are not wrapped at 24 since intervals keep days separately
+        let mut clock = format!(
+            "{}{:02}:{:02}:{:02}",
+            sign,
+            secs / 3_600,
+            (secs % 3_600) / 60,
+            secs % 60
+        );
+        if frac != 0 {
+            clock.push('.');
+            clock.push_str(format_fraction(frac).trim_end_matches('0'));
+        }
+        parts.push(clock);
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(conn: &Connection, sql: &str) -> DuckDBQueryResult {
+        run_query(conn, sql, &[], 0, 0).unwrap()
+    }
+
+    /// Columns and constraints of a table as DuckDB reports them
+    fn table_shape(conn: &Connection, table: &str) -> (Vec<Vec<serde_json::Value>>, Vec<Vec<serde_json::Value>>) {
+        let table = quote_literal(table);
+        let columns = query(
+            conn,
+            &format!(
+                "SELECT column_name, data_type, column_default, is_nullable FROM duckdb_columns() \
+                 WHERE table_name = {} ORDER BY column_index",
+                table
+            ),
+        );
+        let constraints = query(
+            conn,
+            &format!(
+                "SELECT constraint_type, constraint_text FROM duckdb_constraints() \
+                 WHERE table_name = {} ORDER BY constraint_type, constraint_text",
+                table
+            ),
+        );
+        (columns.rows, constraints.rows)
+    }
+
+    #[test]
+    fn compression_extension_is_added_once() {
+        let path = |p: &str, compression: Option<&str>| with_compression_extension(p.to_string(), compression);
+        assert_eq!(path("out.csv", Some("gzip")), "out.csv.gz");
+        assert_eq!(path("out.csv.gz", Some("gzip")), "out.csv.gz");
+        assert_eq!(path("OUT.CSV.GZ", Some("gzip")), "OUT.CSV.GZ");
+        assert_eq!(path("out.csv", Some("ZSTD")), "out.csv.zst");
+        assert_eq!(path("out.csv.zst", Some("zstd")), "out.csv.zst");
+        assert_eq!(path("out.csv", Some("none")), "out.csv");
+        assert_eq!(path("out.csv", None), "out.csv");
+    }
+
+    #[test]
+    fn integers_are_numbers_only_when_they_fit_in_i64() {
+        assert_eq!(integer_to_json(i64::MAX as i128), serde_json::json!(i64::MAX));
+        assert_eq!(integer_to_json(i64::MIN as i128), serde_json::json!(i64::MIN));
+        assert_eq!(integer_to_json(i64::MAX as i128 + 1), serde_json::json!("9223372036854775808"));
+        assert_eq!(
+            integer_to_json(i128::MAX),
+            serde_json::json!("170141183460469231731687303715884105727")
+        );
+    }
+
+    #[test]
+    fn decimals_are_numbers_only_when_exact() {
+        assert_eq!(decimal_to_json(12_345, 2, "123.45"), serde_json::json!(123.45));
+        assert_eq!(decimal_to_json(-5, 1, "-0.5"), serde_json::json!(-0.5));
+        assert_eq!(
+            decimal_to_json(1_234_567_890_123_456_789, 10, "123456789.0123456789"),
+            serde_json::json!("123456789.0123456789")
+        );
+        // Scale 0 is an integer, whatever its width
+        assert_eq!(decimal_to_json(42, 0, "42"), serde_json::json!(42));
+    }
+
+    #[test]
+    fn wide_integers_and_decimals_come_back_exact() {
+        let conn = Connection::open_in_memory().unwrap();
+        let result = query(
+            &conn,
+            "SELECT 170141183460469231731687303715884105727::HUGEINT AS big, \
+                    42::HUGEINT AS small, \
+                    '123456789012345678.1234567890'::DECIMAL(38, 10) AS wide, \
+                    '0.5'::DECIMAL(38, 10) AS wide_short, \
+                    '12.34'::DECIMAL(10, 2) AS narrow",
+        );
+
+        assert_eq!(
+            result.rows[0],
+            vec![
+                serde_json::json!("170141183460469231731687303715884105727"),
+                serde_json::json!(42),
+                serde_json::json!("123456789012345678.1234567890"),
+                serde_json::json!("0.5000000000"),
+                serde_json::json!(12.34),
+            ]
+        );
+        assert_eq!(
+            result.column_hints,
+            vec![
+                Some("hugeint".to_string()),
+                Some("hugeint".to_string()),
+                Some("decimal".to_string()),
+                Some("decimal".to_string()),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn query_reports_its_execution_time() {
+        let conn = Connection::open_in_memory().unwrap();
+        let started = Instant::now();
+        let result = query(&conn, "SELECT sum(length(range::VARCHAR)) FROM range(2000000)");
+        let elapsed = started.elapsed();
+
+        assert_eq!(result.row_count, 1);
+        assert!(result.execution_ms > 0);
+        assert!(u128::from(result.execution_ms) <= elapsed.as_millis());
+    }
+
+    #[test]
+    fn column_types_come_from_the_result() {
+        let conn = Connection::open_in_memory().unwrap();
+        let result = query(
+            &conn,
+            "SELECT 1::INTEGER, 'a', [1, 2], {'a': 1, 'b': 'x'}, 1.5::DECIMAL(18, 2), \
+                    TIMESTAMPTZ '2024-06-03 14:00:00+00', MAP {'k': 1.5}",
+        );
+
+        assert_eq!(
+            result.column_types,
+            [
+                "INTEGER",
+                "VARCHAR",
+                "INTEGER[]",
+                "STRUCT(a INTEGER, b VARCHAR)",
+                "DECIMAL(18,2)",
+                "TIMESTAMP WITH TIME ZONE",
+                "MAP(VARCHAR, DECIMAL(2,1))",
+            ]
+        );
+    }
+
+    #[test]
+    fn query_counts_rows_past_the_limit() {
+        let conn = Connection::open_in_memory().unwrap();
+        let result = run_query(&conn, "SELECT * FROM range(250)", &[], 100, 0).unwrap();
+
+        assert_eq!(result.row_count, 100);
+        assert_eq!(result.rows.len(), 100);
+        assert!(result.truncated);
+        assert_eq!(result.total_rows_estimate, Some(250));
+    }
+
+    #[test]
+    fn explain_analyze_runs_the_statement_once() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (id INTEGER)").unwrap();
+
+        let result = explain_plan(&conn, "INSERT INTO t VALUES (1)", true).unwrap();
+        assert!(result.text.is_some() || !result.plan.is_empty());
+        let count: i64 = conn.query_row("SELECT count(*) FROM t", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn table_ddl_recreates_the_table() {
+        let original = Connection::open_in_memory().unwrap();
+        original
+            .execute_batch(
+                "CREATE TABLE orders ( \
+                     id INTEGER PRIMARY KEY, \
+                     code VARCHAR NOT NULL UNIQUE, \
+                     quantity INTEGER DEFAULT 1 CHECK (quantity > 0), \
+                     price DECIMAL(10, 2), \
+                     created TIMESTAMP DEFAULT current_timestamp, \
+                     tags VARCHAR[], \
+                     \"Odd \"\"Name\" STRUCT(a INTEGER, b VARCHAR), \
+                     UNIQUE (code, quantity) \
+                 )",
+            )
+            .unwrap();
+        let ddl = build_table_ddl(&original, "main", "orders").unwrap();
+
+        let fresh = Connection::open_in_memory().unwrap();
+        fresh.execute_batch(&ddl).unwrap();
+
+        let (columns, constraints) = table_shape(&fresh, "orders");
+        assert_eq!((columns.clone(), constraints.clone()), table_shape(&original, "orders"));
+        assert_eq!(columns.len(), 7);
+        let types: Vec<&str> = constraints
+            .iter()
+            .filter_map(|row| row[0].as_str())
+            .filter(|kind| *kind != "NOT NULL")
+            .collect();
+        assert_eq!(types, ["CHECK", "PRIMARY KEY", "UNIQUE", "UNIQUE"]);
+        assert_eq!(build_table_ddl(&fresh, "main", "orders").unwrap(), ddl);
+    }
+
+    #[test]
+    fn timestamps_before_1970_count_backwards() {
+        assert_eq!(format_timestamp(TimeUnit::Microsecond, -1), "1969-12-31T23:59:59.999999Z");
+        assert_eq!(format_timestamp(TimeUnit::Millisecond, -1), "1969-12-31T23:59:59.999Z");
+        assert_eq!(format_timestamp(TimeUnit::Second, -1_234_567_890), "1930-11-18T00:28:30.000Z");
+        assert_eq!(format_timestamp(TimeUnit::Second, -25_567 * 86_400), "1900-01-01T00:00:00.000Z");
+        assert_eq!(format_date(-1), "1969-12-31");
+    }
+
+    #[test]
+    fn timestamps_keep_sub_second_precision() {
+        assert_eq!(
+            format_timestamp(TimeUnit::Microsecond, 1_717_432_800_123_456),
+            "2024-06-03T16:40:00.123456Z"
+        );
+        assert_eq!(
+            format_timestamp(TimeUnit::Nanosecond, 1_717_432_800_000_000_001),
+            "2024-06-03T16:40:00.000000001Z"
+        );
+        assert_eq!(format_timestamp(TimeUnit::Microsecond, 1_717_432_800_000_000), "2024-06-03T16:40:00.000Z");
+        assert_eq!(format_time(TimeUnit::Microsecond, 50_400_000_250), "14:00:00.000250");
+    }
+
+    #[test]
+    fn timestamps_convert_from_queries() {
+        let conn = Connection::open_in_memory().unwrap();
+        let result = query(
+            &conn,
+            "SELECT TIMESTAMP '1969-07-20 20:17:40.123456', DATE '1900-01-01', TIME '14:00:00'",
+        );
+
+        assert_eq!(
+            result.rows[0],
+            vec![
+                serde_json::json!("1969-07-20T20:17:40.123456Z"),
+                serde_json::json!("1900-01-01"),
+                serde_json::json!("14:00:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn intervals_keep_each_component_sign() {
+        let clock = (4 * 3_600 + 5 * 60 + 6) * 1_000_000_000;
+        assert_eq!(format_interval(-14, -3, -clock), "-1 year -2 mons -3 days -04:05:06");
+        assert_eq!(format_interval(1, -2, 3_600_500_000_000), "1 mon -2 days 01:00:00.5");
+        assert_eq!(format_interval(0, 1, -1_500_000), "1 day -00:00:00.0015");
+        assert_eq!(format_interval(0, 0, 0), "00:00:00");
+        assert_eq!(
+            interval_to_json(-25, 0, -90_000_000_000),
+            serde_json::json!({
+                "months": -25,
+                "days": 0,
+                "micros": -90_000_000,
+                "text": "-2 years -1 mon -00:01:30",
+            })
+        );
+    }
+
+    #[test]
+    fn nested_values_convert_recursively() {
+        let conn = Connection::open_in_memory().unwrap();
+        let result = query(
+            &conn,
+            "SELECT INTERVAL 1 MONTH - INTERVAL 2 DAY AS mixed, \
+                    []::INTEGER[] AS empty, \
+                    [0.5, 1.5, -2]::FLOAT[3] AS embedding, \
+                    [{'id': 1, 'tags': ['a']}, {'id': 2, 'tags': []}] AS list_of_structs, \
+                    [{'id': 3}, {'id': NULL}]::STRUCT(id INTEGER)[2] AS array_of_structs",
+        );
+
+        assert_eq!(
+            result.rows[0],
+            vec![
+                serde_json::json!({ "months": 1, "days": -2, "micros": 0, "text": "1 mon -2 days" }),
+                serde_json::json!([]),
+                serde_json::json!([0.5, 1.5, -2.0]),
+                serde_json::json!([{ "id": 1, "tags": ["a"] }, { "id": 2, "tags": [] }]),
+                serde_json::json!([{ "id": 3 }, { "id": null }]),
+            ]
+        );
     }
 }
@@ -1,6 +1,6 @@
 use git2::{
-    build::RepoBuilder, Cred, CredentialType, FetchOptions, PushOptions,
-    RemoteCallbacks, Repository, Signature, StatusOptions,
+    build::{CheckoutBuilder, RepoBuilder}, BranchType, Cred, CredentialType, DiffOptions, FetchOptions,
+    PushOptions, RemoteCallbacks, Repository, Signature, Sort, StatusOptions,
 };
 use serde::{Deserialize, Serialize};
 use std::path::Path;
@@ -39,6 +39,36 @@ pub struct SyncResult {
     pub files_changed: Vec<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BranchInfo {
+    /// Short name, e.g. `main` or `origin/main`
+    pub name: String,
+    pub is_remote: bool,
+    /// Whether this is the checked out branch
+    pub is_head: bool,
+    /// The remote branch a local branch tracks, e.g. `origin/main`
+    pub upstream: Option<String>,
+    pub last_commit_id: Option<String>,
+    pub last_commit_summary: Option<String>,
+    /// Seconds since the Unix epoch
+    pub last_commit_time: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommitInfo {
+    pub id: String,
+    pub short_id: String,
+    pub author_name: String,
+    pub author_email: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: i64,
+    pub summary: String,
+    /// Has more than one parent
+    pub is_merge: bool,
+    /// Paths changed since the first parent
+    pub files: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GitCredentials {
     pub username: Option<String>,
@@ -816,6 +846,372 @@ pub fn git_get_remote_url(path: String) -> Result<Option<String>, GitError> {
     Ok(result)
 }
 
+#[tauri::command]
+pub fn git_list_branches(path: String) -> Result<Vec<BranchInfo>, GitError> {
+    let repo = Repository::open(Path::new(&path))
+        .map_err(|e| GitError {
+            message: format!("Failed to open repository: {}", e),
+            code: "REPO_OPEN_ERROR".to_string(),
+        })?;
+
+    let branches = repo.branches(None).map_err(|e| GitError {
+        message: format!("Failed to list branches: {}", e),
+        code: "BRANCH_ERROR".to_string(),
+    })?;
+
+    let mut result = Vec::new();
+    for branch in branches {
+        let (branch, branch_type) = branch.map_err(|e| GitError {
+            message: format!("Failed to read branch: {}", e),
+            code: "BRANCH_ERROR".to_string(),
+        })?;
+        let name = match branch.name() {
+            Ok(Some(name)) => name.to_string(),
+            _ => continue,
+        };
+        // origin/HEAD only points at another remote branch
+        if branch_type == BranchType::Remote && name.ends_with("/HEAD") {
+            continue;
+        }
+
+        let upstream = branch
+            .upstream()
+            .ok()
+            .and_then(|upstream| upstream.name().ok().flatten().map(|name| name.to_string()));
+        let commit = branch.get().peel_to_commit().ok();
+
+        result.push(BranchInfo {
+            name,
+            is_remote: branch_type == BranchType::Remote,
+            is_head: branch.is_head(),
+            upstream,
+            last_commit_id: commit.as_ref().map(|commit| commit.id().to_string()),
+            last_commit_summary: commit
+                .as_ref()
+                .and_then(|commit| commit.summary().map(|summary| summary.to_string())),
+            last_commit_time: commit.as_ref().map(|commit| commit.time().seconds()),
+        });
+    }
+
+    // Local branches first
+    result.sort_by(|a, b| a.is_remote.cmp(&b.is_remote).then_with(|| a.name.cmp(&b.name)));
+    Ok(result)
+}
+
+/// Create a local branch at `from_ref` (a branch, tag or commit; HEAD when
+/// not given) without checking it out. Branching from a remote branch
+/// tracks it.
+#[tauri::command]
+pub fn git_create_branch(path: String, name: String, from_ref: Option<String>) -> Result<(), GitError> {
+    let repo = Repository::open(Path::new(&path))
+        .map_err(|e| GitError {
+            message: format!("Failed to open repository: {}", e),
+            code: "REPO_OPEN_ERROR".to_string(),
+        })?;
+
+    if !git2::Branch::name_is_valid(&name).unwrap_or(false) {
+        return Err(GitError {
+            message: format!("Invalid branch name: {}", name),
+            code: "INVALID_BRANCH_NAME".to_string(),
+        });
+    }
+
+    let from_ref = from_ref.unwrap_or_else(|| "HEAD".to_string());
+    let commit = repo
+        .revparse_single(&from_ref)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|e| GitError {
+            message: format!("Failed to find {}: {}", from_ref, e),
+            code: "REF_NOT_FOUND".to_string(),
+        })?;
+
+    let mut branch = repo.branch(&name, &commit, false).map_err(|e| GitError {
+        message: format!("Failed to create branch {}: {}", name, e),
+        code: if e.code() == git2::ErrorCode::Exists {
+            "BRANCH_EXISTS".to_string()
+        } else {
+            "BRANCH_ERROR".to_string()
+        },
+    })?;
+
+    if repo.find_branch(&from_ref, BranchType::Remote).is_ok() {
+        branch.set_upstream(Some(&from_ref)).map_err(|e| GitError {
+            message: format!("Failed to track {}: {}", from_ref, e),
+            code: "BRANCH_ERROR".to_string(),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Check out a local branch, or create one tracking `origin/<name>` if only
+/// that exists. Refuses when tracked files have uncommitted changes, unless
+/// `force`, which discards them.
+#[tauri::command]
+pub fn git_checkout_branch(path: String, name: String, force: Option<bool>) -> Result<(), GitError> {
+    let repo = Repository::open(Path::new(&path))
+        .map_err(|e| GitError {
+            message: format!("Failed to open repository: {}", e),
+            code: "REPO_OPEN_ERROR".to_string(),
+        })?;
+    let force = force.unwrap_or(false);
+
+    if !force {
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(false);
+        opts.include_ignored(false);
+        let statuses = repo
+            .statuses(Some(&mut opts))
+            .map_err(|e| GitError {
+                message: format!("Failed to get status: {}", e),
+                code: "REPO_ERROR".to_string(),
+            })?;
+        if !statuses.is_empty() {
+            return Err(GitError {
+                message: format!(
+                    "{} file(s) have uncommitted changes; commit or discard them before switching branches",
+                    statuses.len()
+                ),
+                code: "WORKTREE_DIRTY".to_string(),
+            });
+        }
+    }
+
+    let branch = match repo.find_branch(&name, BranchType::Local) {
+        Ok(branch) => branch,
+        Err(_) => {
+            let remote_name = format!("origin/{}", name);
+            let remote_branch = repo.find_branch(&remote_name, BranchType::Remote).map_err(|_| GitError {
+                message: format!("Branch not found: {}", name),
+                code: "BRANCH_NOT_FOUND".to_string(),
+            })?;
+            let commit = remote_branch.get().peel_to_commit().map_err(|e| GitError {
+                message: format!("Failed to read {}: {}", remote_name, e),
+                code: "BRANCH_ERROR".to_string(),
+            })?;
+            let mut branch = repo.branch(&name, &commit, false).map_err(|e| GitError {
+                message: format!("Failed to create branch {}: {}", name, e),
+                code: "BRANCH_ERROR".to_string(),
+            })?;
+            branch.set_upstream(Some(&remote_name)).map_err(|e| GitError {
+                message: format!("Failed to track {}: {}", remote_name, e),
+                code: "BRANCH_ERROR".to_string(),
+            })?;
+            branch
+        }
+    };
+
+    let refname = branch
+        .get()
+        .name()
+        .ok_or(GitError {
+            message: "Branch name is not valid UTF-8".to_string(),
+            code: "BRANCH_ERROR".to_string(),
+        })?
+        .to_string();
+    let target = branch.get().peel_to_commit().map_err(|e| GitError {
+        message: format!("Failed to read branch {}: {}", name, e),
+        code: "BRANCH_ERROR".to_string(),
+    })?;
+
+    let mut checkout = CheckoutBuilder::new();
+    if force {
+        checkout.force();
+    } else {
+        checkout.safe();
+    }
+    repo.checkout_tree(target.as_object(), Some(&mut checkout))
+        .map_err(|e| GitError {
+            message: format!("Failed to check out {}: {}", name, e),
+            code: "CHECKOUT_ERROR".to_string(),
+        })?;
+    repo.set_head(&refname).map_err(|e| GitError {
+        message: format!("Failed to switch to {}: {}", name, e),
+        code: "CHECKOUT_ERROR".to_string(),
+    })?;
+
+    Ok(())
+}
+
+/// Delete a local branch. Unless `force`, only branches whose commits are
+/// all in HEAD or their upstream can go, so no work is lost.
+#[tauri::command]
+pub fn git_delete_branch(path: String, name: String, force: bool) -> Result<(), GitError> {
+    let repo = Repository::open(Path::new(&path))
+        .map_err(|e| GitError {
+            message: format!("Failed to open repository: {}", e),
+            code: "REPO_OPEN_ERROR".to_string(),
+        })?;
+
+    let mut branch = repo.find_branch(&name, BranchType::Local).map_err(|_| GitError {
+        message: format!("Branch not found: {}", name),
+        code: "BRANCH_NOT_FOUND".to_string(),
+    })?;
+
+    if branch.is_head() {
+        return Err(GitError {
+            message: format!("{} is checked out; switch to another branch first", name),
+            code: "BRANCH_CHECKED_OUT".to_string(),
+        });
+    }
+
+    if !force {
+        let tip = branch.get().peel_to_commit().map(|commit| commit.id()).ok();
+        let merged_into = |other: Option<git2::Oid>| match (tip, other) {
+            (Some(tip), Some(other)) => other == tip || repo.graph_descendant_of(other, tip).unwrap_or(false),
+            _ => false,
+        };
+        let head = repo.head().ok().and_then(|head| head.target());
+        let upstream = branch.upstream().ok().and_then(|upstream| upstream.get().target());
+        if !merged_into(head) && !merged_into(upstream) {
+            return Err(GitError {
+                message: format!("{} has commits that aren't merged anywhere; delete it with force to lose them", name),
+                code: "BRANCH_NOT_MERGED".to_string(),
+            });
+        }
+    }
+
+    branch.delete().map_err(|e| GitError {
+        message: format!("Failed to delete branch {}: {}", name, e),
+        code: "BRANCH_ERROR".to_string(),
+    })?;
+
+    Ok(())
+}
+
+/// Commits reachable from HEAD, newest first, paged with `limit` (50 by
+/// default) and `skip`. With `file_path`, only commits that changed that
+/// file count.
+#[tauri::command]
+pub fn git_get_log(
+    path: String,
+    limit: Option<usize>,
+    skip: Option<usize>,
+    file_path: Option<String>,
+) -> Result<Vec<CommitInfo>, GitError> {
+    let repo = Repository::open(Path::new(&path))
+        .map_err(|e| GitError {
+            message: format!("Failed to open repository: {}", e),
+            code: "REPO_OPEN_ERROR".to_string(),
+        })?;
+
+    let mut revwalk = repo.revwalk().map_err(|e| GitError {
+        message: format!("Failed to walk history: {}", e),
+        code: "LOG_ERROR".to_string(),
+    })?;
+    match revwalk.push_head() {
+        Ok(()) => {}
+        // No commits yet
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch || e.code() == git2::ErrorCode::NotFound => {
+            return Ok(vec![]);
+        }
+        Err(e) => {
+            return Err(GitError {
+                message: format!("Failed to walk history: {}", e),
+                code: "LOG_ERROR".to_string(),
+            });
+        }
+    }
+    revwalk.set_sorting(Sort::TIME).map_err(|e| GitError {
+        message: format!("Failed to walk history: {}", e),
+        code: "LOG_ERROR".to_string(),
+    })?;
+
+    let limit = limit.unwrap_or(50);
+    let mut skip = skip.unwrap_or(0);
+    let mut commits = Vec::new();
+
+    for oid in revwalk {
+        if commits.len() >= limit {
+            break;
+        }
+        let commit = oid
+            .and_then(|oid| repo.find_commit(oid))
+            .map_err(|e| GitError {
+                message: format!("Failed to read commit: {}", e),
+                code: "LOG_ERROR".to_string(),
+            })?;
+
+        // Without a file filter, skipped commits don't need diffing
+        if file_path.is_none() && skip > 0 {
+            skip -= 1;
+            continue;
+        }
+
+        let Some(files) = commit_files(&repo, &commit, file_path.as_deref())? else {
+            continue;
+        };
+        if file_path.is_some() && skip > 0 {
+            skip -= 1;
+            continue;
+        }
+
+        let id = commit.id().to_string();
+        let author = commit.author();
+        commits.push(CommitInfo {
+            short_id: id.chars().take(7).collect(),
+            id,
+            author_name: author.name().unwrap_or_default().to_string(),
+            author_email: author.email().unwrap_or_default().to_string(),
+            timestamp: commit.time().seconds(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+            is_merge: commit.parent_count() > 1,
+            files,
+        });
+    }
+
+    Ok(commits)
+}
+
+/// Every path a commit changed relative to its first parent, or None when
+/// `file_path` is given and the commit didn't touch it
+fn commit_files(
+    repo: &Repository,
+    commit: &git2::Commit,
+    file_path: Option<&str>,
+) -> Result<Option<Vec<String>>, GitError> {
+    let diff_error = |e: git2::Error| GitError {
+        message: format!("Failed to diff commit {}: {}", commit.id(), e),
+        code: "LOG_ERROR".to_string(),
+    };
+
+    let tree = commit.tree().map_err(diff_error)?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree().map_err(diff_error)?),
+        // The first commit is diffed against nothing
+        Err(_) => None,
+    };
+
+    // The file filter only picks commits; the full diff is listed either way
+    if let Some(file_path) = file_path {
+        let mut opts = DiffOptions::new();
+        opts.pathspec(file_path);
+        opts.disable_pathspec_match(true);
+        let touched = repo
+            .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut opts))
+            .map_err(diff_error)?;
+        if touched.deltas().next().is_none() {
+            return Ok(None);
+        }
+    }
+
+    let diff = repo
+        .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)
+        .map_err(diff_error)?;
+
+    Ok(Some(
+        diff.deltas()
+            .filter_map(|delta| {
+                delta
+                    .new_file()
+                    .path()
+                    .or_else(|| delta.old_file().path())
+                    .map(|path| path.to_string_lossy().to_string())
+            })
+            .collect(),
+    ))
+}
+
 fn get_signature(repo: &Repository) -> Result<Signature<'_>, GitError> {
     // Try to get signature from repo config
     if let Ok(sig) = repo.signature() {
@@ -855,10 +1251,13 @@ fn calculate_ahead_behind(repo: &Repository, branch: &str) -> Option<(usize, usi
                             .ok()
                     }
                     Err(_) => {
-                        // No remote branch - count all local commits as ahead
+                        // No remote branch - count the local commits that
+                        // aren't on any remote branch, so a new branch made
+                        // from a pushed one starts out level
                         let mut count = 0usize;
                         let mut revwalk = repo.revwalk().ok()?;
                         revwalk.push(local_commit.id()).ok()?;
+                        revwalk.hide_glob("refs/remotes/origin/*").ok()?;
                         for _ in revwalk {
                             count += 1;
                         }
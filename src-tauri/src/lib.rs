@@ -9,6 +9,8 @@ use tauri_plugin_updater::UpdaterExt;
 mod duckdb_commands;
 mod git;
 mod mssql;
+mod ppk;
+mod ssh_config;
 mod ssh_tunnel;
 
 use duckdb_commands::DuckDBState;
@@ -157,6 +159,10 @@ async fn install_update(
                 message: format!("Failed to install update: {}", e),
                 code: "UPDATE_ERROR".to_string(),
             })?;
+            // restart() skips the exit events, so close tunnels here
+            app.state::<TunnelManager>()
+                .close_all(std::time::Duration::from_secs(2))
+                .await;
             app.restart();
         }
     }
@@ -282,15 +288,71 @@ pub fn run() {
             ssh_tunnel::close_ssh_tunnel,
             ssh_tunnel::check_tunnel_status,
             ssh_tunnel::list_active_tunnels,
+            ssh_tunnel::get_tunnel_stats,
+            ssh_tunnel::ssh_tunnel_answer_prompts,
+            ssh_tunnel::ssh_tunnel_provide_passphrase,
+            ssh_tunnel::ssh_test_connection,
+            ssh_config::ssh_resolve_host_config,
             mssql::mssql_connect,
+            mssql::mssql_connect_with_string,
+            mssql::mssql_test_connection,
             mssql::mssql_disconnect,
+            mssql::mssql_list_connections,
             mssql::mssql_query,
+            mssql::mssql_query_multi,
+            mssql::mssql_get_query_plan,
+            mssql::mssql_exec_procedure,
+            mssql::mssql_get_procedure_params,
+            mssql::mssql_get_table_ddl,
+            mssql::mssql_get_object_definition,
+            mssql::mssql_query_open,
+            mssql::mssql_query_fetch,
+            mssql::mssql_query_close,
+            mssql::mssql_get_cell_binary,
+            mssql::mssql_save_cell_binary,
+            mssql::mssql_get_schema,
+            mssql::mssql_list_databases,
+            mssql::mssql_use_database,
+            mssql::mssql_bulk_insert,
             mssql::mssql_execute,
             duckdb_commands::duckdb_connect,
             duckdb_commands::duckdb_disconnect,
             duckdb_commands::duckdb_query,
+            duckdb_commands::duckdb_query_open,
+            duckdb_commands::duckdb_query_fetch,
+            duckdb_commands::duckdb_query_close,
+            duckdb_commands::duckdb_get_cell_blob,
+            duckdb_commands::duckdb_save_cell_blob,
             duckdb_commands::duckdb_execute,
             duckdb_commands::duckdb_test,
+            duckdb_commands::duckdb_cancel,
+            duckdb_commands::duckdb_list_extensions,
+            duckdb_commands::duckdb_install_extension,
+            duckdb_commands::duckdb_load_extension,
+            duckdb_commands::duckdb_load_extension_from_path,
+            duckdb_commands::duckdb_create_secret,
+            duckdb_commands::duckdb_list_secrets,
+            duckdb_commands::duckdb_drop_secret,
+            duckdb_commands::duckdb_attach,
+            duckdb_commands::duckdb_detach,
+            duckdb_commands::duckdb_get_schema,
+            duckdb_commands::duckdb_get_table_ddl,
+            duckdb_commands::duckdb_get_view_ddl,
+            duckdb_commands::duckdb_explain,
+            duckdb_commands::duckdb_export_query,
+            duckdb_commands::duckdb_import_file,
+            duckdb_commands::duckdb_sniff_csv,
+            duckdb_commands::duckdb_export_database,
+            duckdb_commands::duckdb_import_database,
+            duckdb_commands::duckdb_checkpoint,
+            duckdb_commands::duckdb_vacuum,
+            duckdb_commands::duckdb_append_rows,
+            duckdb_commands::duckdb_execute_batch,
+            duckdb_commands::duckdb_query_multi,
+            duckdb_commands::duckdb_begin_transaction,
+            duckdb_commands::duckdb_commit,
+            duckdb_commands::duckdb_rollback,
+            duckdb_commands::duckdb_transaction_status,
             git::git_clone_repo,
             git::git_init_repo,
             git::git_pull_repo,
@@ -303,6 +365,11 @@ pub fn run() {
             git::git_get_conflict_content,
             git::git_set_remote,
             git::git_get_remote_url,
+            git::git_list_branches,
+            git::git_create_branch,
+            git::git_checkout_branch,
+            git::git_delete_branch,
+            git::git_get_log,
         ])
         .setup(|app| {
             // Set up custom menu
@@ -324,8 +391,16 @@ pub fn run() {
             });
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::ExitRequested { .. } | tauri::RunEvent::Exit = event {
+                // Disconnect SSH sessions rather than leave them for the
+                // servers to time out, without holding up exit for long
+                let tunnel_manager = app.state::<TunnelManager>();
+                tauri::async_runtime::block_on(tunnel_manager.close_all(std::time::Duration::from_secs(2)));
+            }
+        });
 }
 
 async fn check_for_update(app: tauri::AppHandle) -> tauri_plugin_updater::Result<()> {
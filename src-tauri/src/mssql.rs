@@ -1,34 +1,337 @@
 use async_native_tls::TlsStream;
+use futures_util::{FutureExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tauri::State;
-use tiberius::{AuthMethod, Client, Config, Query, Row};
-use tokio::net::TcpStream;
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager, State};
+use tiberius::numeric::Numeric;
+use tiberius::time::chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
+use tiberius::xml::XmlData;
+use tiberius::{
+    AuthMethod, Client, Column, ColumnData, ColumnType, Config, IntoSql, Query, QueryItem, QueryStream, Row, TokenRow,
+    Uuid,
+};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::{mpsc, oneshot, Mutex, OwnedMutexGuard};
 use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+use sqlparser::ast::{Query as SqlQuery, SetExpr, Statement};
+use sqlparser::dialect::MsSqlDialect;
+use sqlparser::parser::Parser;
+use tracing::instrument::WithSubscriber;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MssqlConfig {
+    /// Host name, optionally as `host\INSTANCE` for a named instance
     pub host: String,
     pub port: u16,
+    /// Named instance whose port is looked up through SQL Browser; `port` is
+    /// ignored when set
+    pub instance_name: Option<String>,
     pub database: String,
+    /// "sql_server" (default), "windows" (NTLM with `domain`), or "integrated"
+    /// (SSPI as the current Windows user, no username or password)
+    pub auth_method: Option<String>,
+    pub domain: Option<String>,
+    #[serde(default)]
     pub username: String,
+    #[serde(default)]
     pub password: String,
-    pub encrypt: Option<bool>,
-    pub trust_cert: Option<bool>,
+    /// Defaults to `on`
+    pub encrypt: Option<MssqlEncrypt>,
+    /// Accept any server certificate. Off by default; prefer ca_cert_path.
+    #[serde(default)]
+    pub trust_cert: bool,
+    /// PEM or DER file with a CA to trust in addition to the system store
+    pub ca_cert_path: Option<String>,
+    /// Name to validate the certificate against when it differs from `host`,
+    /// e.g. when connecting through a tunnel
+    pub hostname_override: Option<String>,
+    /// Reported to the server as the program name (defaults to
+    /// `Seaquel <version>`)
+    pub application_name: Option<String>,
+    /// Time allowed for connecting as a whole: TCP, TLS handshake and login
+    /// (defaults to 30 seconds)
+    pub connect_timeout_secs: Option<u64>,
+    /// Default limit for each query, overridable per call. No limit if unset.
+    pub query_timeout_secs: Option<u64>,
+    /// Log in with ApplicationIntent=ReadOnly, so an availability group
+    /// listener routes the connection to a readable secondary
+    #[serde(default)]
+    pub read_only_intent: bool,
+    /// Refuse anything but SELECT queries before they reach the server
+    #[serde(default)]
+    pub read_only: bool,
+    /// How long a connection may sit idle before it is pinged (defaults to
+    /// 60 seconds; 0 turns pinging off)
+    pub keep_alive_secs: Option<u64>,
+    /// Binary cells longer than this come back as `{"$blob_length": n}`
+    /// instead of their bytes (defaults to 1 MiB; 0 sends every value whole).
+    /// Fetch them with mssql_get_cell_binary or mssql_save_cell_binary.
+    pub binary_preview_bytes: Option<usize>,
+    /// Sessions opened beyond the first when commands overlap, so a table
+    /// preview doesn't wait behind a long query (defaults to 1)
+    pub extra_sessions: Option<usize>,
+    /// Dial every address the host resolves to at once and use the first
+    /// that answers, as for an availability group listener spanning subnets
+    #[serde(default)]
+    pub multi_subnet_failover: bool,
+    /// `host[\instance][,port]` to try when the server can't be reached
+    pub failover_partner: Option<String>,
+}
+
+/// How the connection is encrypted, matching the `Encrypt` connection
+/// string setting
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MssqlEncrypt {
+    /// No TLS
+    Off,
+    /// TLS negotiated during pre-login (TDS 7.x)
+    #[default]
+    On,
+    /// TLS before any TDS traffic (TDS 8.0). The certificate is always
+    /// validated; `trust_cert` is ignored.
+    Strict,
 }
 
 #[derive(Debug, Serialize)]
 pub struct MssqlConnection {
     pub connection_id: String,
+    /// The server's session id (@@SPID)
+    pub session_id: i16,
+    /// The host and address the connection landed on, e.g.
+    /// `aglistener (10.0.2.7:1433)`
+    pub endpoint: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlConnectionInfo {
+    pub connection_id: String,
+    pub server: String,
+    /// Where the connection actually landed, which changes after a failover
+    pub endpoint: String,
+    pub database: String,
+    /// "healthy", or "stale" once a keep-alive ping has failed. Commands
+    /// still reconnect a stale connection when they find it broken.
+    pub state: String,
+    /// Seconds since the last command; 0 while one is running
+    pub idle_secs: u64,
+}
+
+/// Payload of connection lifecycle events such as `mssql://reconnected`
+#[derive(Debug, Clone, Serialize)]
+pub struct MssqlConnectionEvent {
+    pub connection_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlTestResult {
+    /// Full @@VERSION string
+    pub version: String,
+    pub edition: String,
+    /// The database the login ended up in, which is the login's default
+    /// database when none was given
+    pub database: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlConnectionStringResult {
+    pub connection_id: String,
+    pub session_id: i16,
+    pub endpoint: String,
+    /// The parsed settings with the password removed, for filling in the form
+    pub config: MssqlConfig,
+    /// Keys in the connection string that were ignored
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlCursorInfo {
+    pub cursor_id: String,
+    pub columns: Vec<String>,
+    pub column_types: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlCursorBatch {
+    pub rows: Vec<serde_json::Value>,
+    /// No rows are left; the cursor can be closed
+    pub done: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlBulkInsertResult {
+    pub rows_sent: u64,
+    pub batches: Vec<MssqlBulkInsertBatch>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MssqlBulkInsertBatch {
+    pub rows: u64,
+    pub elapsed_ms: u64,
+}
+
+/// Payload of `mssql://bulk-insert-progress`, sent after each batch
+#[derive(Debug, Clone, Serialize)]
+pub struct MssqlBulkInsertProgress {
+    pub connection_id: String,
+    pub table: String,
+    pub rows_sent: u64,
+    pub total_rows: u64,
+    pub batch: MssqlBulkInsertBatch,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlDatabase {
+    pub name: String,
+    /// sys.databases state, e.g. ONLINE, OFFLINE, RESTORING
+    pub state: String,
+    /// Whether the login can open it
+    pub has_access: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlSchemaTree {
+    /// The database the schemas belong to
+    pub database: String,
+    /// Every database the login can open
+    pub databases: Vec<String>,
+    pub schemas: Vec<MssqlSchema>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlSchema {
+    pub name: String,
+    pub tables: Vec<MssqlTable>,
+    pub views: Vec<MssqlTable>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlTable {
+    pub name: String,
+    pub columns: Vec<MssqlColumn>,
+    /// Primary key columns in key order; empty for views and heaps
+    pub primary_key: Vec<String>,
+    pub foreign_keys: Vec<MssqlForeignKey>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlColumn {
+    pub name: String,
+    /// Type with its length, precision or scale, e.g. `nvarchar(50)`
+    pub data_type: String,
+    pub nullable: bool,
+    pub identity: bool,
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlForeignKey {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub referenced_schema: String,
+    pub referenced_table: String,
+    pub referenced_columns: Vec<String>,
+}
+
+/// One argument to mssql_exec_procedure
+#[derive(Debug, Deserialize)]
+pub struct MssqlProcedureParam {
+    /// Parameter name, with or without the leading `@`
+    pub name: String,
+    /// Ignored for `out` parameters
+    #[serde(default)]
+    pub value: serde_json::Value,
+    /// "in" (default), "out" or "inout"
+    pub direction: Option<String>,
+    /// SQL type such as `int` or `nvarchar(100)`; required for `out` and
+    /// `inout`, which are declared as variables
+    pub sql_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlProcedureResult {
+    /// Result sets the procedure returned
+    pub results: Vec<MssqlQueryResult>,
+    /// Output parameter values by name, without the `@`
+    pub output: serde_json::Map<String, serde_json::Value>,
+    /// The procedure's RETURN value
+    pub return_code: Option<i32>,
+    pub messages: Vec<MssqlMessage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlProcedureParamInfo {
+    pub name: String,
+    pub sql_type: String,
+    /// "in", or "inout" for OUTPUT parameters (T-SQL has no output-only ones)
+    pub direction: String,
+    /// Only reported for CLR procedures; T-SQL defaults aren't in the catalog
+    pub has_default: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlQueryPlan {
+    /// ShowPlan XML documents as the server returned them, one per batch for
+    /// estimated plans and one per statement for actual plans
+    pub plans: Vec<String>,
+    pub statements: Vec<MssqlPlanStatement>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlPlanStatement {
+    pub text: String,
+    pub estimated_cost: Option<f64>,
+    /// Plan-level warnings, e.g. implicit conversions
+    pub warnings: Vec<String>,
+    /// The most expensive operators by their own estimated cost
+    pub top_operators: Vec<MssqlPlanOperator>,
+    pub root: Option<MssqlPlanNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MssqlPlanOperator {
+    pub node_id: i32,
+    pub physical_op: String,
+    pub cost_percent: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MssqlPlanNode {
+    pub node_id: i32,
+    pub physical_op: String,
+    pub logical_op: String,
+    /// Table or index the operator reads, e.g. `[dbo].[Orders].[PK_Orders]`
+    pub object: Option<String>,
+    pub estimated_rows: Option<f64>,
+    /// Only in actual plans; summed over threads
+    pub actual_rows: Option<u64>,
+    /// Estimated cost of this operator and everything below it
+    pub subtree_cost: Option<f64>,
+    /// This operator's own share of the statement's estimated cost
+    pub cost_percent: Option<f64>,
+    pub warnings: Vec<String>,
+    pub children: Vec<MssqlPlanNode>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct MssqlQueryResult {
     pub columns: Vec<String>,
+    /// SQL Server type of each column, aligned with `columns`
+    pub column_types: Vec<String>,
     pub rows: Vec<serde_json::Value>,
     pub rows_affected: u64,
+    /// PRINT output and RAISERROR messages of severity 10 or lower, in order
+    pub messages: Vec<MssqlMessage>,
+}
+
+/// An informational message from the server. tiberius 0.12 only logs the
+/// text of INFO tokens, so their number, severity and line can't be returned.
+#[derive(Debug, Clone, Serialize)]
+pub struct MssqlMessage {
+    pub text: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,263 +348,4066 @@ impl std::fmt::Display for MssqlError {
 
 impl std::error::Error for MssqlError {}
 
+/// One result set from a batch. Columns come from the result metadata, so
+/// they're known even when there are no rows.
+struct ResultSet {
+    columns: Vec<Column>,
+    rows: Vec<Row>,
+}
+
 // Support both TLS and non-TLS connections
 enum MssqlClient {
+    /// Strict encryption: TLS set up by us before tiberius sees the stream
     Tls(Client<TlsStream<Compat<TcpStream>>>),
+    /// Unencrypted, or encrypted by tiberius itself during pre-login
     Plain(Client<Compat<TcpStream>>),
 }
 
 impl MssqlClient {
-    async fn query(&mut self, sql: &str) -> Result<Vec<Row>, tiberius::error::Error> {
-        let query = Query::new(sql);
-        match self {
-            MssqlClient::Tls(client) => {
-                let stream = query.query(client).await?;
-                stream.into_first_result().await
+    async fn query_results(&mut self, query: Query<'_>) -> Result<Vec<ResultSet>, tiberius::error::Error> {
+        let run = async move {
+            match self {
+                MssqlClient::Tls(client) => collect_results(query.query(client).await?).await,
+                MssqlClient::Plain(client) => collect_results(query.query(client).await?).await,
             }
-            MssqlClient::Plain(client) => {
-                let stream = query.query(client).await?;
-                stream.into_first_result().await
+        };
+        guard_unsupported_types(run).await
+    }
+
+    async fn query_stream(&mut self, query: Query<'_>) -> Result<QueryStream<'_>, tiberius::error::Error> {
+        let run = async move {
+            match self {
+                MssqlClient::Tls(client) => query.query(client).await,
+                MssqlClient::Plain(client) => query.query(client).await,
             }
+        };
+        guard_unsupported_types(run).await
+    }
+
+    async fn execute(&mut self, query: Query<'_>) -> Result<tiberius::ExecuteResult, tiberius::error::Error> {
+        match self {
+            MssqlClient::Tls(client) => query.execute(client).await,
+            MssqlClient::Plain(client) => query.execute(client).await,
         }
     }
 
-    async fn execute(&mut self, sql: &str) -> Result<tiberius::ExecuteResult, tiberius::error::Error> {
+    /// Send rows in one bulk load request. On failure, returns the index of
+    /// the row being sent if the error was about that row's data.
+    async fn bulk_insert(
+        &mut self,
+        table: &str,
+        rows: Vec<TokenRow<'static>>,
+    ) -> Result<u64, (Option<usize>, tiberius::error::Error)> {
         match self {
-            MssqlClient::Tls(client) => client.execute(sql, &[]).await,
-            MssqlClient::Plain(client) => client.execute(sql, &[]).await,
+            MssqlClient::Tls(client) => bulk_load(client, table, rows).await,
+            MssqlClient::Plain(client) => bulk_load(client, table, rows).await,
         }
     }
+
+    /// The server's session id for this connection (@@SPID)
+    async fn spid(&mut self) -> Result<i16, tiberius::error::Error> {
+        let results = self.query_results(Query::new("SELECT @@SPID")).await?;
+        Ok(results
+            .first()
+            .and_then(|result| result.rows.first())
+            .and_then(|row| row.try_get::<i16, _>(0).ok().flatten())
+            .unwrap_or_default())
+    }
 }
 
-struct ConnectionHandle {
-    client: MssqlClient,
+async fn bulk_load<S>(
+    client: &mut Client<S>,
+    table: &str,
+    rows: Vec<TokenRow<'static>>,
+) -> Result<u64, (Option<usize>, tiberius::error::Error)>
+where
+    S: futures_util::AsyncRead + futures_util::AsyncWrite + Unpin + Send,
+{
+    let mut request = client.bulk_insert(table).await.map_err(|e| (None, e))?;
+    for (index, row) in rows.into_iter().enumerate() {
+        request.send(row).await.map_err(|e| (Some(index), e))?;
+    }
+    let result = request.finalize().await.map_err(|e| (None, e))?;
+    Ok(result.total())
 }
 
-pub struct MssqlConnectionManager {
-    connections: Arc<Mutex<HashMap<String, ConnectionHandle>>>,
-    next_id: Arc<Mutex<u64>>,
+/// Collects the server's informational messages while a query runs.
+///
+/// tiberius reads INFO tokens (PRINT, low-severity RAISERROR) but doesn't
+/// return them; it only logs their text through `tracing`. Installed with
+/// `with_subscriber`, this sees the log events of the one future it wraps.
+#[derive(Clone, Default)]
+struct MessageCollector {
+    messages: Arc<std::sync::Mutex<Vec<MssqlMessage>>>,
 }
 
-impl MssqlConnectionManager {
-    pub fn new() -> Self {
-        Self {
-            connections: Arc::new(Mutex::new(HashMap::new())),
-            next_id: Arc::new(Mutex::new(1)),
+impl MessageCollector {
+    fn clear(&self) {
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.clear();
         }
     }
-}
 
-impl Default for MssqlConnectionManager {
-    fn default() -> Self {
-        Self::new()
+    fn take(&self) -> Vec<MssqlMessage> {
+        self.messages.lock().map(|mut m| std::mem::take(&mut *m)).unwrap_or_default()
     }
 }
 
-fn row_to_json(row: &Row) -> serde_json::Value {
-    let mut obj = serde_json::Map::new();
-    for col in row.columns() {
-        let col_name = col.name().to_string();
-        // Try to get value as different types, falling back through common types
-        // Start with string since SQL Server often returns nvarchar
-        let value = if let Some(v) = row.try_get::<&str, _>(col_name.as_str()).ok().flatten() {
-            serde_json::json!(v)
-        } else if let Some(v) = row.try_get::<i64, _>(col_name.as_str()).ok().flatten() {
-            serde_json::json!(v)
-        } else if let Some(v) = row.try_get::<i32, _>(col_name.as_str()).ok().flatten() {
-            serde_json::json!(v)
-        } else if let Some(v) = row.try_get::<i16, _>(col_name.as_str()).ok().flatten() {
-            serde_json::json!(v)
-        } else if let Some(v) = row.try_get::<u8, _>(col_name.as_str()).ok().flatten() {
-            serde_json::json!(v)
-        } else if let Some(v) = row.try_get::<f64, _>(col_name.as_str()).ok().flatten() {
-            serde_json::json!(v)
-        } else if let Some(v) = row.try_get::<f32, _>(col_name.as_str()).ok().flatten() {
-            serde_json::json!(v)
-        } else if let Some(v) = row.try_get::<bool, _>(col_name.as_str()).ok().flatten() {
-            serde_json::json!(v)
-        } else if let Some(v) = row.try_get::<&[u8], _>(col_name.as_str()).ok().flatten() {
-            // Binary data - encode as base64
-            use base64::{Engine as _, engine::general_purpose::STANDARD};
-            serde_json::json!(STANDARD.encode(v))
-        } else {
-            // NULL or unsupported type (dates, decimals, GUIDs handled as NULL for now)
-            // These would require additional feature flags in tiberius
-            serde_json::Value::Null
-        };
-        obj.insert(col_name, value);
+impl tracing::Subscriber for MessageCollector {
+    fn enabled(&self, metadata: &tracing::Metadata<'_>) -> bool {
+        *metadata.level() == tracing::Level::INFO && metadata.target() == "tiberius::tds::stream::token"
     }
-    serde_json::Value::Object(obj)
-}
 
-#[tauri::command]
-pub async fn mssql_connect(
-    config: MssqlConfig,
-    manager: State<'_, MssqlConnectionManager>,
-) -> Result<MssqlConnection, MssqlError> {
-    let mut tiberius_config = Config::new();
+    fn new_span(&self, _span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        tracing::span::Id::from_u64(1)
+    }
 
-    tiberius_config.host(&config.host);
-    tiberius_config.port(config.port);
-    tiberius_config.database(&config.database);
-    tiberius_config.authentication(AuthMethod::sql_server(&config.username, &config.password));
+    fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
 
-    // We handle TLS manually, so tell tiberius not to do encryption
-    tiberius_config.encryption(tiberius::EncryptionLevel::NotSupported);
+    fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
 
-    // Connect with timeout
-    let tcp = tokio::time::timeout(
-        std::time::Duration::from_secs(30),
-        TcpStream::connect(tiberius_config.get_addr()),
-    )
-    .await
-    .map_err(|_| MssqlError {
-        message: "Connection timed out".to_string(),
-        code: "TIMEOUT".to_string(),
-    })?
-    .map_err(|e| MssqlError {
-        message: format!("Failed to connect: {}", e),
-        code: "CONNECTION_ERROR".to_string(),
-    })?;
+    fn event(&self, event: &tracing::Event<'_>) {
+        struct Text(String);
+        impl tracing::field::Visit for Text {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{:?}", value);
+                }
+            }
+        }
 
-    tcp.set_nodelay(true).map_err(|e| MssqlError {
-        message: format!("Failed to set TCP nodelay: {}", e),
-        code: "TCP_ERROR".to_string(),
-    })?;
+        let mut text = Text(String::new());
+        event.record(&mut text);
+        // The same module logs environment changes at INFO, in these exact
+        // forms; those aren't messages from the batch. Nothing else depends
+        // on them: transaction state is read from the server afterwards.
+        const ENV_CHANGES: &[&str] = &[
+            "Begin transaction",
+            "Commit transaction",
+            "Rollback transaction",
+            "Defect transaction",
+        ];
+        const ENV_CHANGE_PREFIXES: &[&str] = &[
+            "Database change from '",
+            "Packet size change from '",
+            "SQL collation change from ",
+            "Server requested routing to a new address: ",
+            "Fallback mirror server: `",
+            "Ignored env change: `",
+        ];
+        if text.0.is_empty()
+            || ENV_CHANGES.contains(&text.0.as_str())
+            || ENV_CHANGE_PREFIXES.iter().any(|prefix| text.0.starts_with(prefix))
+        {
+            return;
+        }
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.push(MssqlMessage { text: text.0 });
+        }
+    }
 
-    // Wrap TCP stream with compat for futures-io trait compatibility
-    let tcp_compat = tcp.compat();
+    fn enter(&self, _span: &tracing::span::Id) {}
 
-    // Use encryption unless explicitly disabled (encrypt defaults to true for security)
-    let use_encryption = config.encrypt.unwrap_or(true);
+    fn exit(&self, _span: &tracing::span::Id) {}
+}
 
-    let client = if use_encryption {
-        // Wrap with TLS - Azure SQL and most production servers require encryption
-        let tls_connector = async_native_tls::TlsConnector::new()
-            .danger_accept_invalid_certs(config.trust_cert.unwrap_or(false))
-            .use_sni(true);
+const UNREADABLE_TYPES: &str =
+    "The result has a column type that can't be read (sql_variant, or a CLR type such as geography or hierarchyid)";
 
-        let tls_stream = tls_connector
-            .connect(&config.host, tcp_compat)
-            .await
-            .map_err(|e| MssqlError {
-                message: format!("TLS connection failed: {}. Try setting SSL Mode to 'disable' for localhost servers without TLS.", e),
-                code: "TLS_ERROR".to_string(),
-            })?;
+/// tiberius 0.12 panics on column metadata it can't decode: sql_variant and
+/// CLR types such as geography or hierarchyid. Turn that into an I/O error,
+/// as the panic leaves the rest of the response unread and the client can't
+/// be used again; callers reconnect as for a dropped connection.
+async fn guard_unsupported_types<F, T>(future: F) -> Result<T, tiberius::error::Error>
+where
+    F: std::future::Future<Output = Result<T, tiberius::error::Error>>,
+{
+    std::panic::AssertUnwindSafe(future).catch_unwind().await.unwrap_or_else(|_| {
+        Err(tiberius::error::Error::Io {
+            kind: std::io::ErrorKind::InvalidData,
+            message: UNREADABLE_TYPES.to_string(),
+        })
+    })
+}
 
-        let inner_client = Client::connect(tiberius_config, tls_stream)
-            .await
-            .map_err(|e| MssqlError {
-                message: format!("Failed to connect to SQL Server: {}", e),
-                code: "AUTH_ERROR".to_string(),
-            })?;
+/// Whether an error came from guard_unsupported_types
+fn is_unreadable_types(e: &tiberius::error::Error) -> bool {
+    matches!(e, tiberius::error::Error::Io { message, .. } if message == UNREADABLE_TYPES)
+}
 
-        MssqlClient::Tls(inner_client)
-    } else {
-        // Plain TCP connection for localhost/development servers without TLS
-        let inner_client = Client::connect(tiberius_config, tcp_compat)
-            .await
-            .map_err(|e| MssqlError {
-                message: format!("Failed to connect to SQL Server: {}", e),
-                code: "AUTH_ERROR".to_string(),
-            })?;
+/// The error for a result tiberius can't read, once the client is replaced
+fn unreadable_types_error(rolled_back: bool) -> MssqlError {
+    let mut message = format!(
+        "{}. CAST the column to another type, e.g. CAST(col AS nvarchar(4000)), and use \
+         SQL_VARIANT_PROPERTY(col, 'BaseType') for a variant's base type.",
+        UNREADABLE_TYPES
+    );
+    if rolled_back {
+        message.push_str(" The connection had to be reset, which rolled back the open transaction.");
+    }
+    MssqlError {
+        message,
+        code: "UNSUPPORTED_TYPE".to_string(),
+    }
+}
 
-        MssqlClient::Plain(inner_client)
-    };
+/// Read every result set from a stream, including empty ones
+async fn collect_results(mut stream: QueryStream<'_>) -> Result<Vec<ResultSet>, tiberius::error::Error> {
+    let mut results: Vec<ResultSet> = Vec::new();
+    while let Some(item) = stream.try_next().await? {
+        match item {
+            QueryItem::Metadata(meta) => results.push(ResultSet {
+                columns: meta.columns().to_vec(),
+                rows: Vec::new(),
+            }),
+            QueryItem::Row(row) => {
+                if let Some(result) = results.last_mut() {
+                    result.rows.push(row);
+                }
+            }
+        }
+    }
+    Ok(results)
+}
 
-    // Generate connection ID
-    let connection_id = {
-        let mut next_id = manager.next_id.lock().await;
-        let id = format!("mssql-{}", *next_id);
-        *next_id += 1;
-        id
-    };
+/// Map tiberius' wire type to the SQL Server type name shown to the user.
+/// Nullable variants (intn, floatn, ...) don't carry their exact size.
+fn column_type_name(column_type: ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Null => "null",
+        ColumnType::Bit | ColumnType::Bitn => "bit",
+        ColumnType::Int1 => "tinyint",
+        ColumnType::Int2 => "smallint",
+        ColumnType::Int4 | ColumnType::Intn => "int",
+        ColumnType::Int8 => "bigint",
+        ColumnType::Datetime4 => "smalldatetime",
+        ColumnType::Float4 => "real",
+        ColumnType::Float8 | ColumnType::Floatn => "float",
+        ColumnType::Money => "money",
+        ColumnType::Money4 => "smallmoney",
+        ColumnType::Datetime | ColumnType::Datetimen => "datetime",
+        ColumnType::Guid => "uniqueidentifier",
+        ColumnType::Decimaln => "decimal",
+        ColumnType::Numericn => "numeric",
+        ColumnType::Daten => "date",
+        ColumnType::Timen => "time",
+        ColumnType::Datetime2 => "datetime2",
+        ColumnType::DatetimeOffsetn => "datetimeoffset",
+        ColumnType::BigVarBin => "varbinary",
+        ColumnType::BigVarChar => "varchar",
+        ColumnType::BigBinary => "binary",
+        ColumnType::BigChar => "char",
+        ColumnType::NVarchar => "nvarchar",
+        ColumnType::NChar => "nchar",
+        ColumnType::Xml => "xml",
+        ColumnType::Udt => "udt",
+        ColumnType::Text => "text",
+        ColumnType::Image => "image",
+        ColumnType::NText => "ntext",
+        ColumnType::SSVariant => "sql_variant",
+    }
+}
 
-    // Store connection
-    {
-        let mut connections = manager.connections.lock().await;
-        connections.insert(connection_id.clone(), ConnectionHandle { client });
+struct ConnectionHandle {
+    /// The connection's sessions. The first is opened on connect and the
+    /// rest as commands overlap, up to `max_sessions`.
+    sessions: Vec<PooledSession>,
+    max_sessions: usize,
+    /// Sessions being opened, counted against `max_sessions`
+    opening: usize,
+    /// Used to open more sessions; its database follows mssql_use_database
+    config: MssqlConfig,
+    /// `host:port` or `host\instance`, for listing
+    server: String,
+    /// Where the first session landed, listed while every session is busy
+    endpoint: String,
+    /// When a command last used the connection
+    last_used: Instant,
+}
+
+struct PooledSession {
+    /// Locked per command, so a long query only blocks its own session
+    session: Arc<Mutex<Session>>,
+    /// Shared with the session; readable while it's busy
+    in_transaction: Arc<AtomicBool>,
+}
+
+impl PooledSession {
+    fn new(session: Session) -> Self {
+        let in_transaction = Arc::clone(&session.in_transaction);
+        PooledSession {
+            session: Arc::new(Mutex::new(session)),
+            in_transaction,
+        }
     }
+}
 
-    Ok(MssqlConnection { connection_id })
+/// A live client plus the config it was opened with, kept so the connection
+/// can be re-established after it drops
+struct Session {
+    client: MssqlClient,
+    config: MssqlConfig,
+    /// Server session id, used to kill a query that runs past its timeout
+    spid: i16,
+    /// A keep-alive ping failed. Cleared when the session is reopened.
+    stale: bool,
+    /// A cursor stopped mid-response on a result tiberius can't read, so the
+    /// client can't be used again. The next checkout replaces it.
+    broken: bool,
+    /// A transaction is open, so the pool sends every command here
+    in_transaction: Arc<AtomicBool>,
+    /// Host and address the session is connected to
+    endpoint: String,
 }
 
-#[tauri::command]
-pub async fn mssql_disconnect(
-    connection_id: String,
-    manager: State<'_, MssqlConnectionManager>,
-) -> Result<(), MssqlError> {
-    let mut connections = manager.connections.lock().await;
+impl Session {
+    async fn open(config: MssqlConfig) -> Result<Self, MssqlError> {
+        let (mut client, endpoint) = connect_with_backoff(&config).await?;
+        let spid = client.spid().await.map_err(|e| MssqlError {
+            message: format!("Failed to read session id: {}", e),
+            code: "CONNECTION_ERROR".to_string(),
+        })?;
+        Ok(Session {
+            client,
+            config,
+            spid,
+            stale: false,
+            broken: false,
+            in_transaction: Arc::new(AtomicBool::new(false)),
+            endpoint,
+        })
+    }
 
-    if connections.remove(&connection_id).is_some() {
+    /// Take over a newly opened session. The server dropped any open
+    /// transaction with the old one, so it no longer pins the pool.
+    fn replace(&mut self, fresh: Session) {
+        let in_transaction = Arc::clone(&self.in_transaction);
+        in_transaction.store(false, Ordering::SeqCst);
+        *self = Session { in_transaction, ..fresh };
+    }
+
+    /// Ask the server whether the last command left a transaction open,
+    /// which also covers procedures and implicit transactions. If it can't
+    /// be asked, the next command finds out why.
+    async fn refresh_transaction(&mut self) {
+        let Ok(results) = self.client.query_results(Query::new("SELECT @@TRANCOUNT")).await else {
+            return;
+        };
+        let count = results
+            .first()
+            .and_then(|result| result.rows.first())
+            .and_then(|row| row.try_get::<i32, _>(0).ok().flatten());
+        if let Some(count) = count {
+            self.in_transaction.store(count > 0, Ordering::SeqCst);
+        }
+    }
+
+    /// Run a single SELECT whose result tiberius can't read, with its
+    /// sql_variant columns cast to nvarchar and its CLR columns converted
+    /// with ToString(). Returns the results and each column's original type
+    /// name, or None if the result can't be described or has unnamed or
+    /// duplicate columns, which a wrapping SELECT can't list.
+    async fn query_as_text(
+        &mut self,
+        sql: &str,
+    ) -> Result<Option<(Vec<ResultSet>, Vec<Option<String>>)>, tiberius::error::Error> {
+        let mut describe = Query::new("EXEC sp_describe_first_result_set @P1");
+        describe.bind(sql);
+        let described = self.client.query_results(describe).await?;
+        let Some(described) = described.into_iter().next() else {
+            return Ok(None);
+        };
+
+        let mut names: Vec<String> = Vec::new();
+        let mut projection = Vec::new();
+        let mut type_names = Vec::new();
+        for row in &described.rows {
+            if row.try_get::<bool, _>("is_hidden")?.unwrap_or(false) {
+                continue;
+            }
+            let Some(name) = row.try_get::<&str, _>("name")? else {
+                return Ok(None);
+            };
+            if names.iter().any(|seen| seen.eq_ignore_ascii_case(name)) {
+                return Ok(None);
+            }
+            let column = quote_identifier(name);
+            let type_name = row.try_get::<&str, _>("system_type_name")?.map(str::to_string);
+            let (expression, type_name) = match row.try_get::<i32, _>("system_type_id")? {
+                Some(98) => (format!("CAST({} AS nvarchar(4000))", column), Some("sql_variant".to_string())),
+                Some(240) => (format!("{}.ToString()", column), type_name.or(Some("udt".to_string()))),
+                _ => (column.clone(), None),
+            };
+            projection.push(format!("{} AS {}", expression, column));
+            type_names.push(type_name);
+            names.push(name.to_string());
+        }
+        if type_names.iter().all(Option::is_none) {
+            return Ok(None);
+        }
+
+        let inner = sql.trim().trim_end_matches(';');
+        let wrapped = |inner: &str| format!("SELECT {} FROM ({}) AS [result]", projection.join(", "), inner);
+        let results = match self.client.query_results(Query::new(wrapped(inner))).await {
+            // ORDER BY is only allowed in a derived table alongside OFFSET
+            Err(tiberius::error::Error::Server(token)) if token.code() == 1033 => {
+                self.client
+                    .query_results(Query::new(wrapped(&format!("{} OFFSET 0 ROWS", inner))))
+                    .await?
+            }
+            result => result?,
+        };
+        Ok(Some((results, type_names)))
+    }
+
+    /// Replace a client whose connection has failed. Returns CONNECTION_LOST
+    /// with both errors if the new connection can't be made either.
+    async fn reconnect(
+        &mut self,
+        app: &tauri::AppHandle,
+        connection_id: &str,
+        error: tiberius::error::Error,
+    ) -> Result<(), MssqlError> {
+        let fresh = Session::open(self.config.clone()).await.map_err(|e| MssqlError {
+            message: format!("Connection lost ({}) and reconnecting failed: {}", error, e.message),
+            code: "CONNECTION_LOST".to_string(),
+        })?;
+        self.replace(fresh);
+        let _ = app.emit(
+            "mssql://reconnected",
+            MssqlConnectionEvent {
+                connection_id: connection_id.to_string(),
+            },
+        );
         Ok(())
-    } else {
+    }
+
+    /// Reconnect after a connection error and decide whether the failed
+    /// statement may be sent again. Only a read outside a transaction is: a
+    /// write may already have run before the connection dropped, and an open
+    /// transaction died with the old connection, so anything sent now would
+    /// run outside it. Otherwise returns CONNECTION_LOST, or UNSUPPORTED_TYPE
+    /// for a result tiberius can't read, which would fail again.
+    async fn recover(
+        &mut self,
+        app: &tauri::AppHandle,
+        connection_id: &str,
+        error: tiberius::error::Error,
+        reads_only: bool,
+    ) -> Result<(), MssqlError> {
+        let in_transaction = self.in_transaction.load(Ordering::SeqCst);
+        let unreadable = is_unreadable_types(&error);
+        let retry = !unreadable && !in_transaction && (reads_only || never_ran(&error));
+        let lost = error.to_string();
+        self.reconnect(app, connection_id, error).await?;
+        if retry {
+            return Ok(());
+        }
+        if unreadable {
+            return Err(unreadable_types_error(in_transaction));
+        }
+        let reason = if in_transaction {
+            "its transaction was rolled back"
+        } else {
+            "it may already have run"
+        };
         Err(MssqlError {
-            message: format!("Connection not found: {}", connection_id),
-            code: "CONNECTION_NOT_FOUND".to_string(),
+            message: format!("Connection lost ({}). Reconnected, but the statement wasn't re-run: {}", lost, reason),
+            code: "CONNECTION_LOST".to_string(),
         })
     }
-}
-
-#[tauri::command]
-pub async fn mssql_query(
-    connection_id: String,
-    sql: String,
-    manager: State<'_, MssqlConnectionManager>,
-) -> Result<MssqlQueryResult, MssqlError> {
-    let mut connections = manager.connections.lock().await;
 
-    let handle = connections.get_mut(&connection_id).ok_or(MssqlError {
-        message: format!("Connection not found: {}", connection_id),
-        code: "CONNECTION_NOT_FOUND".to_string(),
-    })?;
+    /// On a read-only connection, refuse `sql` unless it only reads. Pass None
+    /// for commands that always write.
+    fn check_read_only(&self, sql: Option<&str>) -> Result<(), MssqlError> {
+        if !self.config.read_only || sql.is_some_and(is_read_only_sql) {
+            return Ok(());
+        }
+        Err(MssqlError {
+            message: "This connection is read-only; only SELECT queries are allowed".to_string(),
+            code: "READ_ONLY_CONNECTION".to_string(),
+        })
+    }
 
-    let rows = handle.client.query(&sql).await.map_err(|e| MssqlError {
-        message: format!("Query failed: {}", e),
-        code: "QUERY_ERROR".to_string(),
-    })?;
+    /// The longest binary cell sent inline
+    fn binary_limit(&self) -> usize {
+        match self.config.binary_preview_bytes {
+            Some(0) => usize::MAX,
+            Some(limit) => limit,
+            None => 1024 * 1024,
+        }
+    }
 
-    // Get column names from first row or return empty result
-    let columns: Vec<String> = if !rows.is_empty() {
-        rows[0].columns().iter().map(|c| c.name().to_string()).collect()
-    } else {
-        vec![]
-    };
+    /// The limit for a query: the per-call value, else the connection default
+    fn query_timeout(&self, timeout_secs: Option<u64>) -> Option<u64> {
+        timeout_secs.or(self.config.query_timeout_secs).filter(|secs| *secs > 0)
+    }
 
-    // Convert rows to JSON
-    let json_rows: Vec<serde_json::Value> = rows
-        .iter()
-        .map(|row| row_to_json(row))
-        .collect();
+    /// Stop a query that ran past its limit. tiberius can't send an attention
+    /// packet, so the session is killed from a second connection, which then
+    /// takes over. Without permission to KILL, dropping the old connection
+    /// still makes the server abort the batch.
+    async fn cancel_timed_out(&mut self, timeout_secs: u64) -> MssqlError {
+        let mut message = format!("Query exceeded the {} second timeout and was cancelled", timeout_secs);
+        match Session::open(self.config.clone()).await {
+            Ok(mut fresh) => {
+                let kill = Query::new(format!("KILL {}", self.spid));
+                let _ = fresh.client.execute(kill).await;
+                self.replace(fresh);
+            }
+            // The old client is mid-response; the next command will find it
+            // broken and reconnect
+            Err(e) => message = format!("{} ({})", message, e.message),
+        }
+        MssqlError {
+            message,
+            code: "QUERY_TIMEOUT".to_string(),
+        }
+    }
+}
 
-    Ok(MssqlQueryResult {
-        columns,
-        rows: json_rows,
-        rows_affected: 0,
-    })
+/// Run a future, giving up after `timeout_secs` if set. Returns None when the
+/// limit was reached.
+async fn within<F: std::future::Future>(timeout_secs: Option<u64>, future: F) -> Option<F::Output> {
+    match timeout_secs {
+        Some(secs) => tokio::time::timeout(std::time::Duration::from_secs(secs), future).await.ok(),
+        None => Some(future.await),
+    }
 }
 
-#[tauri::command]
-pub async fn mssql_execute(
-    connection_id: String,
-    sql: String,
-    manager: State<'_, MssqlConnectionManager>,
-) -> Result<MssqlQueryResult, MssqlError> {
-    let mut connections = manager.connections.lock().await;
+/// Whether every statement in a batch is a plain query. Anything the parser
+/// can't read counts as a write, as do SELECT ... INTO and CTEs that wrap an
+/// INSERT, UPDATE or DELETE.
+fn is_read_only_sql(sql: &str) -> bool {
+    fn query_reads(query: &SqlQuery) -> bool {
+        let ctes_read = query
+            .with
+            .as_ref()
+            .is_none_or(|with| with.cte_tables.iter().all(|cte| query_reads(&cte.query)));
+        ctes_read && body_reads(&query.body)
+    }
 
-    let handle = connections.get_mut(&connection_id).ok_or(MssqlError {
-        message: format!("Connection not found: {}", connection_id),
-        code: "CONNECTION_NOT_FOUND".to_string(),
-    })?;
+    fn body_reads(body: &SetExpr) -> bool {
+        match body {
+            SetExpr::Select(select) => select.into.is_none(),
+            SetExpr::Query(query) => query_reads(query),
+            SetExpr::SetOperation { left, right, .. } => body_reads(left) && body_reads(right),
+            SetExpr::Values(_) | SetExpr::Table(_) => true,
+            _ => false,
+        }
+    }
 
-    let result = handle.client.execute(&sql).await.map_err(|e| MssqlError {
-        message: format!("Execute failed: {}", e),
-        code: "EXECUTE_ERROR".to_string(),
-    })?;
+    match Parser::parse_sql(&MsSqlDialect {}, sql) {
+        Ok(statements) => statements.iter().all(|statement| match statement {
+            Statement::Query(query) => query_reads(query),
+            _ => false,
+        }),
+        Err(_) => false,
+    }
+}
+
+/// Whether an error means the connection itself is gone, as opposed to the
+/// statement failing
+/// Whether `sql` is one read-only query, which can be wrapped in a SELECT
+fn is_single_select(sql: &str) -> bool {
+    is_read_only_sql(sql) && Parser::parse_sql(&MsSqlDialect {}, sql).is_ok_and(|statements| statements.len() == 1)
+}
+
+fn is_connection_error(e: &tiberius::error::Error) -> bool {
+    match e {
+        tiberius::error::Error::Io { .. } => true,
+        // Severity 20 and above closes the connection; 40613 is Azure SQL
+        // reporting the database as unavailable (e.g. a paused serverless one)
+        tiberius::error::Error::Server(token) => token.class() >= 20 || token.code() == 40613,
+        _ => false,
+    }
+}
+
+/// Whether a connection error means the server never ran the statement:
+/// Azure SQL refuses requests while the database is unavailable (40613)
+fn never_ran(e: &tiberius::error::Error) -> bool {
+    matches!(e, tiberius::error::Error::Server(token) if token.code() == 40613)
+}
+
+type CursorRequest = (usize, oneshot::Sender<Result<MssqlCursorBatch, MssqlError>>);
+
+/// A streaming query. The task behind it holds one of the connection's
+/// sessions until the cursor is closed.
+struct MssqlCursor {
+    connection_id: String,
+    requests: mpsc::Sender<CursorRequest>,
+}
+
+pub struct MssqlConnectionManager {
+    connections: Arc<Mutex<HashMap<String, ConnectionHandle>>>,
+    cursors: Arc<Mutex<HashMap<String, MssqlCursor>>>,
+    next_id: Arc<Mutex<u64>>,
+}
+
+impl MssqlConnectionManager {
+    pub fn new() -> Self {
+        Self {
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            cursors: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(Mutex::new(1)),
+        }
+    }
+}
+
+impl Default for MssqlConnectionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Convert a row to an object keyed by column name. Columns sharing a name
+/// overwrite each other, so prefer row_to_array where that matters.
+fn row_to_json(row: &Row, binary_limit: usize) -> serde_json::Value {
+    let mut obj = serde_json::Map::new();
+    for (idx, col) in row.columns().iter().enumerate() {
+        obj.insert(col.name().to_string(), cell_to_json(row, idx, binary_limit));
+    }
+    serde_json::Value::Object(obj)
+}
+
+/// Convert a row to an array of values in column order
+fn row_to_array(row: &Row, binary_limit: usize) -> serde_json::Value {
+    serde_json::Value::Array((0..row.len()).map(|idx| cell_to_json(row, idx, binary_limit)).collect())
+}
+
+fn cell_to_json(row: &Row, idx: usize, binary_limit: usize) -> serde_json::Value {
+    match row.columns()[idx].column_type() {
+        // tiberius decodes money as f64 already divided by 10000; rebuild the
+        // four decimal places so 12.5 shows as 12.5000 like in SSMS
+        ColumnType::Money | ColumnType::Money4 => {
+            return match row.try_get::<f64, _>(idx).ok().flatten() {
+                Some(v) => serde_json::json!(money_to_string(v)),
+                None => serde_json::Value::Null,
+            };
+        }
+        ColumnType::Xml => {
+            return match row.try_get::<&XmlData, _>(idx).ok().flatten() {
+                Some(v) => serde_json::json!(v.as_ref()),
+                None => serde_json::Value::Null,
+            };
+        }
+        _ => {}
+    }
+
+    // Try to get value as different types, falling back through common types
+    // Start with string since SQL Server often returns nvarchar
+    if let Some(v) = row.try_get::<&str, _>(idx).ok().flatten() {
+        serde_json::json!(v)
+    } else if let Some(v) = row.try_get::<i64, _>(idx).ok().flatten() {
+        serde_json::json!(v)
+    } else if let Some(v) = row.try_get::<i32, _>(idx).ok().flatten() {
+        serde_json::json!(v)
+    } else if let Some(v) = row.try_get::<i16, _>(idx).ok().flatten() {
+        serde_json::json!(v)
+    } else if let Some(v) = row.try_get::<u8, _>(idx).ok().flatten() {
+        serde_json::json!(v)
+    } else if let Some(v) = row.try_get::<f64, _>(idx).ok().flatten() {
+        serde_json::json!(v)
+    } else if let Some(v) = row.try_get::<f32, _>(idx).ok().flatten() {
+        serde_json::json!(v)
+    } else if let Some(v) = row.try_get::<bool, _>(idx).ok().flatten() {
+        serde_json::json!(v)
+    } else if let Some(v) = row.try_get::<&[u8], _>(idx).ok().flatten() {
+        // Large blobs stay on the Rust side; the UI fetches them on demand
+        if v.len() > binary_limit {
+            return serde_json::json!({ "$blob_length": v.len() });
+        }
+        // Binary data - encode as base64
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+        serde_json::json!(STANDARD.encode(v))
+    } else if let Some(v) = row.try_get::<DateTime<FixedOffset>, _>(idx).ok().flatten() {
+        serde_json::json!(datetimeoffset_to_string(v))
+    } else if let Some(v) = row.try_get::<NaiveDateTime, _>(idx).ok().flatten() {
+        serde_json::json!(datetime_to_string(v))
+    } else if let Some(v) = row.try_get::<NaiveDate, _>(idx).ok().flatten() {
+        serde_json::json!(v.to_string())
+    } else if let Some(v) = row.try_get::<NaiveTime, _>(idx).ok().flatten() {
+        serde_json::json!(v.to_string())
+    } else if let Some(v) = row.try_get::<Numeric, _>(idx).ok().flatten() {
+        // decimal/numeric as a string so no precision is lost in JS
+        serde_json::json!(numeric_to_string(v))
+    } else if let Some(v) = row.try_get::<Uuid, _>(idx).ok().flatten() {
+        serde_json::json!(v.hyphenated().to_string())
+    } else {
+        // NULL or unsupported type
+        serde_json::Value::Null
+    }
+}
+
+/// money and smallmoney with their four decimal places, as SSMS shows them
+fn money_to_string(value: f64) -> String {
+    numeric_to_string(Numeric::new_with_scale((value * 1e4).round() as i128, 4))
+}
+
+/// datetimeoffset, keeping the original offset rather than converting to UTC
+fn datetimeoffset_to_string(value: DateTime<FixedOffset>) -> String {
+    value.to_rfc3339()
+}
+
+/// datetime, datetime2 and smalldatetime. The fraction comes in groups of
+/// three digits and is left out when zero.
+fn datetime_to_string(value: NaiveDateTime) -> String {
+    value.format("%Y-%m-%dT%H:%M:%S%.f").to_string()
+}
+
+/// Format a decimal exactly. tiberius' own Numeric formatting breaks on
+/// negative fractions, and rust_decimal can't hold all 38 digits of precision.
+fn numeric_to_string(n: Numeric) -> String {
+    let scale = n.scale() as usize;
+    let sign = if n.value() < 0 { "-" } else { "" };
+    let digits = n.value().unsigned_abs().to_string();
+    if scale == 0 {
+        return format!("{}{}", sign, digits);
+    }
+
+    // Pad so there is at least one digit before the decimal point
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+    format!("{}{}.{}", sign, int_part, frac_part)
+}
+
+fn auth_method(config: &MssqlConfig) -> Result<AuthMethod, MssqlError> {
+    match config.auth_method.as_deref().unwrap_or("sql_server") {
+        "sql_server" => Ok(AuthMethod::sql_server(&config.username, &config.password)),
+        "windows" => windows_auth(config),
+        "integrated" => integrated_auth(),
+        other => Err(MssqlError {
+            message: format!("Unknown auth method: {}", other),
+            code: "INVALID_AUTH_METHOD".to_string(),
+        }),
+    }
+}
+
+#[cfg(windows)]
+fn windows_auth(config: &MssqlConfig) -> Result<AuthMethod, MssqlError> {
+    // tiberius takes the domain as part of the user name
+    let user = match config.domain.as_deref().filter(|d| !d.is_empty()) {
+        Some(domain) => format!("{}\\{}", domain, config.username),
+        None => config.username.clone(),
+    };
+    Ok(AuthMethod::windows(user, &config.password))
+}
+
+#[cfg(not(windows))]
+fn windows_auth(_config: &MssqlConfig) -> Result<AuthMethod, MssqlError> {
+    Err(MssqlError {
+        message: "Windows authentication is only supported on Windows".to_string(),
+        code: "UNSUPPORTED_AUTH_METHOD".to_string(),
+    })
+}
+
+#[cfg(windows)]
+fn integrated_auth() -> Result<AuthMethod, MssqlError> {
+    Ok(AuthMethod::Integrated)
+}
+
+#[cfg(not(windows))]
+fn integrated_auth() -> Result<AuthMethod, MssqlError> {
+    Err(MssqlError {
+        message: "Integrated authentication is only supported on Windows".to_string(),
+        code: "UNSUPPORTED_AUTH_METHOD".to_string(),
+    })
+}
+
+/// Login failures come back as server errors; pass their message through so a
+/// bad password or domain account is distinguishable from a network problem
+fn login_error(e: tiberius::error::Error) -> MssqlError {
+    match e {
+        // 18456: login failed, 18452: untrusted domain, 18470: login disabled.
+        // 4060: the requested database doesn't exist or can't be opened.
+        tiberius::error::Error::Server(token) if matches!(token.code(), 18456 | 18452 | 18470) => MssqlError {
+            message: token.message().to_string(),
+            code: "AUTH_FAILED".to_string(),
+        },
+        tiberius::error::Error::Server(token) if token.code() == 4060 => MssqlError {
+            message: token.message().to_string(),
+            code: "DATABASE_NOT_FOUND".to_string(),
+        },
+        tiberius::error::Error::Tls(message) => MssqlError {
+            message: format!("TLS connection failed: {}", message),
+            code: "TLS_ERROR".to_string(),
+        },
+        // Azure SQL serverless databases report this while resuming from pause
+        tiberius::error::Error::Server(token) if token.code() == 40613 => MssqlError {
+            message: token.message().to_string(),
+            code: "DATABASE_UNAVAILABLE".to_string(),
+        },
+        e => MssqlError {
+            message: format!("Failed to connect to SQL Server: {}", e),
+            code: "AUTH_ERROR".to_string(),
+        },
+    }
+}
+
+/// Build a query with JSON parameters bound as @P1, @P2, ... Strings are sent
+/// as NVARCHAR; `param_types` can name a SQL type per parameter where that
+/// matters (VARCHAR to keep index seeks on varchar columns, DATETIME2, ...).
+fn build_query(
+    sql: &str,
+    params: Option<&[serde_json::Value]>,
+    param_types: Option<&[Option<String>]>,
+) -> Result<Query<'static>, MssqlError> {
+    let params = params.unwrap_or_default();
+    if params.is_empty() {
+        return Ok(Query::new(sql.to_string()));
+    }
+
+    // Catch a count mismatch here; the server would only report a confusing
+    // "must declare the scalar variable" error
+    let placeholders = find_placeholders(sql);
+    let expected = placeholders.iter().map(|(_, n)| *n).max().unwrap_or(0);
+    if expected != params.len() {
+        return Err(MssqlError {
+            message: format!(
+                "Query references {} parameter(s) but {} were supplied",
+                expected,
+                params.len()
+            ),
+            code: "PARAM_ERROR".to_string(),
+        });
+    }
+
+    let hints: Vec<Option<String>> = param_types
+        .unwrap_or_default()
+        .iter()
+        .map(|hint| hint.as_ref().map(|h| h.trim().to_lowercase()).filter(|h| !h.is_empty()))
+        .collect();
+    let hint = |index: usize| hints.get(index).and_then(|h| h.as_deref());
+
+    // tiberius declares every string parameter as NVARCHAR, so VARCHAR is
+    // achieved by casting the parameter where it's used
+    let mut sql = sql.to_string();
+    for (range, n) in placeholders.iter().rev() {
+        if matches!(hint(n - 1), Some("varchar") | Some("char")) {
+            sql.replace_range(range.clone(), &format!("CAST(@P{} AS varchar(max))", n));
+        }
+    }
+
+    let mut query = Query::new(sql);
+    for (index, value) in params.iter().enumerate() {
+        bind_param(&mut query, index, value, hint(index))?;
+    }
+    Ok(query)
+}
+
+/// Bind one JSON value, converting it to the hinted SQL type if given.
+/// Binary values are `{"$blob": "<base64>"}` objects.
+fn bind_param(
+    query: &mut Query<'static>,
+    index: usize,
+    value: &serde_json::Value,
+    hint: Option<&str>,
+) -> Result<(), MssqlError> {
+    use serde_json::Value as Json;
+
+    let invalid = |expected: &str| MssqlError {
+        message: format!("Parameter @P{}: expected {}, got {}", index + 1, expected, value),
+        code: "PARAM_ERROR".to_string(),
+    };
+    let text = match value {
+        Json::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let integer = value.as_i64().or_else(|| text.trim().parse::<i64>().ok());
+    let float = value.as_f64().or_else(|| text.trim().parse::<f64>().ok());
+    let blob = || decode_blob(value);
+
+    if value.is_null() {
+        // Untyped NULLs convert implicitly to everything except binary types
+        match hint {
+            Some("varbinary") | Some("binary") => query.bind(Option::<Vec<u8>>::None),
+            _ => query.bind(Option::<String>::None),
+        }
+        return Ok(());
+    }
+
+    match hint {
+        None => match value {
+            Json::Bool(b) => query.bind(*b),
+            Json::Number(n) => match n.as_i64() {
+                Some(v) => query.bind(v),
+                None => query.bind(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Json::Object(obj) if obj.contains_key("$blob") => {
+                query.bind(blob().ok_or_else(|| invalid("base64 $blob"))?)
+            }
+            _ => query.bind(text),
+        },
+        Some("nvarchar") | Some("nchar") | Some("varchar") | Some("char") => query.bind(text),
+        Some("bigint") => query.bind(integer.ok_or_else(|| invalid("bigint"))?),
+        Some("int") => {
+            query.bind(integer.and_then(|v| i32::try_from(v).ok()).ok_or_else(|| invalid("int"))?)
+        }
+        Some("smallint") => {
+            query.bind(integer.and_then(|v| i16::try_from(v).ok()).ok_or_else(|| invalid("smallint"))?)
+        }
+        Some("tinyint") => {
+            query.bind(integer.and_then(|v| u8::try_from(v).ok()).ok_or_else(|| invalid("tinyint"))?)
+        }
+        Some("bit") => query.bind(parse_bit(value).ok_or_else(|| invalid("bit"))?),
+        Some("float") => query.bind(float.ok_or_else(|| invalid("float"))?),
+        Some("real") => query.bind(float.ok_or_else(|| invalid("real"))? as f32),
+        Some("decimal") | Some("numeric") => {
+            query.bind(parse_numeric(&text).ok_or_else(|| invalid("decimal"))?)
+        }
+        Some("uniqueidentifier") => {
+            query.bind(Uuid::parse_str(text.trim()).map_err(|_| invalid("uniqueidentifier"))?)
+        }
+        Some("date") => query.bind(
+            NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").map_err(|_| invalid("date (YYYY-MM-DD)"))?,
+        ),
+        Some("time") => query.bind(
+            NaiveTime::parse_from_str(text.trim(), "%H:%M:%S%.f").map_err(|_| invalid("time (HH:MM:SS)"))?,
+        ),
+        Some("datetime2") | Some("datetime") | Some("smalldatetime") => {
+            query.bind(parse_datetime(&text).ok_or_else(|| invalid("ISO datetime"))?)
+        }
+        Some("datetimeoffset") => query.bind(
+            DateTime::parse_from_rfc3339(text.trim()).map_err(|_| invalid("RFC 3339 datetime with offset"))?,
+        ),
+        Some("varbinary") | Some("binary") => query.bind(blob().ok_or_else(|| invalid("base64 binary"))?),
+        Some(other) => {
+            return Err(MssqlError {
+                message: format!("Parameter @P{}: unsupported type hint '{}'", index + 1, other),
+                code: "PARAM_ERROR".to_string(),
+            })
+        }
+    }
+    Ok(())
+}
+
+/// A `{"$blob": "<base64>"}` object or a bare base64 string
+fn decode_blob(value: &serde_json::Value) -> Option<Vec<u8>> {
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+    let encoded = match value {
+        serde_json::Value::Object(obj) if obj.len() == 1 => obj.get("$blob").and_then(|v| v.as_str()),
+        serde_json::Value::String(s) => Some(s.as_str()),
+        _ => None,
+    };
+    encoded.and_then(|encoded| STANDARD.decode(encoded).ok())
+}
+
+fn parse_bit(value: &serde_json::Value) -> Option<bool> {
+    match value {
+        serde_json::Value::Bool(b) => Some(*b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(1) => Some(true),
+            Some(0) => Some(false),
+            _ => None,
+        },
+        serde_json::Value::String(s) => match s.trim().to_lowercase().as_str() {
+            "1" | "true" => Some(true),
+            "0" | "false" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// ISO 8601 without an offset; a space is accepted in place of the `T`
+fn parse_datetime(text: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(&text.trim().replacen(' ', "T", 1), "%Y-%m-%dT%H:%M:%S%.f").ok()
+}
+
+/// Parse a decimal string such as "-123.4500" without going through f64
+fn parse_numeric(text: &str) -> Option<Numeric> {
+    let text = text.trim();
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if (int_part.is_empty() && frac_part.is_empty())
+        || !int_part.chars().chain(frac_part.chars()).all(|c| c.is_ascii_digit())
+        || frac_part.len() >= 38
+    {
+        return None;
+    }
+    let value: i128 = format!("{}{}", int_part, frac_part).parse().ok()?;
+    Some(Numeric::new_with_scale(
+        if negative { -value } else { value },
+        frac_part.len() as u8,
+    ))
+}
+
+/// Find `@P<n>` placeholders outside string literals, quoted identifiers and
+/// comments, returning each one's byte range and parameter number
+fn find_placeholders(sql: &str) -> Vec<(std::ops::Range<usize>, usize)> {
+    let bytes = sql.as_bytes();
+    let is_word = |b: u8| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'@' | b'#' | b'$');
+    let mut placeholders = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"' | b'[') => {
+                let close = if quote == b'[' { b']' } else { quote };
+                i += 1;
+                while i < bytes.len() {
+                    if bytes[i] == close {
+                        // A doubled closing character is an escaped one
+                        if bytes.get(i + 1) == Some(&close) {
+                            i += 2;
+                            continue;
+                        }
+                        break;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                // Block comments nest in T-SQL
+                let mut depth = 0;
+                while i < bytes.len() {
+                    if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+                        depth += 1;
+                        i += 2;
+                    } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/') {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+            b'@' if (i == 0 || !is_word(bytes[i - 1]))
+                && matches!(bytes.get(i + 1), Some(b'P') | Some(b'p')) =>
+            {
+                let digits_start = i + 2;
+                let mut end = digits_start;
+                while end < bytes.len() && bytes[end].is_ascii_digit() {
+                    end += 1;
+                }
+                let number = sql[digits_start..end].parse::<usize>().ok().filter(|n| *n > 0);
+                match number {
+                    Some(n) if end == bytes.len() || !is_word(bytes[end]) => {
+                        placeholders.push((i..end, n));
+                        i = end;
+                    }
+                    _ => i += 1,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    placeholders
+}
+
+/// Open a client, returning it with the host and address it connected to
+async fn open_client(config: &MssqlConfig) -> Result<(MssqlClient, String), MssqlError> {
+    // Accept both `host\INSTANCE` and an explicit instance_name
+    let (host, instance) = match config.host.split_once('\\') {
+        Some((host, instance)) => (host, Some(instance)),
+        None => (config.host.as_str(), None),
+    };
+    let instance = config
+        .instance_name
+        .as_deref()
+        .or(instance)
+        .filter(|name| !name.is_empty());
+    let connect_timeout = config.connect_timeout_secs.unwrap_or(30);
+    // One deadline for every stage, so a server that accepts the connection
+    // and then goes quiet can't hang the connect
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(connect_timeout);
+    let timed_out = |code: &str, stage: &str| MssqlError {
+        message: format!("Connecting timed out after {} seconds {}", connect_timeout, stage),
+        code: code.to_string(),
+    };
+    let parallel = config.multi_subnet_failover;
+
+    let dial = async {
+        let port = match instance {
+            Some(instance) => resolve_instance_port(host, instance).await?,
+            None => config.port,
+        };
+        connect_tcp(&format!("{}:{}", host, port), deadline, parallel).await
+    };
+    let partner = config.failover_partner.as_deref().map(str::trim).filter(|p| !p.is_empty());
+    let (tcp_compat, peer, host) = match (dial.await, partner) {
+        (Ok((tcp, peer)), _) => (tcp, peer, host),
+        // Only fall back when the server can't be reached; a login failure
+        // would fail on the partner too
+        (Err(e), Some(partner)) if is_unreachable(&e) => {
+            let (partner_host, tcp, peer) = connect_partner(partner, config.port, deadline, parallel)
+                .await
+                .map_err(|partner_error| MssqlError {
+                    message: format!(
+                        "{} Failover partner {} failed too: {}",
+                        e.message, partner, partner_error.message
+                    ),
+                    code: partner_error.code,
+                })?;
+            (tcp, peer, partner_host)
+        }
+        (Err(e), _) => return Err(e),
+    };
+    let endpoint = format!("{} ({})", host, peer);
+
+    let mut tiberius_config = Config::new();
+
+    // tiberius validates the certificate against this name; we open the TCP
+    // connection ourselves, so it doesn't have to be the address we dial
+    let tls_host = config
+        .hostname_override
+        .as_deref()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(host);
+    tiberius_config.host(tls_host);
+    tiberius_config.port(peer.port());
+    tiberius_config.database(&config.database);
+    tiberius_config.authentication(auth_method(config)?);
+    tiberius_config.readonly(config.read_only_intent);
+    // Shown as program_name in sys.dm_exec_sessions
+    match config.application_name.as_deref().filter(|n| !n.is_empty()) {
+        Some(application_name) => tiberius_config.application_name(application_name),
+        None => tiberius_config.application_name(format!("Seaquel {}", env!("CARGO_PKG_VERSION"))),
+    }
+    // host_name stays empty: tiberius 0.12 builds the LOGIN7 packet itself and
+    // has no setting for its host name, so there is no workstation_id until
+    // it does
+
+    let client = match config.encrypt.unwrap_or_default() {
+        MssqlEncrypt::Off => {
+            // Plain TCP connection for localhost/development servers without TLS
+            tiberius_config.encryption(tiberius::EncryptionLevel::NotSupported);
+            let inner_client = tokio::time::timeout_at(deadline, Client::connect(tiberius_config, tcp_compat))
+                .await
+                .map_err(|_| timed_out("LOGIN_TIMEOUT", "waiting for the server to log in"))?
+                .map_err(login_error)?;
+
+            MssqlClient::Plain(inner_client)
+        }
+        MssqlEncrypt::On => {
+            tiberius_config.encryption(tiberius::EncryptionLevel::Required);
+            if config.trust_cert {
+                tiberius_config.trust_cert();
+            } else if let Some(path) = config.ca_cert_path.as_deref().filter(|p| !p.is_empty()) {
+                // tiberius picks the format from the extension: .pem, .crt or .der
+                tiberius_config.trust_cert_ca(path);
+            }
+
+            // tiberius does pre-login, the TLS handshake and login in one go,
+            // so a timeout here can't be pinned to one of them
+            let inner_client = tokio::time::timeout_at(deadline, Client::connect(tiberius_config, tcp_compat))
+                .await
+                .map_err(|_| timed_out("LOGIN_TIMEOUT", "during pre-login, the TLS handshake or login"))?
+                .map_err(|e| match e {
+                    tiberius::error::Error::Tls(message) if !config.trust_cert => MssqlError {
+                        message: format!(
+                            "The server certificate was rejected: {}. \
+                             Add its CA, set a hostname override, or choose to trust the certificate.",
+                            message
+                        ),
+                        code: "TLS_CERT_ERROR".to_string(),
+                    },
+                    e => login_error(e),
+                })?;
+
+            MssqlClient::Plain(inner_client)
+        }
+        MssqlEncrypt::Strict => {
+            // TDS 8.0: the TLS handshake comes first, and tiberius is told not
+            // to encrypt because the stream already is
+            tiberius_config.encryption(tiberius::EncryptionLevel::NotSupported);
+            let handshake = strict_tls_connector(config)?.connect(tls_host, tcp_compat);
+            let tls_stream = match tokio::time::timeout_at(deadline, handshake).await {
+                Err(_) => return Err(timed_out("TLS_TIMEOUT", "during the TLS handshake")),
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    // Handshake again without validation; if that works, the
+                    // certificate was the problem and the user can decide on it
+                    return Err(match server_certificate_subject(peer, tls_host, connect_timeout).await {
+                        Some(subject) => MssqlError {
+                            message: format!(
+                                "The server certificate was rejected: {}. Certificate subject: {}. \
+                                 Add its CA or set a hostname override.",
+                                e, subject
+                            ),
+                            code: "TLS_CERT_ERROR".to_string(),
+                        },
+                        None => tls_error(e),
+                    });
+                }
+            };
+
+            // A server that didn't pick tds/8.0 isn't speaking strict TDS, so
+            // don't carry on as if the session were protected end to end
+            let alpn = tls_stream.negotiated_alpn().ok().flatten();
+            if alpn.as_deref() != Some(b"tds/8.0".as_slice()) {
+                return Err(MssqlError {
+                    message: "The server did not negotiate TDS 8.0 strict encryption. \
+                              It may not support Encrypt=Strict; use 'on' instead."
+                        .to_string(),
+                    code: "STRICT_ENCRYPTION_UNSUPPORTED".to_string(),
+                });
+            }
+
+            let inner_client = tokio::time::timeout_at(deadline, Client::connect(tiberius_config, tls_stream))
+                .await
+                .map_err(|_| timed_out("LOGIN_TIMEOUT", "waiting for the server to log in"))?
+                .map_err(login_error)?;
+
+            MssqlClient::Tls(inner_client)
+        }
+    };
+
+    Ok((client, endpoint))
+}
+
+/// Whether a connect error means nothing answered, as opposed to a server
+/// that answered and refused the login
+fn is_unreachable(e: &MssqlError) -> bool {
+    matches!(
+        e.code.as_str(),
+        "DNS_ERROR"
+            | "CONNECTION_REFUSED"
+            | "CONNECTION_ERROR"
+            | "TCP_TIMEOUT"
+            | "BROWSER_UNREACHABLE"
+            | "INSTANCE_NOT_FOUND"
+    )
+}
+
+/// Dial a failover partner given as `host[\instance][,port]`
+async fn connect_partner<'a>(
+    partner: &'a str,
+    default_port: u16,
+    deadline: tokio::time::Instant,
+    parallel: bool,
+) -> Result<(&'a str, Compat<TcpStream>, std::net::SocketAddr), MssqlError> {
+    let (server, port) = match partner.split_once(',') {
+        Some((server, port)) => (server.trim(), Some(parse_port(port)?)),
+        None => (partner, None),
+    };
+    let (host, port) = match server.split_once('\\') {
+        Some((host, instance)) if port.is_none() => (host, resolve_instance_port(host, instance).await?),
+        Some((host, _)) => (host, port.unwrap_or(default_port)),
+        None => (server, port.unwrap_or(default_port)),
+    };
+    let (tcp, peer) = connect_tcp(&format!("{}:{}", host, port), deadline, parallel).await?;
+    Ok((host, tcp, peer))
+}
+
+/// Connect to `addr`, returning the stream and the address that answered.
+/// With `parallel`, every resolved address is dialed at once.
+async fn connect_tcp(
+    addr: &str,
+    deadline: tokio::time::Instant,
+    parallel: bool,
+) -> Result<(Compat<TcpStream>, std::net::SocketAddr), MssqlError> {
+    // Connect with timeout
+    let connect = async {
+        // Resolve first so a bad host name is reported as such
+        let addrs: Vec<std::net::SocketAddr> = tokio::net::lookup_host(addr)
+            .await
+            .map_err(|e| MssqlError {
+                message: format!("Could not resolve {}: {}", addr, e),
+                code: "DNS_ERROR".to_string(),
+            })?
+            .collect();
+        if addrs.is_empty() {
+            return Err(MssqlError {
+                message: format!("Could not resolve {}", addr),
+                code: "DNS_ERROR".to_string(),
+            });
+        }
+
+        let connected = if parallel {
+            connect_first(addrs).await
+        } else {
+            TcpStream::connect(&addrs[..])
+                .await
+                .and_then(|tcp| tcp.peer_addr().map(|peer| (tcp, peer)))
+        };
+        connected.map_err(|e| match e.kind() {
+            std::io::ErrorKind::ConnectionRefused => MssqlError {
+                message: format!("Connection to {} was refused. Check the port and that SQL Server accepts TCP connections.", addr),
+                code: "CONNECTION_REFUSED".to_string(),
+            },
+            _ => MssqlError {
+                message: format!("Failed to connect: {}", e),
+                code: "CONNECTION_ERROR".to_string(),
+            },
+        })
+    };
+    let tcp = tokio::time::timeout_at(deadline, connect).await.map_err(|_| MssqlError {
+        message: format!("Timed out connecting to {}", addr),
+        code: "TCP_TIMEOUT".to_string(),
+    })??;
+
+    let (tcp, peer) = tcp;
+    tcp.set_nodelay(true).map_err(|e| MssqlError {
+        message: format!("Failed to set TCP nodelay: {}", e),
+        code: "TCP_ERROR".to_string(),
+    })?;
+
+    // Wrap TCP stream with compat for futures-io trait compatibility
+    Ok((tcp.compat(), peer))
+}
+
+/// Dial every address at once and keep the first connection made; the
+/// others are dropped. Fails with the last error if none connect.
+async fn connect_first(
+    addrs: Vec<std::net::SocketAddr>,
+) -> std::io::Result<(TcpStream, std::net::SocketAddr)> {
+    use futures_util::stream::{FuturesUnordered, StreamExt};
+
+    let mut attempts: FuturesUnordered<_> = addrs
+        .into_iter()
+        .map(|addr| async move { TcpStream::connect(addr).await.map(|tcp| (tcp, addr)) })
+        .collect();
+    let mut last_error = None;
+    while let Some(attempt) = attempts.next().await {
+        match attempt {
+            Ok(connected) => return Ok(connected),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses")))
+}
+
+/// TLS settings for strict encryption: TLS 1.2 or later, ALPN `tds/8.0` and
+/// a validated certificate
+fn strict_tls_connector(config: &MssqlConfig) -> Result<async_native_tls::TlsConnector, MssqlError> {
+    let mut connector = async_native_tls::TlsConnector::new()
+        .min_protocol_version(Some(async_native_tls::Protocol::Tlsv12))
+        .request_alpns(&["tds/8.0"])
+        .use_sni(true);
+
+    if let Some(path) = config.ca_cert_path.as_deref().filter(|p| !p.is_empty()) {
+        let bytes = std::fs::read(path).map_err(|e| MssqlError {
+            message: format!("Failed to read CA certificate {}: {}", path, e),
+            code: "CA_CERT_ERROR".to_string(),
+        })?;
+        let certificate = async_native_tls::Certificate::from_pem(&bytes)
+            .or_else(|_| async_native_tls::Certificate::from_der(&bytes))
+            .map_err(|e| MssqlError {
+                message: format!("{} is not a PEM or DER certificate: {}", path, e),
+                code: "CA_CERT_ERROR".to_string(),
+            })?;
+        connector = connector.add_root_certificate(certificate);
+    }
+
+    Ok(connector)
+}
+
+fn tls_error(e: async_native_tls::Error) -> MssqlError {
+    MssqlError {
+        message: format!("TLS connection failed: {}. The server may not support strict encryption.", e),
+        code: "TLS_ERROR".to_string(),
+    }
+}
+
+/// Fetch the subject of the certificate a server presents, skipping
+/// validation. Only used to explain a rejected certificate.
+async fn server_certificate_subject(
+    addr: std::net::SocketAddr,
+    tls_host: &str,
+    timeout_secs: u64,
+) -> Option<String> {
+    use x509_cert::der::Decode;
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(timeout_secs);
+    let (tcp, _) = connect_tcp(&addr.to_string(), deadline, false).await.ok()?;
+    let handshake = async_native_tls::TlsConnector::new()
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .connect(tls_host, tcp);
+    let stream = tokio::time::timeout_at(deadline, handshake).await.ok()?.ok()?;
+    let der = stream.peer_certificate().ok()??.to_der().ok()?;
+    let certificate = x509_cert::Certificate::from_der(&der).ok()?;
+    Some(certificate.tbs_certificate.subject.to_string())
+}
+
+/// Open a client, waiting and retrying while an Azure SQL serverless database
+/// resumes from pause, which can take up to a minute
+async fn connect_with_backoff(config: &MssqlConfig) -> Result<(MssqlClient, String), MssqlError> {
+    let mut delay = std::time::Duration::from_secs(2);
+    for _ in 0..5 {
+        match open_client(config).await {
+            Err(e) if e.code == "DATABASE_UNAVAILABLE" => {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    open_client(config).await
+}
+
+/// Ask the SQL Browser service (UDP 1434) which TCP port a named instance
+/// is listening on
+async fn resolve_instance_port(host: &str, instance: &str) -> Result<u16, MssqlError> {
+    let unreachable = |detail: String| MssqlError {
+        message: format!(
+            "Could not resolve instance '{}' through SQL Browser on {}:1434 ({}). \
+             Check that the SQL Server Browser service is running, or enter the instance's port instead.",
+            instance, host, detail
+        ),
+        code: "BROWSER_UNREACHABLE".to_string(),
+    };
+
+    let addr = tokio::net::lookup_host((host, 1434))
+        .await
+        .map_err(|e| MssqlError {
+            message: format!("Failed to resolve {}: {}", host, e),
+            code: "CONNECTION_ERROR".to_string(),
+        })?
+        .next()
+        .ok_or_else(|| MssqlError {
+            message: format!("Failed to resolve {}", host),
+            code: "CONNECTION_ERROR".to_string(),
+        })?;
+    let local = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(local).await.map_err(|e| unreachable(e.to_string()))?;
+
+    // CLNT_UCAST_INST: 0x04 followed by the instance name
+    let request = [&[4u8][..], instance.as_bytes()].concat();
+    socket
+        .send_to(&request, addr)
+        .await
+        .map_err(|e| unreachable(e.to_string()))?;
+
+    let mut buf = vec![0u8; 4096];
+    let len = tokio::time::timeout(std::time::Duration::from_secs(2), socket.recv(&mut buf))
+        .await
+        .map_err(|_| unreachable("no response".to_string()))?
+        .map_err(|e| unreachable(e.to_string()))?;
+
+    // SVR_RESP: 0x05, a 2-byte length, then "ServerName;X;InstanceName;Y;...;tcp;1433;;"
+    if len < 3 || buf[0] != 5 {
+        return Err(unreachable("unexpected response".to_string()));
+    }
+    let reply = String::from_utf8_lossy(&buf[3..len]);
+    let fields: Vec<&str> = reply.split(';').collect();
+    fields
+        .iter()
+        .position(|field| field.eq_ignore_ascii_case("tcp"))
+        .and_then(|i| fields.get(i + 1))
+        .and_then(|port| port.parse().ok())
+        .ok_or_else(|| MssqlError {
+            message: format!("Instance '{}' on {} does not accept TCP connections", instance, host),
+            code: "INSTANCE_NOT_FOUND".to_string(),
+        })
+}
+
+#[tauri::command]
+pub async fn mssql_connect(
+    config: MssqlConfig,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<MssqlConnection, MssqlError> {
+    let session = Session::open(config).await?;
+    let session_id = session.spid;
+    let endpoint = session.endpoint.clone();
+    let connection_id = store_connection(&app, &manager, session).await;
+
+    Ok(MssqlConnection {
+        connection_id,
+        session_id,
+        endpoint,
+    })
+}
+
+/// Connect, log in and read the server version without keeping the
+/// connection. Errors carry a code per failing step (DNS_ERROR,
+/// CONNECTION_REFUSED, TLS_ERROR/TLS_CERT_ERROR, AUTH_FAILED,
+/// DATABASE_NOT_FOUND) so the dialog can point at the right field.
+#[tauri::command]
+pub async fn mssql_test_connection(config: MssqlConfig) -> Result<MssqlTestResult, MssqlError> {
+    let (mut client, _) = open_client(&config).await?;
+    let results = client
+        .query_results(Query::new(
+            "SELECT @@VERSION, CAST(SERVERPROPERTY('Edition') AS nvarchar(128)), DB_NAME()",
+        ))
+        .await
+        .map_err(|e| MssqlError {
+            message: format!("Query failed: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })?;
+    // The client is dropped here, closing the connection
+    let row = results.first().and_then(|result| result.rows.first());
+    let text = |idx: usize| {
+        row.and_then(|row| row.try_get::<&str, _>(idx).ok().flatten())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    Ok(MssqlTestResult {
+        version: text(0),
+        edition: text(1),
+        database: text(2),
+    })
+}
+
+/// Connect using an ADO.NET (`Server=...;Database=...`) or JDBC
+/// (`jdbc:sqlserver://...`) connection string
+#[tauri::command]
+pub async fn mssql_connect_with_string(
+    connection_string: String,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<MssqlConnectionStringResult, MssqlError> {
+    let (config, warnings) = parse_connection_string(&connection_string)?;
+    let session = Session::open(config.clone()).await?;
+    let session_id = session.spid;
+    let endpoint = session.endpoint.clone();
+    let connection_id = store_connection(&app, &manager, session).await;
+
+    Ok(MssqlConnectionStringResult {
+        connection_id,
+        session_id,
+        endpoint,
+        config: MssqlConfig {
+            password: String::new(),
+            ..config
+        },
+        warnings,
+    })
+}
+
+async fn store_connection(app: &tauri::AppHandle, manager: &MssqlConnectionManager, session: Session) -> String {
+    // Generate connection ID
+    let connection_id = {
+        let mut next_id = manager.next_id.lock().await;
+        let id = format!("mssql-{}", *next_id);
+        *next_id += 1;
+        id
+    };
+
+    let config = &session.config;
+    let server = match config.instance_name.as_deref().filter(|name| !name.is_empty()) {
+        Some(instance) => format!("{}\\{}", config.host, instance),
+        None => format!("{}:{}", config.host, config.port),
+    };
+    let endpoint = session.endpoint.clone();
+    let keep_alive_secs = config.keep_alive_secs.unwrap_or(60);
+    let max_sessions = 1 + config.extra_sessions.unwrap_or(1);
+    let config = config.clone();
+
+    // Store connection
+    {
+        let mut connections = manager.connections.lock().await;
+        connections.insert(
+            connection_id.clone(),
+            ConnectionHandle {
+                sessions: vec![PooledSession::new(session)],
+                max_sessions,
+                opening: 0,
+                config,
+                server,
+                endpoint,
+                last_used: Instant::now(),
+            },
+        );
+    }
+
+    if keep_alive_secs > 0 {
+        tokio::spawn(keep_alive(app.clone(), connection_id.clone(), keep_alive_secs));
+    }
+
+    connection_id
+}
+
+/// Ping the connection whenever it has been idle for `interval_secs`, so
+/// firewalls don't drop it and a dead one is noticed before the next query.
+/// Emits `mssql://connection-lost` when a ping fails. Ends once the
+/// connection is closed.
+async fn keep_alive(app: tauri::AppHandle, connection_id: String, interval_secs: u64) {
+    let interval = Duration::from_secs(interval_secs);
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let manager = app.state::<MssqlConnectionManager>();
+        let (sessions, idle) = match manager.connections.lock().await.get(&connection_id) {
+            Some(handle) => (
+                handle.sessions.iter().map(|pooled| Arc::clone(&pooled.session)).collect::<Vec<_>>(),
+                handle.last_used.elapsed(),
+            ),
+            None => return,
+        };
+        if idle < interval {
+            continue;
+        }
+
+        let mut lost = false;
+        for session in sessions {
+            // Locked means a command or cursor is using it, so it isn't idle
+            let Ok(mut session) = session.try_lock() else {
+                continue;
+            };
+            let ping = session.client.execute(Query::new("SELECT 1"));
+            if let Some(Ok(_)) = within(Some(10), ping).await {
+                session.stale = false;
+            } else if !session.stale {
+                session.stale = true;
+                lost = true;
+            }
+        }
+        if lost {
+            let _ = app.emit(
+                "mssql://connection-lost",
+                MssqlConnectionEvent {
+                    connection_id: connection_id.clone(),
+                },
+            );
+        }
+    }
+}
+
+/// Every open connection with its health and idle time
+#[tauri::command]
+pub async fn mssql_list_connections(
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<Vec<MssqlConnectionInfo>, MssqlError> {
+    let connections = manager.connections.lock().await;
+    let mut list: Vec<MssqlConnectionInfo> = connections
+        .iter()
+        .map(|(connection_id, handle)| {
+            // A busy session is in use and was fine when the command started
+            let mut stale = false;
+            let mut busy = false;
+            let mut endpoint = None;
+            for pooled in &handle.sessions {
+                match pooled.session.try_lock() {
+                    Ok(session) => {
+                        stale |= session.stale;
+                        endpoint.get_or_insert_with(|| session.endpoint.clone());
+                    }
+                    Err(_) => busy = true,
+                }
+            }
+            let idle_secs = if busy { 0 } else { handle.last_used.elapsed().as_secs() };
+            MssqlConnectionInfo {
+                connection_id: connection_id.clone(),
+                server: handle.server.clone(),
+                endpoint: endpoint.unwrap_or_else(|| handle.endpoint.clone()),
+                database: handle.config.database.clone(),
+                state: if stale { "stale" } else { "healthy" }.to_string(),
+                idle_secs,
+            }
+        })
+        .collect();
+    list.sort_by(|a, b| a.connection_id.cmp(&b.connection_id));
+    Ok(list)
+}
+
+/// Check out a session for one command. A free session is used if there is
+/// one, else another is opened while the pool has room, else the command
+/// waits for the first. While a transaction is open every command goes to
+/// its session.
+async fn get_session(
+    manager: &MssqlConnectionManager,
+    connection_id: &str,
+) -> Result<OwnedMutexGuard<Session>, MssqlError> {
+    let (free, first, open_config, database) = {
+        let mut connections = manager.connections.lock().await;
+        let handle = connections.get_mut(connection_id).ok_or(MssqlError {
+            message: format!("Connection not found: {}", connection_id),
+            code: "CONNECTION_NOT_FOUND".to_string(),
+        })?;
+        handle.last_used = Instant::now();
+        let database = handle.config.database.clone();
+
+        let pinned = handle
+            .sessions
+            .iter()
+            .find(|pooled| pooled.in_transaction.load(Ordering::SeqCst));
+        if let Some(pooled) = pinned {
+            (None, Arc::clone(&pooled.session), None, database)
+        } else {
+            let free = handle
+                .sessions
+                .iter()
+                .find_map(|pooled| Arc::clone(&pooled.session).try_lock_owned().ok());
+            let open_config = if free.is_none() && handle.sessions.len() + handle.opening < handle.max_sessions {
+                handle.opening += 1;
+                Some(handle.config.clone())
+            } else {
+                None
+            };
+            (free, Arc::clone(&handle.sessions[0].session), open_config, database)
+        }
+    };
+
+    let mut session = match (free, open_config) {
+        (Some(session), _) => session,
+        (None, Some(config)) => match open_pooled_session(manager, connection_id, config).await {
+            Some(session) => session,
+            None => first.lock_owned().await,
+        },
+        (None, None) => first.lock_owned().await,
+    };
+
+    // Replace a session whose keep-alive ping failed before anything is sent
+    // on it, which is the one time a command is known not to have run. One
+    // in a transaction is left to fail, as its transaction is already gone.
+    // A broken one is replaced regardless, as nothing can run on it.
+    if session.broken || (session.stale && !session.in_transaction.load(Ordering::SeqCst)) {
+        if let Ok(fresh) = Session::open(session.config.clone()).await {
+            session.replace(fresh);
+        }
+    }
+
+    // mssql_use_database only switches the session it runs on
+    if !session.config.database.eq_ignore_ascii_case(&database) {
+        let sql = format!("USE {}", quote_identifier(&database));
+        match session.client.execute(Query::new(sql)).await {
+            Ok(_) => session.config.database = database,
+            // The command will reconnect, and should land in the new database
+            Err(e) if is_connection_error(&e) => session.config.database = database,
+            Err(e) => {
+                return Err(MssqlError {
+                    message: format!("Failed to switch to database {}: {}", database, e),
+                    code: "USE_DATABASE_ERROR".to_string(),
+                })
+            }
+        }
+    }
+    Ok(session)
+}
+
+/// Open another session for a connection and add it to the pool. None if it
+/// can't be opened, in which case the caller waits for an existing one.
+async fn open_pooled_session(
+    manager: &MssqlConnectionManager,
+    connection_id: &str,
+    config: MssqlConfig,
+) -> Option<OwnedMutexGuard<Session>> {
+    let opened = Session::open(config).await;
+    let mut connections = manager.connections.lock().await;
+    let mut handle = connections.get_mut(connection_id);
+    if let Some(handle) = handle.as_mut() {
+        handle.opening -= 1;
+    }
+    let pooled = PooledSession::new(opened.ok()?);
+    let session = Arc::clone(&pooled.session).lock_owned().await;
+    // Disconnected meanwhile: the session serves this command and is dropped
+    if let Some(handle) = handle {
+        handle.sessions.push(pooled);
+    }
+    Some(session)
+}
+
+/// Parse an ADO.NET or JDBC connection string into a config, returning the
+/// keys that were not understood alongside it
+fn parse_connection_string(connection_string: &str) -> Result<(MssqlConfig, Vec<String>), MssqlError> {
+    let mut config = MssqlConfig {
+        host: String::new(),
+        port: 1433,
+        instance_name: None,
+        database: String::new(),
+        auth_method: None,
+        domain: None,
+        username: String::new(),
+        password: String::new(),
+        encrypt: None,
+        trust_cert: false,
+        ca_cert_path: None,
+        hostname_override: None,
+        application_name: None,
+        connect_timeout_secs: None,
+        query_timeout_secs: None,
+        read_only_intent: false,
+        read_only: false,
+        keep_alive_secs: None,
+        binary_preview_bytes: None,
+        extra_sessions: None,
+        multi_subnet_failover: false,
+        failover_partner: None,
+    };
+    let mut warnings = Vec::new();
+
+    let trimmed = connection_string.trim();
+    let pairs = match strip_prefix_ignore_case(trimmed, "jdbc:sqlserver://") {
+        Some(rest) => {
+            // jdbc:sqlserver://host[\instance][:port][;key=value...]
+            let (server, properties) = rest.split_once(';').unwrap_or((rest, ""));
+            let (server, port) = match server.rsplit_once(':') {
+                Some((server, port)) => (server, Some(port)),
+                None => (server, None),
+            };
+            apply_server(&mut config, server, port)?;
+            split_connection_string(properties)
+        }
+        None => split_connection_string(trimmed),
+    };
+
+    for (key, value) in pairs {
+        let normalized: String = key.to_lowercase().chars().filter(|c| !c.is_whitespace()).collect();
+        match normalized.as_str() {
+            "server" | "datasource" | "address" | "addr" | "networkaddress" | "servername" => {
+                // ADO.NET puts the port after a comma: tcp:host,1433
+                let server = strip_prefix_ignore_case(&value, "tcp:").unwrap_or(&value);
+                let (server, port) = match server.split_once(',') {
+                    Some((server, port)) => (server, Some(port)),
+                    None => (server, None),
+                };
+                apply_server(&mut config, server, port)?;
+            }
+            "port" | "portnumber" => config.port = parse_port(&value)?,
+            "instancename" => config.instance_name = Some(value),
+            "database" | "initialcatalog" | "databasename" => config.database = value,
+            "userid" | "uid" | "user" | "username" => config.username = value,
+            "password" | "pwd" => config.password = value,
+            "encrypt" => {
+                config.encrypt = Some(match value.trim().to_lowercase().as_str() {
+                    "false" | "no" | "optional" => MssqlEncrypt::Off,
+                    "strict" => MssqlEncrypt::Strict,
+                    _ => MssqlEncrypt::On,
+                });
+            }
+            "trustservercertificate" => config.trust_cert = parse_bool(&key, &value)?,
+            "hostnameincertificate" => config.hostname_override = Some(value),
+            "servercertificate" | "trustservercertificateca" => config.ca_cert_path = Some(value),
+            "integratedsecurity" | "trusted_connection" => {
+                if value.eq_ignore_ascii_case("sspi") || parse_bool(&key, &value)? {
+                    config.auth_method = Some("integrated".to_string());
+                }
+            }
+            "applicationname" | "app" => config.application_name = Some(value),
+            "multisubnetfailover" => config.multi_subnet_failover = parse_bool(&key, &value)?,
+            "failoverpartner" => config.failover_partner = Some(value),
+            "applicationintent" => config.read_only_intent = value.trim().eq_ignore_ascii_case("readonly"),
+            // Not supported yet; see open_client
+            "workstationid" | "wsid" => warnings.push(format!(
+                "Ignored '{}': the driver can't send a workstation ID, so host_name stays empty",
+                key
+            )),
+            "connecttimeout" | "connectiontimeout" | "timeout" | "logintimeout" => {
+                config.connect_timeout_secs = Some(value.trim().parse().map_err(|_| MssqlError {
+                    message: format!("Invalid {}: {}", key, value),
+                    code: "INVALID_CONNECTION_STRING".to_string(),
+                })?);
+            }
+            "commandtimeout" => {
+                config.query_timeout_secs = Some(value.trim().parse().map_err(|_| MssqlError {
+                    message: format!("Invalid {}: {}", key, value),
+                    code: "INVALID_CONNECTION_STRING".to_string(),
+                })?);
+            }
+            _ => warnings.push(format!("Ignored unsupported setting '{}'", key)),
+        }
+    }
+
+    if config.host.is_empty() {
+        return Err(MssqlError {
+            message: "Connection string does not specify a server".to_string(),
+            code: "INVALID_CONNECTION_STRING".to_string(),
+        });
+    }
+    Ok((config, warnings))
+}
+
+/// Set host, instance and port from a server value such as `host\INSTANCE`
+fn apply_server(config: &mut MssqlConfig, server: &str, port: Option<&str>) -> Result<(), MssqlError> {
+    let (host, instance) = match server.trim().split_once('\\') {
+        Some((host, instance)) => (host, Some(instance)),
+        None => (server.trim(), None),
+    };
+    config.host = match host {
+        "." | "(local)" | "(localdb)" => "localhost".to_string(),
+        host => host.to_string(),
+    };
+    if let Some(instance) = instance.filter(|i| !i.is_empty()) {
+        config.instance_name = Some(instance.to_string());
+    }
+    if let Some(port) = port {
+        config.port = parse_port(port)?;
+    }
+    Ok(())
+}
+
+fn parse_port(value: &str) -> Result<u16, MssqlError> {
+    value.trim().parse().map_err(|_| MssqlError {
+        message: format!("Invalid port: {}", value),
+        code: "INVALID_CONNECTION_STRING".to_string(),
+    })
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, MssqlError> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" => Ok(true),
+        "false" | "no" => Ok(false),
+        _ => Err(MssqlError {
+            message: format!("Invalid value for {}: {}", key, value),
+            code: "INVALID_CONNECTION_STRING".to_string(),
+        }),
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = value.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &value[prefix.len()..])
+}
+
+/// Split `key=value;key=value` pairs. Values may be wrapped in single or
+/// double quotes (doubled to escape) or braces, so they can contain `;`.
+fn split_connection_string(input: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    loop {
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        let key = key.trim().trim_start_matches(';').trim().to_string();
+        if key.is_empty() {
+            break;
+        }
+
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut value = String::new();
+        match chars.peek().copied() {
+            Some(quote @ ('"' | '\'')) => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == quote {
+                        if chars.peek() == Some(&quote) {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    value.push(c);
+                }
+                // Skip anything up to the separator
+                for c in chars.by_ref() {
+                    if c == ';' {
+                        break;
+                    }
+                }
+            }
+            Some('{') => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == '}' {
+                        if chars.peek() == Some(&'}') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    value.push(c);
+                }
+                for c in chars.by_ref() {
+                    if c == ';' {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                value = chars.by_ref().take_while(|c| *c != ';').collect();
+                value = value.trim().to_string();
+            }
+        }
+        pairs.push((key, value));
+    }
+    pairs
+}
+
+/// Close every session of the connection. One still running a command
+/// closes when that command finishes.
+#[tauri::command]
+pub async fn mssql_disconnect(
+    connection_id: String,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<(), MssqlError> {
+    let mut connections = manager.connections.lock().await;
+
+    if connections.remove(&connection_id).is_some() {
+        // Dropping a cursor's sender ends its task, which releases the client
+        manager
+            .cursors
+            .lock()
+            .await
+            .retain(|_, cursor| cursor.connection_id != connection_id);
+        Ok(())
+    } else {
+        Err(MssqlError {
+            message: format!("Connection not found: {}", connection_id),
+            code: "CONNECTION_NOT_FOUND".to_string(),
+        })
+    }
+}
+
+#[tauri::command]
+pub async fn mssql_query(
+    connection_id: String,
+    sql: String,
+    rows_as_arrays: Option<bool>,
+    params: Option<Vec<serde_json::Value>>,
+    param_types: Option<Vec<Option<String>>>,
+    timeout_secs: Option<u64>,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<MssqlQueryResult, MssqlError> {
+    let query = || build_query(&sql, params.as_deref(), param_types.as_deref());
+    let mut session = get_session(&manager, &connection_id).await?;
+    session.check_read_only(Some(&sql))?;
+    let timeout_secs = session.query_timeout(timeout_secs);
+    let binary_limit = session.binary_limit();
+    let messages = MessageCollector::default();
+    // Original type names of the columns query_as_text had to convert
+    let mut converted_types: Vec<Option<String>> = Vec::new();
+
+    let run = async {
+        match session.client.query_results(query()?).await {
+            // A lone SELECT without parameters can be re-run with the columns
+            // tiberius can't read converted on the server
+            Err(e)
+                if is_unreadable_types(&e)
+                    && params.as_ref().is_none_or(Vec::is_empty)
+                    && !session.in_transaction.load(Ordering::SeqCst)
+                    && is_single_select(&sql) =>
+            {
+                session.reconnect(&app, &connection_id, e).await?;
+                messages.clear();
+                match session.query_as_text(&sql).await {
+                    Ok(Some((results, types))) => {
+                        converted_types = types;
+                        Ok(results)
+                    }
+                    Ok(None) => return Err(unreadable_types_error(false)),
+                    Err(e) if is_unreadable_types(&e) => {
+                        session.reconnect(&app, &connection_id, e).await?;
+                        return Err(unreadable_types_error(false));
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) if is_connection_error(&e) => {
+                session.recover(&app, &connection_id, e, is_read_only_sql(&sql)).await?;
+                // Drop what the login said; only the query's messages matter
+                messages.clear();
+                session.client.query_results(query()?).await
+            }
+            result => result,
+        }
+        .map_err(|e| MssqlError {
+            message: format!("Query failed: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })
+    };
+    let results = match within(timeout_secs, run.with_subscriber(messages.clone())).await {
+        Some(results) => {
+            session.refresh_transaction().await;
+            results?
+        }
+        None => return Err(session.cancel_timed_out(timeout_secs.unwrap_or_default()).await),
+    };
+
+    // Only the first result set is returned; see mssql_query_multi for the rest
+    let ResultSet { columns, rows } = results.into_iter().next().unwrap_or(ResultSet {
+        columns: vec![],
+        rows: vec![],
+    });
+
+    // Convert rows to JSON. Arrays keep duplicate and unnamed columns, which
+    // collapse into a single key when rows are objects.
+    let convert = if rows_as_arrays.unwrap_or(false) {
+        row_to_array
+    } else {
+        row_to_json
+    };
+    let json_rows: Vec<serde_json::Value> = rows.iter().map(|row| convert(row, binary_limit)).collect();
+
+    Ok(MssqlQueryResult {
+        columns: columns.iter().map(|c| c.name().to_string()).collect(),
+        column_types: columns
+            .iter()
+            .enumerate()
+            .map(|(idx, c)| match converted_types.get(idx) {
+                Some(Some(type_name)) => type_name.clone(),
+                _ => column_type_name(c.column_type()).to_string(),
+            })
+            .collect(),
+        rows: json_rows,
+        rows_affected: 0,
+        messages: messages.take(),
+    })
+}
+
+#[tauri::command]
+pub async fn mssql_execute(
+    connection_id: String,
+    sql: String,
+    params: Option<Vec<serde_json::Value>>,
+    param_types: Option<Vec<Option<String>>>,
+    timeout_secs: Option<u64>,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<MssqlQueryResult, MssqlError> {
+    let query = || build_query(&sql, params.as_deref(), param_types.as_deref());
+    let mut session = get_session(&manager, &connection_id).await?;
+    session.check_read_only(None)?;
+    let timeout_secs = session.query_timeout(timeout_secs);
+    let messages = MessageCollector::default();
+
+    let run = async {
+        match session.client.execute(query()?).await {
+            Err(e) if is_connection_error(&e) => {
+                session.recover(&app, &connection_id, e, false).await?;
+                messages.clear();
+                session.client.execute(query()?).await
+            }
+            result => result,
+        }
+        .map_err(|e| MssqlError {
+            message: format!("Execute failed: {}", e),
+            code: "EXECUTE_ERROR".to_string(),
+        })
+    };
+    let result = match within(timeout_secs, run.with_subscriber(messages.clone())).await {
+        Some(result) => {
+            session.refresh_transaction().await;
+            result?
+        }
+        None => return Err(session.cancel_timed_out(timeout_secs.unwrap_or_default()).await),
+    };
+
+    Ok(MssqlQueryResult {
+        columns: vec![],
+        column_types: vec![],
+        rows: vec![],
+        rows_affected: result.rows_affected().iter().sum(),
+        messages: messages.take(),
+    })
+}
+
+/// Run a batch and return every result set it produces, e.g. from a stored
+/// procedure with several SELECTs. DML without output doesn't produce a result
+/// set, and tiberius doesn't expose per-statement counts on a query stream, so
+/// rows_affected is always 0 here. Server messages are on the first result.
+#[tauri::command]
+pub async fn mssql_query_multi(
+    connection_id: String,
+    sql: String,
+    rows_as_arrays: Option<bool>,
+    timeout_secs: Option<u64>,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<Vec<MssqlQueryResult>, MssqlError> {
+    let mut session = get_session(&manager, &connection_id).await?;
+    session.check_read_only(Some(&sql))?;
+    let timeout_secs = session.query_timeout(timeout_secs);
+    let binary_limit = session.binary_limit();
+    let messages = MessageCollector::default();
+
+    let run = async {
+        match session.client.query_results(Query::new(sql.as_str())).await {
+            Err(e) if is_connection_error(&e) => {
+                session.recover(&app, &connection_id, e, is_read_only_sql(&sql)).await?;
+                messages.clear();
+                session.client.query_results(Query::new(sql.as_str())).await
+            }
+            result => result,
+        }
+        .map_err(|e| MssqlError {
+            message: format!("Query failed: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })
+    };
+    let results = match within(timeout_secs, run.with_subscriber(messages.clone())).await {
+        Some(results) => {
+            session.refresh_transaction().await;
+            results?
+        }
+        None => return Err(session.cancel_timed_out(timeout_secs.unwrap_or_default()).await),
+    };
+
+    let convert = if rows_as_arrays.unwrap_or(false) {
+        row_to_array
+    } else {
+        row_to_json
+    };
+    let mut results: Vec<MssqlQueryResult> = results
+        .into_iter()
+        .map(|result| MssqlQueryResult {
+            columns: result.columns.iter().map(|c| c.name().to_string()).collect(),
+            column_types: result
+                .columns
+                .iter()
+                .map(|c| column_type_name(c.column_type()).to_string())
+                .collect(),
+            rows: result.rows.iter().map(|row| convert(row, binary_limit)).collect(),
+            rows_affected: 0,
+            messages: Vec::new(),
+        })
+        .collect();
+
+    // Messages aren't tied to a result set; the first one carries them all,
+    // and a batch with only PRINTs still gets one result to hold them
+    let messages = messages.take();
+    match results.first_mut() {
+        Some(first) => first.messages = messages,
+        None if !messages.is_empty() => results.push(MssqlQueryResult {
+            columns: vec![],
+            column_types: vec![],
+            rows: vec![],
+            rows_affected: 0,
+            messages,
+        }),
+        None => {}
+    }
+    Ok(results)
+}
+
+/// Get the execution plan for `sql`. Estimated plans compile the batch
+/// without running it (SHOWPLAN_XML); actual plans run it with STATISTICS
+/// XML and keep only the plan result sets. The setting is switched off again
+/// even if the query fails.
+#[tauri::command]
+pub async fn mssql_get_query_plan(
+    connection_id: String,
+    sql: String,
+    actual: bool,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<MssqlQueryPlan, MssqlError> {
+    let plan_error = |e: tiberius::error::Error| MssqlError {
+        message: format!("Failed to get query plan: {}", e),
+        code: "QUERY_ERROR".to_string(),
+    };
+    let mut session = get_session(&manager, &connection_id).await?;
+    if actual {
+        session.check_read_only(Some(&sql))?;
+    }
+    // SHOWPLAN_XML has to be alone in its batch
+    let setting = if actual { "STATISTICS XML" } else { "SHOWPLAN_XML" };
+
+    let enable = || Query::new(format!("SET {} ON", setting));
+    match session.client.execute(enable()).await {
+        Err(e) if is_connection_error(&e) => {
+            session.recover(&app, &connection_id, e, true).await?;
+            session.client.execute(enable()).await
+        }
+        result => result,
+    }
+    .map_err(plan_error)?;
+
+    let timeout_secs = session.query_timeout(None);
+    let results = match within(timeout_secs, session.client.query_results(Query::new(sql.as_str()))).await {
+        Some(results) => results,
+        // The replacement session starts with the setting off
+        None => return Err(session.cancel_timed_out(timeout_secs.unwrap_or_default()).await),
+    };
+
+    // If switching it off fails the session's state is unknown, so start a
+    // new one rather than have every later query return plans
+    if let Err(e) = session.client.execute(Query::new(format!("SET {} OFF", setting))).await {
+        session.reconnect(&app, &connection_id, e).await?;
+    }
+    let results = results.map_err(plan_error)?;
+
+    let plans: Vec<String> = results
+        .iter()
+        .filter(|result| {
+            result.columns.len() == 1 && result.columns[0].name() == "Microsoft SQL Server 2005 XML Showplan"
+        })
+        .flat_map(|result| result.rows.iter())
+        .filter_map(|row| {
+            row.try_get::<&str, _>(0)
+                .ok()
+                .flatten()
+                .map(str::to_string)
+                .or_else(|| row.try_get::<&XmlData, _>(0).ok().flatten().map(|xml| xml.to_string()))
+        })
+        .collect();
+    let statements = plans.iter().flat_map(|xml| summarize_plan(xml)).collect();
+
+    Ok(MssqlQueryPlan { plans, statements })
+}
+
+/// Pull statements and their operator trees out of a ShowPlan document.
+/// A document that doesn't parse yields no statements; its XML is still
+/// returned as is.
+fn summarize_plan(xml: &str) -> Vec<MssqlPlanStatement> {
+    let Ok(doc) = roxmltree::Document::parse(xml) else {
+        return Vec::new();
+    };
+
+    doc.descendants()
+        .filter(|node| node.tag_name().name() == "StmtSimple")
+        .map(|statement| {
+            let plan = statement.children().find(|node| node.tag_name().name() == "QueryPlan");
+            let total = plan_attr(statement, "StatementSubTreeCost");
+            let root = plan
+                .and_then(|plan| plan.children().find(|node| node.tag_name().name() == "RelOp"))
+                .map(|relop| plan_node(relop, total));
+
+            let mut operators = Vec::new();
+            if let Some(root) = &root {
+                collect_operators(root, &mut operators);
+            }
+            operators.sort_by(|a, b| b.cost_percent.total_cmp(&a.cost_percent));
+            operators.truncate(5);
+
+            MssqlPlanStatement {
+                text: statement.attribute("StatementText").unwrap_or_default().trim().to_string(),
+                estimated_cost: total,
+                warnings: plan
+                    .and_then(|plan| plan.children().find(|node| node.tag_name().name() == "Warnings"))
+                    .map(plan_warnings)
+                    .unwrap_or_default(),
+                top_operators: operators,
+                root,
+            }
+        })
+        .collect()
+}
+
+fn plan_node(relop: roxmltree::Node, total: Option<f64>) -> MssqlPlanNode {
+    // Child RelOps sit inside operator-specific elements, so walk down to
+    // the next RelOp on each branch
+    let mut own = Vec::new();
+    let mut child_relops = Vec::new();
+    let mut stack: Vec<_> = relop.children().filter(|node| node.is_element()).collect();
+    stack.reverse();
+    while let Some(node) = stack.pop() {
+        if node.tag_name().name() == "RelOp" {
+            child_relops.push(node);
+        } else {
+            own.push(node);
+            stack.extend(node.children().filter(|child| child.is_element()).rev());
+        }
+    }
+
+    let children: Vec<MssqlPlanNode> = child_relops.into_iter().map(|child| plan_node(child, total)).collect();
+    let subtree_cost = plan_attr(relop, "EstimatedTotalSubtreeCost");
+    let cost_percent = subtree_cost.zip(total).filter(|(_, total)| *total > 0.0).map(|(cost, total)| {
+        let below: f64 = children.iter().filter_map(|child| child.subtree_cost).sum();
+        ((cost - below).max(0.0) / total * 100.0 * 10.0).round() / 10.0
+    });
+
+    MssqlPlanNode {
+        node_id: relop.attribute("NodeId").and_then(|v| v.parse().ok()).unwrap_or_default(),
+        physical_op: relop.attribute("PhysicalOp").unwrap_or_default().to_string(),
+        logical_op: relop.attribute("LogicalOp").unwrap_or_default().to_string(),
+        object: own.iter().find(|node| node.tag_name().name() == "Object").map(|object| {
+            ["Schema", "Table", "Index"]
+                .iter()
+                .filter_map(|name| object.attribute(*name))
+                .collect::<Vec<_>>()
+                .join(".")
+        }),
+        estimated_rows: plan_attr(relop, "EstimateRows"),
+        actual_rows: relop
+            .children()
+            .find(|node| node.tag_name().name() == "RunTimeInformation")
+            .map(|info| {
+                info.children()
+                    .filter_map(|thread| thread.attribute("ActualRows"))
+                    .filter_map(|rows| rows.parse::<u64>().ok())
+                    .sum()
+            }),
+        subtree_cost,
+        cost_percent,
+        warnings: own
+            .iter()
+            .filter(|node| node.tag_name().name() == "Warnings")
+            .flat_map(|warnings| plan_warnings(*warnings))
+            .collect(),
+        children,
+    }
+}
+
+fn plan_attr(node: roxmltree::Node, name: &str) -> Option<f64> {
+    node.attribute(name).and_then(|value| value.parse().ok())
+}
+
+/// Describe a `Warnings` element. Some warnings are child elements and some
+/// are boolean attributes.
+fn plan_warnings(warnings: roxmltree::Node) -> Vec<String> {
+    let flags = warnings
+        .attributes()
+        .filter(|attr| attr.value() == "1" || attr.value() == "true")
+        .map(|attr| match attr.name() {
+            "NoJoinPredicate" => "No join predicate".to_string(),
+            name => name.to_string(),
+        });
+    let elements = warnings.children().filter(|node| node.is_element()).map(|node| {
+        match node.tag_name().name() {
+            "PlanAffectingConvert" => format!(
+                "Implicit conversion affects {}: {}",
+                node.attribute("ConvertIssue").unwrap_or("the plan"),
+                node.attribute("Expression").unwrap_or_default()
+            ),
+            "SpillToTempDb" => "Spill to tempdb".to_string(),
+            "ColumnsWithNoStatistics" => "Columns with no statistics".to_string(),
+            "MemoryGrantWarning" => format!(
+                "Memory grant: {}",
+                node.attribute("GrantWarningKind").unwrap_or_default()
+            ),
+            name => name.to_string(),
+        }
+    });
+    flags.chain(elements).collect()
+}
+
+fn collect_operators(node: &MssqlPlanNode, operators: &mut Vec<MssqlPlanOperator>) {
+    if let Some(cost_percent) = node.cost_percent {
+        operators.push(MssqlPlanOperator {
+            node_id: node.node_id,
+            physical_op: node.physical_op.clone(),
+            cost_percent,
+        });
+    }
+    for child in &node.children {
+        collect_operators(child, operators);
+    }
+}
+
+/// Run a stored procedure. Output parameters are declared as variables,
+/// passed with OUTPUT and selected after the EXEC together with the return
+/// code, so the batch's last result set is ours rather than the procedure's.
+#[tauri::command]
+pub async fn mssql_exec_procedure(
+    connection_id: String,
+    schema: Option<String>,
+    name: String,
+    params: Vec<MssqlProcedureParam>,
+    timeout_secs: Option<u64>,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<MssqlProcedureResult, MssqlError> {
+    let param_error = |message: String| MssqlError {
+        message,
+        code: "PARAM_ERROR".to_string(),
+    };
+    // Names and types go into the batch text, so only allow what they can
+    // legitimately contain
+    let valid_name = |name: &str| !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    let valid_type = |sql_type: &str| sql_type.chars().all(|c| c.is_ascii_alphanumeric() || " _(),".contains(c));
+
+    let mut declares = vec!["DECLARE @__seaquel_return int;".to_string()];
+    let mut args = Vec::new();
+    let mut values = Vec::new();
+    let mut hints = Vec::new();
+    let mut outputs = Vec::new();
+    for (index, param) in params.iter().enumerate() {
+        let param_name = param.name.trim().trim_start_matches('@');
+        if !valid_name(param_name) {
+            return Err(param_error(format!("Invalid parameter name: {}", param.name)));
+        }
+        let sql_type = param.sql_type.as_deref().map(str::trim).filter(|t| !t.is_empty());
+        if let Some(sql_type) = sql_type.filter(|t| !valid_type(t)) {
+            return Err(param_error(format!("Invalid type for @{}: {}", param_name, sql_type)));
+        }
+        // bind_param takes the bare type name, without length or precision
+        let hint = sql_type.map(|t| t.split('(').next().unwrap_or(t).trim().to_string());
+
+        match param.direction.as_deref().unwrap_or("in").to_lowercase().as_str() {
+            "in" => {
+                values.push(param.value.clone());
+                hints.push(hint);
+                args.push(format!("@{} = @P{}", param_name, values.len()));
+            }
+            direction @ ("out" | "inout") => {
+                let sql_type = sql_type.ok_or_else(|| {
+                    param_error(format!("Output parameter @{} needs a sql_type", param_name))
+                })?;
+                let variable = format!("@__seaquel_out{}", index);
+                if direction == "inout" && !param.value.is_null() {
+                    values.push(param.value.clone());
+                    hints.push(hint);
+                    declares.push(format!("DECLARE {} {} = @P{};", variable, sql_type, values.len()));
+                } else {
+                    declares.push(format!("DECLARE {} {};", variable, sql_type));
+                }
+                args.push(format!("@{} = {} OUTPUT", param_name, variable));
+                outputs.push((param_name.to_string(), variable));
+            }
+            other => {
+                return Err(param_error(format!(
+                    "Invalid direction for @{}: {} (expected in, out or inout)",
+                    param_name, other
+                )))
+            }
+        }
+    }
+
+    let procedure = match schema.as_deref().filter(|s| !s.is_empty()) {
+        Some(schema) => format!("{}.{}", quote_identifier(schema), quote_identifier(&name)),
+        None => quote_identifier(&name),
+    };
+    let selected: Vec<String> = std::iter::once("@__seaquel_return".to_string())
+        .chain(outputs.iter().map(|(name, variable)| format!("{} AS {}", variable, quote_identifier(name))))
+        .collect();
+    let sql = format!(
+        "{} EXEC @__seaquel_return = {} {}; SELECT {};",
+        declares.join(" "),
+        procedure,
+        args.join(", "),
+        selected.join(", ")
+    );
+    let query = || build_query(&sql, Some(values.as_slice()), Some(hints.as_slice()));
+
+    let mut session = get_session(&manager, &connection_id).await?;
+    // A procedure can do anything, so read-only connections can't run them
+    session.check_read_only(None)?;
+    let timeout_secs = session.query_timeout(timeout_secs);
+    let binary_limit = session.binary_limit();
+    let messages = MessageCollector::default();
+
+    let run = async {
+        match session.client.query_results(query()?).await {
+            Err(e) if is_connection_error(&e) => {
+                session.recover(&app, &connection_id, e, false).await?;
+                messages.clear();
+                session.client.query_results(query()?).await
+            }
+            result => result,
+        }
+        .map_err(|e| MssqlError {
+            message: format!("Procedure failed: {}", e),
+            code: "QUERY_ERROR".to_string(),
+        })
+    };
+    let mut results = match within(timeout_secs, run.with_subscriber(messages.clone())).await {
+        Some(results) => {
+            session.refresh_transaction().await;
+            results?
+        }
+        None => return Err(session.cancel_timed_out(timeout_secs.unwrap_or_default()).await),
+    };
+
+    let mut output = serde_json::Map::new();
+    let mut return_code = None;
+    if let Some(row) = results.pop().and_then(|result| result.rows.into_iter().next()) {
+        return_code = row.try_get::<i32, _>(0).ok().flatten();
+        for (idx, column) in row.columns().iter().enumerate().skip(1) {
+            output.insert(column.name().to_string(), cell_to_json(&row, idx, usize::MAX));
+        }
+    }
+
+    Ok(MssqlProcedureResult {
+        results: results
+            .into_iter()
+            .map(|result| MssqlQueryResult {
+                columns: result.columns.iter().map(|c| c.name().to_string()).collect(),
+                column_types: result
+                    .columns
+                    .iter()
+                    .map(|c| column_type_name(c.column_type()).to_string())
+                    .collect(),
+                rows: result.rows.iter().map(|row| row_to_json(row, binary_limit)).collect(),
+                rows_affected: 0,
+                messages: Vec::new(),
+            })
+            .collect(),
+        output,
+        return_code,
+        messages: messages.take(),
+    })
+}
+
+/// The parameters a stored procedure declares, in order
+#[tauri::command]
+pub async fn mssql_get_procedure_params(
+    connection_id: String,
+    schema: Option<String>,
+    name: String,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<Vec<MssqlProcedureParamInfo>, MssqlError> {
+    let procedure = match schema.as_deref().filter(|s| !s.is_empty()) {
+        Some(schema) => format!("{}.{}", quote_identifier(schema), quote_identifier(&name)),
+        None => quote_identifier(&name),
+    };
+    let sql = format!(
+        "SELECT p.name, {}, p.is_output, p.has_default_value FROM sys.parameters p \
+         JOIN sys.types t ON t.user_type_id = p.user_type_id \
+         WHERE p.object_id = OBJECT_ID(@P1) AND p.parameter_id > 0 ORDER BY p.parameter_id",
+        type_name_sql("p")
+    );
+    let mut session = get_session(&manager, &connection_id).await?;
+    let rows = query_rows(&mut session, &app, &connection_id, &sql, &[serde_json::json!(procedure)]).await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let text = |idx: usize| row.try_get::<&str, _>(idx).ok().flatten().unwrap_or_default().to_string();
+            let flag = |idx: usize| row.try_get::<bool, _>(idx).ok().flatten().unwrap_or_default();
+            MssqlProcedureParamInfo {
+                name: text(0).trim_start_matches('@').to_string(),
+                sql_type: text(1),
+                direction: if flag(2) { "inout" } else { "in" }.to_string(),
+                has_default: flag(3),
+            }
+        })
+        .collect())
+}
+
+/// Start a query whose rows are read in batches with mssql_query_fetch, so a
+/// large result never has to be held in memory at once. Only the first result
+/// set is returned.
+#[tauri::command]
+pub async fn mssql_query_open(
+    connection_id: String,
+    sql: String,
+    rows_as_arrays: Option<bool>,
+    params: Option<Vec<serde_json::Value>>,
+    param_types: Option<Vec<Option<String>>>,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<MssqlCursorInfo, MssqlError> {
+    let query = build_query(&sql, params.as_deref(), param_types.as_deref())?;
+    let session = get_session(&manager, &connection_id).await?;
+    session.check_read_only(Some(&sql))?;
+    let binary_limit = session.binary_limit();
+    let convert = if rows_as_arrays.unwrap_or(false) {
+        row_to_array
+    } else {
+        row_to_json
+    };
+
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let (requests_tx, requests_rx) = mpsc::channel(1);
+    tokio::spawn(run_cursor(session, query, convert, binary_limit, ready_tx, requests_rx));
+
+    let columns = ready_rx.await.map_err(|_| MssqlError {
+        message: "Cursor task ended unexpectedly".to_string(),
+        code: "CURSOR_ERROR".to_string(),
+    })??;
+
+    let cursor_id = {
+        let mut next_id = manager.next_id.lock().await;
+        let id = format!("mssql-cursor-{}", *next_id);
+        *next_id += 1;
+        id
+    };
+    manager.cursors.lock().await.insert(
+        cursor_id.clone(),
+        MssqlCursor {
+            connection_id,
+            requests: requests_tx,
+        },
+    );
+
+    Ok(MssqlCursorInfo {
+        cursor_id,
+        columns: columns.iter().map(|c| c.name().to_string()).collect(),
+        column_types: columns.iter().map(|c| column_type_name(c.column_type()).to_string()).collect(),
+    })
+}
+
+/// Read up to `batch_size` more rows from a cursor
+#[tauri::command]
+pub async fn mssql_query_fetch(
+    cursor_id: String,
+    batch_size: usize,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<MssqlCursorBatch, MssqlError> {
+    let requests = {
+        let cursors = manager.cursors.lock().await;
+        let cursor = cursors.get(&cursor_id).ok_or(MssqlError {
+            message: format!("Cursor not found: {}", cursor_id),
+            code: "CURSOR_NOT_FOUND".to_string(),
+        })?;
+        cursor.requests.clone()
+    };
+
+    let ended = || MssqlError {
+        message: format!("Cursor is no longer open: {}", cursor_id),
+        code: "CURSOR_NOT_FOUND".to_string(),
+    };
+    let (reply_tx, reply_rx) = oneshot::channel();
+    requests
+        .send((batch_size.max(1), reply_tx))
+        .await
+        .map_err(|_| ended())?;
+    reply_rx.await.map_err(|_| ended())?
+}
+
+/// Close a cursor, discarding any rows that weren't fetched
+#[tauri::command]
+pub async fn mssql_query_close(
+    cursor_id: String,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<(), MssqlError> {
+    manager.cursors.lock().await.remove(&cursor_id);
+    Ok(())
+}
+
+/// A destination column as described by INFORMATION_SCHEMA.COLUMNS
+struct BulkColumn {
+    name: String,
+    data_type: String,
+    nullable: bool,
+    /// Decimal places for decimal/numeric, fractional second digits for
+    /// time, datetime2 and datetimeoffset
+    scale: u8,
+}
+
+/// Load rows with the TDS bulk load protocol, which is far faster than
+/// INSERT statements. `rows` are aligned with `columns`; table columns not
+/// listed are sent as NULL. Every value is converted up front, so a bad value
+/// is reported by row and column before anything is written. Each batch is
+/// its own bulk load, committed as it completes.
+#[tauri::command]
+pub async fn mssql_bulk_insert(
+    connection_id: String,
+    table: String,
+    columns: Vec<String>,
+    rows: Vec<Vec<serde_json::Value>>,
+    batch_size: Option<usize>,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<MssqlBulkInsertResult, MssqlError> {
+    let bulk_error = |message: String| MssqlError {
+        message,
+        code: "BULK_INSERT_ERROR".to_string(),
+    };
+    let (schema_name, table_name) = split_table_name(&table);
+    let mut session = get_session(&manager, &connection_id).await?;
+    session.check_read_only(None)?;
+
+    // The columns tiberius will send: everything except identity, computed
+    // and rowversion columns, in table order
+    let lookup = build_query(
+        "SELECT c.COLUMN_NAME, c.DATA_TYPE, c.IS_NULLABLE, \
+                CAST(COALESCE(c.NUMERIC_SCALE, c.DATETIME_PRECISION, 0) AS int), \
+                COLUMNPROPERTY(OBJECT_ID(QUOTENAME(c.TABLE_SCHEMA) + '.' + QUOTENAME(c.TABLE_NAME)), c.COLUMN_NAME, 'IsIdentity'), \
+                COLUMNPROPERTY(OBJECT_ID(QUOTENAME(c.TABLE_SCHEMA) + '.' + QUOTENAME(c.TABLE_NAME)), c.COLUMN_NAME, 'IsComputed') \
+         FROM INFORMATION_SCHEMA.COLUMNS c \
+         WHERE c.TABLE_SCHEMA = COALESCE(@P1, SCHEMA_NAME()) AND c.TABLE_NAME = @P2 \
+         ORDER BY c.ORDINAL_POSITION",
+        Some(&[serde_json::json!(schema_name), serde_json::json!(table_name)][..]),
+        None,
+    )?;
+    let results = session.client.query_results(lookup).await.map_err(|e| MssqlError {
+        message: format!("Failed to read columns of {}: {}", table, e),
+        code: "QUERY_ERROR".to_string(),
+    })?;
+    let rows_of_columns = results.into_iter().next().map(|result| result.rows).unwrap_or_default();
+    if rows_of_columns.is_empty() {
+        return Err(MssqlError {
+            message: format!("Table not found: {}", table),
+            code: "TABLE_NOT_FOUND".to_string(),
+        });
+    }
+
+    let text = |row: &Row, idx: usize| row.try_get::<&str, _>(idx).ok().flatten().unwrap_or_default().to_string();
+    let number = |row: &Row, idx: usize| row.try_get::<i32, _>(idx).ok().flatten().unwrap_or_default();
+    let mut table_columns = Vec::new();
+    let mut generated = Vec::new();
+    for row in &rows_of_columns {
+        let column = BulkColumn {
+            name: text(row, 0),
+            data_type: text(row, 1).to_lowercase(),
+            nullable: text(row, 2) == "YES",
+            scale: number(row, 3).clamp(0, 38) as u8,
+        };
+        if number(row, 4) == 1 || number(row, 5) == 1 || matches!(column.data_type.as_str(), "timestamp" | "rowversion") {
+            generated.push(column.name);
+        } else {
+            table_columns.push(column);
+        }
+    }
+
+    // Where each table column's value comes from in the input rows
+    let mut sources = Vec::with_capacity(table_columns.len());
+    for column in &table_columns {
+        let source = columns.iter().position(|name| name.eq_ignore_ascii_case(&column.name));
+        if source.is_none() && !column.nullable {
+            return Err(bulk_error(format!(
+                "Column '{}' does not allow NULL and is missing from the import",
+                column.name
+            )));
+        }
+        sources.push(source);
+    }
+    for name in &columns {
+        if let Some(generated) = generated.iter().find(|g| g.eq_ignore_ascii_case(name)) {
+            return Err(bulk_error(format!(
+                "Column '{}' is generated by the server (identity, computed or rowversion) and can't be imported",
+                generated
+            )));
+        }
+        if !table_columns.iter().any(|c| c.name.eq_ignore_ascii_case(name)) {
+            return Err(bulk_error(format!("Column '{}' does not exist in {}", name, table)));
+        }
+    }
+
+    let mut token_rows = Vec::with_capacity(rows.len());
+    for (row_index, row) in rows.iter().enumerate() {
+        if row.len() != columns.len() {
+            return Err(bulk_error(format!(
+                "Row {}: expected {} values, got {}",
+                row_index,
+                columns.len(),
+                row.len()
+            )));
+        }
+        let mut token_row = TokenRow::default();
+        for (column, source) in table_columns.iter().zip(&sources) {
+            let value = source.map(|i| &row[i]).unwrap_or(&serde_json::Value::Null);
+            let data = bulk_value(value, column).map_err(|e| {
+                bulk_error(format!("Row {}, column '{}': {}", row_index, column.name, e))
+            })?;
+            token_row.push(data);
+        }
+        token_rows.push(token_row);
+    }
+
+    // tiberius pastes the name into its INSERT BULK statement
+    let target = quote_table_name(&table);
+
+    let total_rows = token_rows.len() as u64;
+    let batch_size = batch_size.filter(|size| *size > 0).unwrap_or(10_000);
+    let mut rows_sent = 0u64;
+    let mut batches = Vec::new();
+    let mut pending = token_rows.into_iter().peekable();
+    while pending.peek().is_some() {
+        let batch_rows: Vec<TokenRow<'static>> = pending.by_ref().take(batch_size).collect();
+        let started = std::time::Instant::now();
+        let sent = match session.client.bulk_insert(&target, batch_rows).await {
+            Ok(sent) => sent,
+            Err((row, e)) => {
+                // A failed bulk load leaves the connection mid-request
+                if let Ok(fresh) = Session::open(session.config.clone()).await {
+                    session.replace(fresh);
+                }
+                let location = match row {
+                    Some(row) => format!("Row {}: ", rows_sent as usize + row),
+                    None => String::new(),
+                };
+                return Err(bulk_error(format!(
+                    "{}{} ({} rows were committed in earlier batches)",
+                    location, e, rows_sent
+                )));
+            }
+        };
+        rows_sent += sent;
+        let batch = MssqlBulkInsertBatch {
+            rows: sent,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        };
+        let _ = app.emit(
+            "mssql://bulk-insert-progress",
+            MssqlBulkInsertProgress {
+                connection_id: connection_id.clone(),
+                table: table.clone(),
+                rows_sent,
+                total_rows,
+                batch: batch.clone(),
+            },
+        );
+        batches.push(batch);
+    }
+
+    Ok(MssqlBulkInsertResult { rows_sent, batches })
+}
+
+/// Bracket-quote a `schema.table` or `table` name for use in SQL
+fn quote_table_name(name: &str) -> String {
+    match split_table_name(name) {
+        (Some(schema), table) => format!("{}.{}", quote_identifier(schema), quote_identifier(table)),
+        (None, table) => quote_identifier(table),
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("[{}]", name.replace(']', "]]"))
+}
+
+/// Split `schema.table` (either part optionally in brackets) into its parts
+fn split_table_name(name: &str) -> (Option<&str>, &str) {
+    let unbracket = |part: &'_ str| {
+        let part = part.trim();
+        part.strip_prefix('[').and_then(|p| p.strip_suffix(']')).unwrap_or(part)
+    };
+    let mut in_brackets = false;
+    for (i, c) in name.char_indices() {
+        match c {
+            '[' => in_brackets = true,
+            ']' => in_brackets = false,
+            '.' if !in_brackets => return (Some(unbracket(&name[..i])), unbracket(&name[i + 1..])),
+            _ => {}
+        }
+    }
+    (None, unbracket(name))
+}
+
+/// Convert a JSON value to the bulk load representation of a column's type
+fn bulk_value(value: &serde_json::Value, column: &BulkColumn) -> Result<ColumnData<'static>, String> {
+    use tiberius::time::chrono::Timelike;
+    use tiberius::time::{DateTime2, DateTimeOffset, SmallDateTime, Time};
+
+    if value.is_null() {
+        if !column.nullable {
+            return Err("column does not allow NULL".to_string());
+        }
+        return bulk_null(&column.data_type);
+    }
+
+    let invalid = || format!("expected {}, got {}", column.data_type, value);
+    let text = match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let integer = value.as_i64().or_else(|| text.trim().parse::<i64>().ok());
+    let float = value.as_f64().or_else(|| text.trim().parse::<f64>().ok());
+    // chrono values convert at 100ns precision; the column may store fewer digits
+    let rescale = |time: Time| Time::new(time.increments() / 10u64.pow(7 - column.scale.min(7) as u32), column.scale.min(7));
+    let days_since_1900 = |date: NaiveDate| (date - NaiveDate::from_ymd_opt(1900, 1, 1).unwrap_or_default()).num_days();
+
+    Ok(match column.data_type.as_str() {
+        "bit" => ColumnData::Bit(Some(parse_bit(value).ok_or_else(invalid)?)),
+        "tinyint" => ColumnData::U8(Some(integer.and_then(|v| u8::try_from(v).ok()).ok_or_else(invalid)?)),
+        "smallint" => ColumnData::I16(Some(integer.and_then(|v| i16::try_from(v).ok()).ok_or_else(invalid)?)),
+        "int" => ColumnData::I32(Some(integer.and_then(|v| i32::try_from(v).ok()).ok_or_else(invalid)?)),
+        "bigint" => ColumnData::I64(Some(integer.ok_or_else(invalid)?)),
+        "real" => ColumnData::F32(Some(float.ok_or_else(invalid)? as f32)),
+        "float" => ColumnData::F64(Some(float.ok_or_else(invalid)?)),
+        "decimal" | "numeric" => {
+            let numeric = parse_numeric(&text).ok_or_else(invalid)?;
+            let numeric = rescale_numeric(numeric, column.scale)
+                .ok_or_else(|| format!("{} has more than {} decimal places", text, column.scale))?;
+            ColumnData::Numeric(Some(numeric))
+        }
+        "char" | "varchar" | "nchar" | "nvarchar" | "text" | "ntext" => ColumnData::String(Some(text.into())),
+        "xml" => ColumnData::Xml(Some(std::borrow::Cow::Owned(tiberius::xml::XmlData::new(text)))),
+        "uniqueidentifier" => ColumnData::Guid(Some(Uuid::parse_str(text.trim()).map_err(|_| invalid())?)),
+        "binary" | "varbinary" | "image" => ColumnData::Binary(Some(decode_blob(value).ok_or_else(invalid)?.into())),
+        "date" => {
+            NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").map_err(|_| invalid())?.into_sql()
+        }
+        "time" => match NaiveTime::parse_from_str(text.trim(), "%H:%M:%S%.f").map_err(|_| invalid())?.into_sql() {
+            ColumnData::Time(time) => ColumnData::Time(time.map(rescale)),
+            other => other,
+        },
+        "datetime2" => parse_datetime(&text).ok_or_else(invalid)?.into_sql(),
+        "datetime" => {
+            let datetime = parse_datetime(&text).ok_or_else(invalid)?;
+            let time = datetime.time();
+            let nanos = time.num_seconds_from_midnight() as u64 * 1_000_000_000 + time.nanosecond() as u64;
+            let fragments = nanos * 300 / 1_000_000_000;
+            ColumnData::DateTime(Some(tiberius::time::DateTime::new(
+                days_since_1900(datetime.date()) as i32,
+                fragments as u32,
+            )))
+        }
+        "smalldatetime" => {
+            let datetime = parse_datetime(&text).ok_or_else(invalid)?;
+            let days = u16::try_from(days_since_1900(datetime.date())).map_err(|_| invalid())?;
+            let minutes = datetime.time().num_seconds_from_midnight() / 60;
+            ColumnData::SmallDateTime(Some(SmallDateTime::new(days, minutes as u16)))
+        }
+        "datetimeoffset" => match DateTime::parse_from_rfc3339(text.trim()).map_err(|_| invalid())?.into_sql() {
+            ColumnData::DateTimeOffset(dto) => ColumnData::DateTimeOffset(dto.map(|dto| {
+                let datetime2 = dto.datetime2();
+                DateTimeOffset::new(DateTime2::new(datetime2.date(), rescale(datetime2.time())), dto.offset())
+            })),
+            other => other,
+        },
+        other => return Err(format!("columns of type {} can't be bulk loaded", other)),
+    })
+}
 
-    Ok(MssqlQueryResult {
-        columns: vec![],
-        rows: vec![],
-        rows_affected: result.rows_affected().iter().sum(),
+/// A NULL of the right variant for a column type
+fn bulk_null(data_type: &str) -> Result<ColumnData<'static>, String> {
+    Ok(match data_type {
+        "bit" => ColumnData::Bit(None),
+        "tinyint" => ColumnData::U8(None),
+        "smallint" => ColumnData::I16(None),
+        "int" => ColumnData::I32(None),
+        "bigint" => ColumnData::I64(None),
+        "real" => ColumnData::F32(None),
+        "float" => ColumnData::F64(None),
+        "decimal" | "numeric" => ColumnData::Numeric(None),
+        "char" | "varchar" | "nchar" | "nvarchar" | "text" | "ntext" => ColumnData::String(None),
+        "xml" => ColumnData::Xml(None),
+        "uniqueidentifier" => ColumnData::Guid(None),
+        "binary" | "varbinary" | "image" => ColumnData::Binary(None),
+        "date" => ColumnData::Date(None),
+        "time" => ColumnData::Time(None),
+        "datetime2" => ColumnData::DateTime2(None),
+        "datetime" => ColumnData::DateTime(None),
+        "smalldatetime" => ColumnData::SmallDateTime(None),
+        "datetimeoffset" => ColumnData::DateTimeOffset(None),
+        other => return Err(format!("columns of type {} can't be bulk loaded", other)),
+    })
+}
+
+/// Bring a decimal to the column's scale. tiberius requires the scales to
+/// match exactly, and dropping digits would silently change the value.
+fn rescale_numeric(numeric: Numeric, scale: u8) -> Option<Numeric> {
+    let (value, current) = (numeric.value(), numeric.scale());
+    let value = if current <= scale {
+        value.checked_mul(10i128.checked_pow((scale - current) as u32)?)?
+    } else {
+        let divisor = 10i128.checked_pow((current - scale) as u32)?;
+        if value % divisor != 0 {
+            return None;
+        }
+        value / divisor
+    };
+    Some(Numeric::new_with_scale(value, scale))
+}
+
+/// Every database on the server, including ones the login can't open
+#[tauri::command]
+pub async fn mssql_list_databases(
+    connection_id: String,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<Vec<MssqlDatabase>, MssqlError> {
+    let sql = "SELECT name, state_desc, \
+               CASE WHEN HAS_DBACCESS(name) = 1 THEN CAST(1 AS bit) ELSE CAST(0 AS bit) END \
+               FROM sys.databases ORDER BY name";
+    let mut session = get_session(&manager, &connection_id).await?;
+
+    let results = match session.client.query_results(Query::new(sql)).await {
+        Err(e) if is_connection_error(&e) => {
+            session.recover(&app, &connection_id, e, true).await?;
+            session.client.query_results(Query::new(sql)).await
+        }
+        result => result,
+    }
+    .map_err(|e| MssqlError {
+        message: format!("Failed to list databases: {}", e),
+        code: "QUERY_ERROR".to_string(),
+    })?;
+
+    Ok(results
+        .into_iter()
+        .next()
+        .map(|result| result.rows)
+        .unwrap_or_default()
+        .iter()
+        .map(|row| MssqlDatabase {
+            name: row.try_get::<&str, _>(0).ok().flatten().unwrap_or_default().to_string(),
+            state: row.try_get::<&str, _>(1).ok().flatten().unwrap_or_default().to_string(),
+            has_access: row.try_get::<bool, _>(2).ok().flatten().unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Switch the connection to another database on the same server. The stored
+/// config is updated too, so a reconnect or a new pooled session lands in
+/// the new database. Returns
+/// the database name as the server reports it.
+#[tauri::command]
+pub async fn mssql_use_database(
+    connection_id: String,
+    database: String,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<String, MssqlError> {
+    let sql = format!("USE [{}]; SELECT DB_NAME()", database.replace(']', "]]"));
+    let mut session = get_session(&manager, &connection_id).await?;
+
+    let results = match session.client.query_results(Query::new(sql.as_str())).await {
+        Err(e) if is_connection_error(&e) => {
+            session.recover(&app, &connection_id, e, true).await?;
+            session.client.query_results(Query::new(sql.as_str())).await
+        }
+        result => result,
+    }
+    .map_err(|e| {
+        let code = match &e {
+            // 911: database does not exist; 916: login has no user in it
+            tiberius::error::Error::Server(token) if token.code() == 911 => "DATABASE_NOT_FOUND",
+            tiberius::error::Error::Server(token) if token.code() == 916 => "DATABASE_ACCESS_DENIED",
+            _ => "USE_DATABASE_ERROR",
+        };
+        MssqlError {
+            message: format!("Failed to switch to database {}: {}", database, e),
+            code: code.to_string(),
+        }
+    })?;
+
+    let current = results
+        .iter()
+        .flat_map(|result| result.rows.iter())
+        .next()
+        .and_then(|row| row.try_get::<&str, _>(0).ok().flatten())
+        .unwrap_or_default()
+        .to_string();
+    if !current.eq_ignore_ascii_case(&database) {
+        return Err(MssqlError {
+            message: format!("Switched to database {} but the server reports {}", database, current),
+            code: "USE_DATABASE_ERROR".to_string(),
+        });
+    }
+
+    session.config.database = current.clone();
+    // Other sessions in the pool switch when they are next checked out
+    if let Some(handle) = manager.connections.lock().await.get_mut(&connection_id) {
+        handle.config.database = current.clone();
+    }
+    Ok(current)
+}
+
+/// A whole binary cell as base64, for values that came back as a
+/// `$blob_length` placeholder. The row is found by `key_column = key_value`,
+/// which must match exactly one row.
+#[tauri::command]
+pub async fn mssql_get_cell_binary(
+    connection_id: String,
+    table: String,
+    key_column: String,
+    key_value: serde_json::Value,
+    column: String,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<String, MssqlError> {
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+    let sql = format!(
+        "SELECT {} FROM {} WHERE {} = @P1",
+        quote_identifier(&column),
+        quote_table_name(&table),
+        quote_identifier(&key_column)
+    );
+    let mut session = get_session(&manager, &connection_id).await?;
+
+    let rows = query_rows(&mut session, &app, &connection_id, &sql, std::slice::from_ref(&key_value)).await?;
+    let row = single_row(&rows)?;
+    let bytes = row.try_get::<&[u8], _>(0).map_err(|e| MssqlError {
+        message: format!("Column {} is not binary: {}", column, e),
+        code: "QUERY_ERROR".to_string(),
+    })?;
+    Ok(STANDARD.encode(bytes.unwrap_or_default()))
+}
+
+/// Write a binary cell to a file, reading it in chunks with SUBSTRING so a
+/// large value is never held in memory whole. Returns the bytes written.
+#[tauri::command]
+pub async fn mssql_save_cell_binary(
+    connection_id: String,
+    table: String,
+    key_column: String,
+    key_value: serde_json::Value,
+    column: String,
+    path: String,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<u64, MssqlError> {
+    use std::io::Write;
+
+    const CHUNK_BYTES: i64 = 4 * 1024 * 1024;
+
+    let column_sql = quote_identifier(&column);
+    let from_sql = format!("FROM {} WHERE {} = @P1", quote_table_name(&table), quote_identifier(&key_column));
+    let mut session = get_session(&manager, &connection_id).await?;
+
+    let length_sql = format!("SELECT CAST(DATALENGTH({}) AS bigint) {}", column_sql, from_sql);
+    let rows = query_rows(&mut session, &app, &connection_id, &length_sql, std::slice::from_ref(&key_value)).await?;
+    let length = single_row(&rows)?.try_get::<i64, _>(0).ok().flatten().unwrap_or_default();
+
+    let file_error = |e: std::io::Error| MssqlError {
+        message: format!("Failed to write {}: {}", path, e),
+        code: "FILE_ERROR".to_string(),
+    };
+    let mut file = std::fs::File::create(&path).map_err(file_error)?;
+
+    let chunk_sql = format!("SELECT SUBSTRING({}, @P2, @P3) {}", column_sql, from_sql);
+    let mut written: i64 = 0;
+    while written < length {
+        let params = [
+            key_value.clone(),
+            serde_json::json!(written + 1),
+            serde_json::json!(CHUNK_BYTES),
+        ];
+        let rows = query_rows(&mut session, &app, &connection_id, &chunk_sql, &params).await?;
+        let chunk = single_row(&rows)?.try_get::<&[u8], _>(0).ok().flatten().unwrap_or_default();
+        // The row changed or went away under us
+        if chunk.is_empty() {
+            return Err(MssqlError {
+                message: format!("Value ended after {} of {} bytes", written, length),
+                code: "QUERY_ERROR".to_string(),
+            });
+        }
+        file.write_all(chunk).map_err(file_error)?;
+        written += chunk.len() as i64;
+    }
+    file.flush().map_err(file_error)?;
+
+    Ok(written as u64)
+}
+
+/// Rows of the first result set, reconnecting once if the connection is gone
+async fn query_rows(
+    session: &mut Session,
+    app: &tauri::AppHandle,
+    connection_id: &str,
+    sql: &str,
+    params: &[serde_json::Value],
+) -> Result<Vec<Row>, MssqlError> {
+    let query = || build_query(sql, Some(params), None);
+    let results = match session.client.query_results(query()?).await {
+        Err(e) if is_connection_error(&e) => {
+            session.recover(app, connection_id, e, true).await?;
+            session.client.query_results(query()?).await
+        }
+        result => result,
+    }
+    .map_err(|e| MssqlError {
+        message: format!("Query failed: {}", e),
+        code: "QUERY_ERROR".to_string(),
+    })?;
+    Ok(results.into_iter().next().map(|result| result.rows).unwrap_or_default())
+}
+
+/// The one row a key lookup matched
+fn single_row(rows: &[Row]) -> Result<&Row, MssqlError> {
+    match rows {
+        [row] => Ok(row),
+        [] => Err(MssqlError {
+            message: "No row matches the key".to_string(),
+            code: "ROW_NOT_FOUND".to_string(),
+        }),
+        _ => Err(MssqlError {
+            message: format!("{} rows match the key; it must identify a single row", rows.len()),
+            code: "KEY_NOT_UNIQUE".to_string(),
+        }),
+    }
+}
+
+/// Describe the schemas, tables, views, columns and keys of the connection's
+/// current database, or of `database` if given
+#[tauri::command]
+pub async fn mssql_get_schema(
+    connection_id: String,
+    database: Option<String>,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<MssqlSchemaTree, MssqlError> {
+    let sql = schema_query(database.as_deref().filter(|name| !name.is_empty()));
+    let mut session = get_session(&manager, &connection_id).await?;
+
+    let results = match session.client.query_results(Query::new(sql.as_str())).await {
+        Err(e) if is_connection_error(&e) => {
+            session.recover(&app, &connection_id, e, true).await?;
+            session.client.query_results(Query::new(sql.as_str())).await
+        }
+        result => result,
+    }
+    .map_err(|e| MssqlError {
+        message: format!("Failed to load schema: {}", e),
+        code: "SCHEMA_ERROR".to_string(),
+    })?;
+
+    Ok(build_schema_tree(results))
+}
+
+/// One batch, so the whole tree takes a single round trip however many
+/// tables there are. Catalog views are qualified with the database name
+/// when one is given. Result sets: current database, databases, schemas,
+/// tables and views, columns, primary keys, foreign keys.
+fn schema_query(database: Option<&str>) -> String {
+    let (name, db) = match database {
+        Some(name) => {
+            let quoted = format!("[{}]", name.replace(']', "]]"));
+            (format!("N'{}'", name.replace('\'', "''")), format!("{}.", quoted))
+        }
+        None => ("DB_NAME()".to_string(), String::new()),
+    };
+
+    format!(
+        "SELECT {name} AS database_name; \
+         SELECT name FROM sys.databases WHERE HAS_DBACCESS(name) = 1 ORDER BY name; \
+         SELECT name FROM {db}sys.schemas \
+         WHERE schema_id < 16384 AND name NOT IN ('sys', 'INFORMATION_SCHEMA', 'guest') ORDER BY name; \
+         SELECT s.name, o.name, o.type FROM {db}sys.objects o \
+         JOIN {db}sys.schemas s ON s.schema_id = o.schema_id \
+         WHERE o.type IN ('U', 'V') AND o.is_ms_shipped = 0 ORDER BY s.name, o.name; \
+         SELECT s.name, o.name, c.name, \
+                {column_type}, c.is_nullable, c.is_identity, dc.definition \
+         FROM {db}sys.columns c \
+         JOIN {db}sys.objects o ON o.object_id = c.object_id \
+         JOIN {db}sys.schemas s ON s.schema_id = o.schema_id \
+         JOIN {db}sys.types t ON t.user_type_id = c.user_type_id \
+         LEFT JOIN {db}sys.default_constraints dc ON dc.object_id = c.default_object_id \
+         WHERE o.type IN ('U', 'V') AND o.is_ms_shipped = 0 \
+         ORDER BY s.name, o.name, c.column_id; \
+         SELECT s.name, o.name, c.name FROM {db}sys.indexes i \
+         JOIN {db}sys.index_columns ic ON ic.object_id = i.object_id AND ic.index_id = i.index_id \
+         JOIN {db}sys.columns c ON c.object_id = ic.object_id AND c.column_id = ic.column_id \
+         JOIN {db}sys.objects o ON o.object_id = i.object_id \
+         JOIN {db}sys.schemas s ON s.schema_id = o.schema_id \
+         WHERE i.is_primary_key = 1 AND o.is_ms_shipped = 0 \
+         ORDER BY s.name, o.name, ic.key_ordinal; \
+         SELECT s.name, o.name, fk.name, pc.name, rs.name, ro.name, rc.name \
+         FROM {db}sys.foreign_keys fk \
+         JOIN {db}sys.foreign_key_columns fkc ON fkc.constraint_object_id = fk.object_id \
+         JOIN {db}sys.objects o ON o.object_id = fk.parent_object_id \
+         JOIN {db}sys.schemas s ON s.schema_id = o.schema_id \
+         JOIN {db}sys.columns pc ON pc.object_id = fkc.parent_object_id AND pc.column_id = fkc.parent_column_id \
+         JOIN {db}sys.objects ro ON ro.object_id = fk.referenced_object_id \
+         JOIN {db}sys.schemas rs ON rs.schema_id = ro.schema_id \
+         JOIN {db}sys.columns rc ON rc.object_id = fkc.referenced_object_id AND rc.column_id = fkc.referenced_column_id \
+         WHERE o.is_ms_shipped = 0 \
+         ORDER BY s.name, o.name, fk.name, fkc.constraint_column_id;",
+        name = name,
+        db = db,
+        column_type = type_name_sql("c"),
+    )
+}
+
+/// SQL for a full type name such as `nvarchar(50)` or `decimal(10,2)`.
+/// `source` is the sys.columns or sys.parameters alias; sys.types must be
+/// joined as `t`.
+fn type_name_sql(source: &str) -> String {
+    format!(
+        "CASE WHEN t.name IN ('varchar', 'char', 'varbinary', 'binary') \
+                   THEN t.name + '(' + CASE WHEN {s}.max_length = -1 THEN 'max' ELSE CAST({s}.max_length AS varchar(10)) END + ')' \
+              WHEN t.name IN ('nvarchar', 'nchar') \
+                   THEN t.name + '(' + CASE WHEN {s}.max_length = -1 THEN 'max' ELSE CAST({s}.max_length / 2 AS varchar(10)) END + ')' \
+              WHEN t.name IN ('decimal', 'numeric') \
+                   THEN t.name + '(' + CAST({s}.precision AS varchar(10)) + ',' + CAST({s}.scale AS varchar(10)) + ')' \
+              WHEN t.name IN ('datetime2', 'time', 'datetimeoffset') \
+                   THEN t.name + '(' + CAST({s}.scale AS varchar(10)) + ')' \
+              ELSE t.name END",
+        s = source
+    )
+}
+
+/// Assemble the result sets of schema_query into the nested tree
+fn build_schema_tree(results: Vec<ResultSet>) -> MssqlSchemaTree {
+    let text = |row: &Row, idx: usize| row.try_get::<&str, _>(idx).ok().flatten().unwrap_or_default().to_string();
+    let flag = |row: &Row, idx: usize| row.try_get::<bool, _>(idx).ok().flatten().unwrap_or_default();
+    let mut results = results.into_iter().map(|result| result.rows);
+    let mut next = || results.next().unwrap_or_default();
+
+    let database = next().first().map(|row| text(row, 0)).unwrap_or_default();
+    let databases = next().iter().map(|row| text(row, 0)).collect();
+
+    let mut schemas: std::collections::BTreeMap<String, MssqlSchema> = next()
+        .iter()
+        .map(|row| {
+            let name = text(row, 0);
+            let schema = MssqlSchema {
+                name: name.clone(),
+                tables: Vec::new(),
+                views: Vec::new(),
+            };
+            (name, schema)
+        })
+        .collect();
+
+    // (schema, object) -> (is_view, index into tables/views)
+    let mut object_index: HashMap<(String, String), (bool, usize)> = HashMap::new();
+    for row in next() {
+        let (schema_name, name) = (text(&row, 0), text(&row, 1));
+        let schema = schemas.entry(schema_name.clone()).or_insert_with(|| MssqlSchema {
+            name: schema_name.clone(),
+            tables: Vec::new(),
+            views: Vec::new(),
+        });
+        let table = MssqlTable {
+            name: name.clone(),
+            columns: Vec::new(),
+            primary_key: Vec::new(),
+            foreign_keys: Vec::new(),
+        };
+        // sys.objects.type is char(2), so it comes back padded
+        if text(&row, 2).trim() == "V" {
+            object_index.insert((schema_name, name), (true, schema.views.len()));
+            schema.views.push(table);
+        } else {
+            object_index.insert((schema_name, name), (false, schema.tables.len()));
+            schema.tables.push(table);
+        }
+    }
+
+    // Later result sets start with schema and object name
+    fn table_for<'a>(
+        schemas: &'a mut std::collections::BTreeMap<String, MssqlSchema>,
+        object_index: &HashMap<(String, String), (bool, usize)>,
+        key: (String, String),
+    ) -> Option<&'a mut MssqlTable> {
+        let &(is_view, index) = object_index.get(&key)?;
+        let schema = schemas.get_mut(&key.0)?;
+        Some(if is_view { &mut schema.views[index] } else { &mut schema.tables[index] })
+    }
+
+    for row in next() {
+        if let Some(table) = table_for(&mut schemas, &object_index, (text(&row, 0), text(&row, 1))) {
+            table.columns.push(MssqlColumn {
+                name: text(&row, 2),
+                data_type: text(&row, 3),
+                nullable: flag(&row, 4),
+                identity: flag(&row, 5),
+                default: row.try_get::<&str, _>(6).ok().flatten().map(|d| d.to_string()),
+            });
+        }
+    }
+
+    for row in next() {
+        if let Some(table) = table_for(&mut schemas, &object_index, (text(&row, 0), text(&row, 1))) {
+            table.primary_key.push(text(&row, 2));
+        }
+    }
+
+    // One row per column pair, ordered so a key's columns are adjacent
+    for row in next() {
+        if let Some(table) = table_for(&mut schemas, &object_index, (text(&row, 0), text(&row, 1))) {
+            let name = text(&row, 2);
+            if table.foreign_keys.last().map(|fk| &fk.name) != Some(&name) {
+                table.foreign_keys.push(MssqlForeignKey {
+                    name,
+                    columns: Vec::new(),
+                    referenced_schema: text(&row, 4),
+                    referenced_table: text(&row, 5),
+                    referenced_columns: Vec::new(),
+                });
+            }
+            if let Some(fk) = table.foreign_keys.last_mut() {
+                fk.columns.push(text(&row, 3));
+                fk.referenced_columns.push(text(&row, 6));
+            }
+        }
+    }
+
+    MssqlSchemaTree {
+        database,
+        databases,
+        schemas: schemas.into_values().collect(),
+    }
+}
+
+/// A runnable script that recreates a table: CREATE TABLE with its columns
+/// (identity, computed, defaults), key and check constraints, then the
+/// foreign keys and remaining indexes. Partitioning, compression and
+/// temporal settings are not scripted.
+#[tauri::command]
+pub async fn mssql_get_table_ddl(
+    connection_id: String,
+    schema: String,
+    table: String,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<String, MssqlError> {
+    let sql = table_ddl_query();
+    let params = [serde_json::json!(format!("{}.{}", quote_identifier(&schema), quote_identifier(&table)))];
+    let query = || build_query(&sql, Some(params.as_slice()), None);
+    let mut session = get_session(&manager, &connection_id).await?;
+
+    let results = match session.client.query_results(query()?).await {
+        Err(e) if is_connection_error(&e) => {
+            session.recover(&app, &connection_id, e, true).await?;
+            session.client.query_results(query()?).await
+        }
+        result => result,
+    }
+    .map_err(|e| MssqlError {
+        message: format!("Failed to script table: {}", e),
+        code: "SCHEMA_ERROR".to_string(),
+    })?;
+
+    build_table_ddl(results).ok_or_else(|| MssqlError {
+        message: format!("Table {}.{} not found", schema, table),
+        code: "TABLE_NOT_FOUND".to_string(),
     })
 }
+
+/// One batch for the table named by @P1. Result sets: table, columns, check
+/// constraints, indexes, index columns, foreign keys, foreign key columns.
+/// Identity seeds are sql_variant, which tiberius can't read, so they're cast.
+fn table_ddl_query() -> String {
+    format!(
+        "DECLARE @id int = OBJECT_ID(@P1); \
+         SELECT SCHEMA_NAME(schema_id), name FROM sys.tables WHERE object_id = @id; \
+         SELECT c.name, \
+                CASE WHEN t.is_user_defined = 1 THEN QUOTENAME(SCHEMA_NAME(t.schema_id)) + '.' + QUOTENAME(t.name) \
+                     ELSE {column_type} END, \
+                c.is_nullable, c.is_identity, \
+                CAST(ic.seed_value AS nvarchar(40)), CAST(ic.increment_value AS nvarchar(40)), \
+                cc.definition, cc.is_persisted, dc.name, dc.definition, \
+                CASE WHEN c.collation_name <> CAST(DATABASEPROPERTYEX(DB_NAME(), 'Collation') AS sysname) \
+                     THEN c.collation_name END, \
+                c.is_sparse, c.is_rowguidcol \
+         FROM sys.columns c \
+         JOIN sys.types t ON t.user_type_id = c.user_type_id \
+         LEFT JOIN sys.identity_columns ic ON ic.object_id = c.object_id AND ic.column_id = c.column_id \
+         LEFT JOIN sys.computed_columns cc ON cc.object_id = c.object_id AND cc.column_id = c.column_id \
+         LEFT JOIN sys.default_constraints dc ON dc.object_id = c.default_object_id \
+         WHERE c.object_id = @id \
+         ORDER BY c.column_id; \
+         SELECT name, definition, is_disabled FROM sys.check_constraints \
+         WHERE parent_object_id = @id ORDER BY name; \
+         SELECT index_id, name, type_desc, is_primary_key, is_unique_constraint, is_unique, filter_definition \
+         FROM sys.indexes \
+         WHERE object_id = @id AND type > 0 AND is_hypothetical = 0 AND is_disabled = 0 \
+         ORDER BY index_id; \
+         SELECT ic.index_id, c.name, ic.is_descending_key, ic.is_included_column \
+         FROM sys.index_columns ic \
+         JOIN sys.columns c ON c.object_id = ic.object_id AND c.column_id = ic.column_id \
+         WHERE ic.object_id = @id \
+         ORDER BY ic.index_id, ic.is_included_column, ic.key_ordinal, ic.index_column_id; \
+         SELECT fk.object_id, fk.name, SCHEMA_NAME(r.schema_id), r.name, \
+                fk.delete_referential_action_desc, fk.update_referential_action_desc, \
+                fk.is_disabled, fk.is_not_trusted \
+         FROM sys.foreign_keys fk \
+         JOIN sys.objects r ON r.object_id = fk.referenced_object_id \
+         WHERE fk.parent_object_id = @id \
+         ORDER BY fk.name; \
+         SELECT fkc.constraint_object_id, pc.name, rc.name \
+         FROM sys.foreign_key_columns fkc \
+         JOIN sys.columns pc ON pc.object_id = fkc.parent_object_id AND pc.column_id = fkc.parent_column_id \
+         JOIN sys.columns rc ON rc.object_id = fkc.referenced_object_id AND rc.column_id = fkc.referenced_column_id \
+         WHERE fkc.parent_object_id = @id \
+         ORDER BY fkc.constraint_object_id, fkc.constraint_column_id;",
+        column_type = type_name_sql("c")
+    )
+}
+
+/// Assemble the result sets of table_ddl_query into a script, or None if the
+/// table doesn't exist
+fn build_table_ddl(results: Vec<ResultSet>) -> Option<String> {
+    let text = |row: &Row, idx: usize| row.try_get::<&str, _>(idx).ok().flatten().map(str::to_string);
+    let flag = |row: &Row, idx: usize| row.try_get::<bool, _>(idx).ok().flatten().unwrap_or_default();
+    let id = |row: &Row, idx: usize| row.try_get::<i32, _>(idx).ok().flatten().unwrap_or_default();
+    let mut results = results.into_iter().map(|result| result.rows);
+    let mut next = || results.next().unwrap_or_default();
+
+    let table = next().first().map(|row| {
+        format!(
+            "{}.{}",
+            quote_identifier(&text(row, 0).unwrap_or_default()),
+            quote_identifier(&text(row, 1).unwrap_or_default())
+        )
+    })?;
+
+    let mut lines: Vec<String> = next()
+        .iter()
+        .map(|row| {
+            let mut line = quote_identifier(&text(row, 0).unwrap_or_default());
+            if let Some(expression) = text(row, 6) {
+                line += &format!(" AS {}", expression);
+                if flag(row, 7) {
+                    line += " PERSISTED";
+                    if !flag(row, 2) {
+                        line += " NOT NULL";
+                    }
+                }
+                return line;
+            }
+            line += &format!(" {}", text(row, 1).unwrap_or_default());
+            if let Some(collation) = text(row, 10) {
+                line += &format!(" COLLATE {}", collation);
+            }
+            if flag(row, 11) {
+                line += " SPARSE";
+            }
+            if flag(row, 3) {
+                line += &format!(
+                    " IDENTITY({}, {})",
+                    text(row, 4).unwrap_or_else(|| "1".to_string()),
+                    text(row, 5).unwrap_or_else(|| "1".to_string())
+                );
+            }
+            if flag(row, 12) {
+                line += " ROWGUIDCOL";
+            }
+            line += if flag(row, 2) { " NULL" } else { " NOT NULL" };
+            if let (Some(name), Some(default)) = (text(row, 8), text(row, 9)) {
+                line += &format!(" CONSTRAINT {} DEFAULT {}", quote_identifier(&name), default);
+            }
+            line
+        })
+        .collect();
+
+    // Statements that follow the CREATE TABLE
+    let mut after = Vec::new();
+
+    for row in next() {
+        let name = quote_identifier(&text(&row, 0).unwrap_or_default());
+        lines.push(format!("CONSTRAINT {} CHECK {}", name, text(&row, 1).unwrap_or_default()));
+        if flag(&row, 2) {
+            after.push(format!("ALTER TABLE {} NOCHECK CONSTRAINT {};", table, name));
+        }
+    }
+
+    let indexes = next();
+    let mut index_columns: HashMap<i32, Vec<(String, bool, bool)>> = HashMap::new();
+    for row in next() {
+        let column = quote_identifier(&text(&row, 1).unwrap_or_default());
+        index_columns.entry(id(&row, 0)).or_default().push((column, flag(&row, 2), flag(&row, 3)));
+    }
+
+    let mut create_indexes = Vec::new();
+    for row in &indexes {
+        let name = quote_identifier(&text(row, 1).unwrap_or_default());
+        let kind = text(row, 2).unwrap_or_default();
+        let columns = index_columns.remove(&id(row, 0)).unwrap_or_default();
+        let keys = columns
+            .iter()
+            .filter(|(_, _, included)| !included)
+            .map(|(column, descending, _)| format!("{} {}", column, if *descending { "DESC" } else { "ASC" }))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let included = columns
+            .iter()
+            .filter(|(_, _, included)| *included)
+            .map(|(column, _, _)| column.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let filter = text(row, 6).map(|filter| format!(" WHERE {}", filter)).unwrap_or_default();
+
+        if flag(row, 3) || flag(row, 4) {
+            let constraint = if flag(row, 3) { "PRIMARY KEY" } else { "UNIQUE" };
+            lines.push(format!("CONSTRAINT {} {} {} ({})", name, constraint, kind, keys));
+            continue;
+        }
+        match kind.as_str() {
+            "CLUSTERED" | "NONCLUSTERED" => {
+                let mut statement = format!(
+                    "CREATE {}{} INDEX {} ON {} ({})",
+                    if flag(row, 5) { "UNIQUE " } else { "" },
+                    kind,
+                    name,
+                    table,
+                    keys
+                );
+                if !included.is_empty() {
+                    statement += &format!(" INCLUDE ({})", included);
+                }
+                create_indexes.push(format!("{}{};", statement, filter));
+            }
+            "CLUSTERED COLUMNSTORE" => {
+                create_indexes.push(format!("CREATE CLUSTERED COLUMNSTORE INDEX {} ON {};", name, table));
+            }
+            "NONCLUSTERED COLUMNSTORE" => {
+                let columns = columns.iter().map(|(column, _, _)| column.as_str()).collect::<Vec<_>>().join(", ");
+                create_indexes.push(format!(
+                    "CREATE NONCLUSTERED COLUMNSTORE INDEX {} ON {} ({}){};",
+                    name, table, columns, filter
+                ));
+            }
+            // XML, spatial and other specialised indexes
+            _ => create_indexes.push(format!("-- {} index {} is not scripted", kind, name)),
+        }
+    }
+
+    let foreign_keys = next();
+    let mut fk_columns: HashMap<i32, (Vec<String>, Vec<String>)> = HashMap::new();
+    for row in next() {
+        let columns = fk_columns.entry(id(&row, 0)).or_default();
+        columns.0.push(quote_identifier(&text(&row, 1).unwrap_or_default()));
+        columns.1.push(quote_identifier(&text(&row, 2).unwrap_or_default()));
+    }
+    for row in &foreign_keys {
+        let name = quote_identifier(&text(row, 1).unwrap_or_default());
+        let (columns, referenced) = fk_columns.remove(&id(row, 0)).unwrap_or_default();
+        let mut statement = format!(
+            "ALTER TABLE {} WITH {} ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {}.{} ({})",
+            table,
+            if flag(row, 7) { "NOCHECK" } else { "CHECK" },
+            name,
+            columns.join(", "),
+            quote_identifier(&text(row, 2).unwrap_or_default()),
+            quote_identifier(&text(row, 3).unwrap_or_default()),
+            referenced.join(", ")
+        );
+        for (clause, idx) in [("DELETE", 4), ("UPDATE", 5)] {
+            let action = text(row, idx).unwrap_or_default();
+            if action != "NO_ACTION" && !action.is_empty() {
+                statement += &format!(" ON {} {}", clause, action.replace('_', " "));
+            }
+        }
+        after.push(format!("{};", statement));
+        if flag(row, 6) {
+            after.push(format!("ALTER TABLE {} NOCHECK CONSTRAINT {};", table, name));
+        }
+    }
+
+    let mut script = format!("CREATE TABLE {} (\n    {}\n);\n", table, lines.join(",\n    "));
+    for statement in after.into_iter().chain(create_indexes) {
+        script += &statement;
+        script.push('\n');
+    }
+    Some(script)
+}
+
+/// The source of a view, procedure, function or trigger, as stored in
+/// sys.sql_modules
+#[tauri::command]
+pub async fn mssql_get_object_definition(
+    connection_id: String,
+    schema: String,
+    name: String,
+    app: tauri::AppHandle,
+    manager: State<'_, MssqlConnectionManager>,
+) -> Result<String, MssqlError> {
+    let object = format!("{}.{}", quote_identifier(&schema), quote_identifier(&name));
+    let sql = "SELECT o.type_desc, m.object_id, COALESCE(m.definition, OBJECT_DEFINITION(o.object_id)) \
+               FROM sys.objects o LEFT JOIN sys.sql_modules m ON m.object_id = o.object_id \
+               WHERE o.object_id = OBJECT_ID(@P1)";
+    let mut session = get_session(&manager, &connection_id).await?;
+    let rows = query_rows(&mut session, &app, &connection_id, sql, &[serde_json::json!(object)]).await?;
+
+    let row = rows.first().ok_or_else(|| MssqlError {
+        message: format!("Object {}.{} not found", schema, name),
+        code: "OBJECT_NOT_FOUND".to_string(),
+    })?;
+    if row.try_get::<i32, _>(1).ok().flatten().is_none() {
+        let kind = row.try_get::<&str, _>(0).ok().flatten().unwrap_or_default();
+        return Err(MssqlError {
+            message: format!(
+                "{}.{} is a {}, which has no definition",
+                schema,
+                name,
+                kind.to_lowercase().replace('_', " ")
+            ),
+            code: "NOT_A_MODULE".to_string(),
+        });
+    }
+    // NULL for modules created WITH ENCRYPTION
+    row.try_get::<&str, _>(2)
+        .ok()
+        .flatten()
+        .map(str::to_string)
+        .ok_or_else(|| MssqlError {
+            message: format!("The definition of {}.{} is encrypted", schema, name),
+            code: "DEFINITION_UNAVAILABLE".to_string(),
+        })
+}
+
+/// Owns the connection's client for the cursor's lifetime, answering fetch
+/// requests from the open query stream
+async fn run_cursor(
+    mut session: OwnedMutexGuard<Session>,
+    query: Query<'static>,
+    convert: fn(&Row, usize) -> serde_json::Value,
+    binary_limit: usize,
+    ready: oneshot::Sender<Result<Vec<Column>, MssqlError>>,
+    mut requests: mpsc::Receiver<CursorRequest>,
+) {
+    let query_error = |e: tiberius::error::Error| MssqlError {
+        message: format!("Query failed: {}", e),
+        code: "QUERY_ERROR".to_string(),
+    };
+
+    let mut stream = match session.client.query_stream(query).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            let _ = ready.send(Err(query_error(e)));
+            return;
+        }
+    };
+    let columns = match guard_unsupported_types(stream.columns()).await {
+        Ok(columns) => columns.map(|c| c.to_vec()).unwrap_or_default(),
+        Err(e) => {
+            let broken = is_unreadable_types(&e);
+            let _ = ready.send(Err(query_error(e)));
+            drop(stream);
+            session.broken |= broken;
+            return;
+        }
+    };
+    if ready.send(Ok(columns)).is_err() {
+        return;
+    }
+
+    let mut result_sets = 0;
+    let mut done = false;
+    let mut broken = false;
+    while let Some((batch_size, reply)) = requests.recv().await {
+        let mut rows = Vec::new();
+        let mut error = None;
+        while !done && rows.len() < batch_size {
+            match guard_unsupported_types(stream.try_next()).await {
+                Ok(Some(QueryItem::Row(row))) => rows.push(convert(&row, binary_limit)),
+                Ok(Some(QueryItem::Metadata(_))) => {
+                    // Stop at the start of a second result set
+                    result_sets += 1;
+                    done = result_sets > 1;
+                }
+                Ok(None) => done = true,
+                Err(e) => {
+                    broken = is_unreadable_types(&e);
+                    error = Some(query_error(e));
+                    done = true;
+                }
+            }
+        }
+        let _ = reply.send(match error {
+            Some(e) => Err(e),
+            None => Ok(MssqlCursorBatch { rows, done }),
+        });
+    }
+
+    // Closed early: read the rest of the response so the connection is left
+    // ready for its next command rather than mid-stream
+    while !broken {
+        match guard_unsupported_types(stream.try_next()).await {
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(e) => {
+                broken = is_unreadable_types(&e);
+                break;
+            }
+        }
+    }
+    drop(stream);
+    session.broken |= broken;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONNECT_TIMEOUT_SECS: u64 = 2;
+
+    #[test]
+    fn decimals_keep_every_digit() {
+        // decimal(38,10) at its largest
+        let max = Numeric::new_with_scale(99_999_999_999_999_999_999_999_999_999_999_999_999, 10);
+        assert_eq!(numeric_to_string(max), "9999999999999999999999999999.9999999999");
+        assert_eq!(
+            numeric_to_string(Numeric::new_with_scale(-12_345_678_901, 10)),
+            "-1.2345678901"
+        );
+        assert_eq!(numeric_to_string(Numeric::new_with_scale(-5, 10)), "-0.0000000005");
+        assert_eq!(numeric_to_string(Numeric::new_with_scale(42, 0)), "42");
+    }
+
+    #[test]
+    fn money_shows_four_decimal_places() {
+        assert_eq!(money_to_string(12.5), "12.5000");
+        assert_eq!(money_to_string(-0.0001), "-0.0001");
+        assert_eq!(money_to_string(123_456.7891), "123456.7891");
+    }
+
+    #[test]
+    fn datetimeoffset_keeps_its_offset() {
+        let value = DateTime::parse_from_rfc3339("2024-03-01T09:30:15.1234567+05:30").unwrap();
+        assert_eq!(datetimeoffset_to_string(value), "2024-03-01T09:30:15.123456700+05:30");
+        let value = DateTime::parse_from_rfc3339("1999-12-31T23:59:59-08:00").unwrap();
+        assert_eq!(datetimeoffset_to_string(value), "1999-12-31T23:59:59-08:00");
+    }
+
+    #[test]
+    fn datetimes_keep_their_precision() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        // datetime2(7) ticks are 100ns
+        let datetime2 = date.and_hms_nano_opt(9, 30, 15, 123_456_700).unwrap();
+        assert_eq!(datetime_to_string(datetime2), "2024-03-01T09:30:15.123456700");
+        // smalldatetime has no seconds
+        let smalldatetime = date.and_hms_opt(9, 30, 0).unwrap();
+        assert_eq!(datetime_to_string(smalldatetime), "2024-03-01T09:30:00");
+        assert_eq!(date.to_string(), "2024-03-01");
+    }
+
+    fn silent_config(port: u16, encrypt: &str) -> MssqlConfig {
+        serde_json::from_value(serde_json::json!({
+            "host": "127.0.0.1",
+            "port": port,
+            "database": "master",
+            "username": "sa",
+            "password": "unused",
+            "encrypt": encrypt,
+            "connect_timeout_secs": CONNECT_TIMEOUT_SECS,
+        }))
+        .unwrap()
+    }
+
+    /// A server that accepts connections and never says a word
+    async fn silent_server() -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+        port
+    }
+
+    /// Connect, expecting a failure within the connect timeout
+    async fn connect_error(config: &MssqlConfig) -> MssqlError {
+        let started = Instant::now();
+        let result = tokio::time::timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS * 5), open_client(config))
+            .await
+            .expect("connecting ignored its timeout");
+        let elapsed = started.elapsed();
+        assert!(
+            elapsed < Duration::from_secs(CONNECT_TIMEOUT_SECS) + Duration::from_millis(500),
+            "took {:?}",
+            elapsed
+        );
+        match result {
+            Ok(_) => panic!("connected to a server that never answered"),
+            Err(e) => e,
+        }
+    }
+
+    #[tokio::test]
+    async fn silent_server_times_out_logging_in() {
+        let port = silent_server().await;
+        assert_eq!(connect_error(&silent_config(port, "off")).await.code, "LOGIN_TIMEOUT");
+        assert_eq!(connect_error(&silent_config(port, "on")).await.code, "LOGIN_TIMEOUT");
+    }
+
+    #[tokio::test]
+    async fn silent_server_times_out_in_strict_tls_handshake() {
+        let port = silent_server().await;
+        assert_eq!(connect_error(&silent_config(port, "strict")).await.code, "TLS_TIMEOUT");
+    }
+
+    #[tokio::test]
+    async fn unanswered_connect_times_out() {
+        // A listener that never accepts stops answering SYNs once its
+        // backlog is full, so the next connect hangs
+        let socket = tokio::net::TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let listener = socket.listen(1).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut backlog = Vec::new();
+        for _ in 0..8 {
+            if let Ok(Ok(stream)) = tokio::time::timeout(Duration::from_millis(200), TcpStream::connect(addr)).await {
+                backlog.push(stream);
+            }
+        }
+
+        assert_eq!(connect_error(&silent_config(addr.port(), "off")).await.code, "TCP_TIMEOUT");
+    }
+
+    /// Connect to the server named by SEAQUEL_TEST_MSSQL, an ADO.NET
+    /// connection string such as
+    /// `Server=localhost;User ID=sa;Password=...;TrustServerCertificate=true`
+    fn test_config() -> MssqlConfig {
+        let connection_string =
+            std::env::var("SEAQUEL_TEST_MSSQL").expect("SEAQUEL_TEST_MSSQL isn't set to a connection string");
+        parse_connection_string(&connection_string).unwrap().0
+    }
+
+    async fn test_server() -> MssqlClient {
+        let (client, _) = open_client(&test_config()).await.unwrap();
+        client
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a SQL Server in SEAQUEL_TEST_MSSQL"]
+    async fn empty_result_keeps_its_columns() {
+        let mut client = test_server().await;
+        let results = client
+            .query_results(Query::new("SELECT name, object_id FROM sys.tables WHERE 1=0"))
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].rows.is_empty());
+        let names: Vec<&str> = results[0].columns.iter().map(|c| c.name()).collect();
+        assert_eq!(names, ["name", "object_id"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a SQL Server in SEAQUEL_TEST_MSSQL"]
+    async fn unreadable_columns_are_read_as_text() {
+        let mut session = Session::open(test_config()).await.unwrap();
+        let sql = "SELECT CAST(42 AS sql_variant) AS v, hierarchyid::Parse('/1/2/') AS h, 7 AS n ORDER BY n";
+
+        let error = session.client.query_results(Query::new(sql)).await.unwrap_err();
+        assert!(is_unreadable_types(&error));
+
+        session.client = test_server().await;
+        let (results, types) = session.query_as_text(sql).await.unwrap().unwrap();
+        assert_eq!(types, [Some("sql_variant".to_string()), Some("hierarchyid".to_string()), None]);
+        let row = row_to_array(&results[0].rows[0], usize::MAX);
+        assert_eq!(row, serde_json::json!(["42", "/1/2/", 7]));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a SQL Server in SEAQUEL_TEST_MSSQL"]
+    async fn values_convert_without_loss() {
+        let mut client = test_server().await;
+        let results = client
+            .query_results(Query::new(
+                "SELECT CAST('2024-03-01' AS date), \
+                        CAST('2024-03-01T09:30:15.1234567' AS datetime2(7)), \
+                        CAST('2024-03-01T09:30:20' AS smalldatetime), \
+                        CAST('-123456789012345678.0000000001' AS decimal(38, 10)), \
+                        CAST(12.5 AS money), \
+                        CAST('6F9619FF-8B86-D011-B42D-00C04FC964FF' AS uniqueidentifier), \
+                        CAST('2024-03-01T09:30:15.1234567+05:30' AS datetimeoffset(7))",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            row_to_array(&results[0].rows[0], usize::MAX),
+            serde_json::json!([
+                "2024-03-01",
+                "2024-03-01T09:30:15.123456700",
+                "2024-03-01T09:30:00",
+                "-123456789012345678.0000000001",
+                "12.5000",
+                "6f9619ff-8b86-d011-b42d-00c04fc964ff",
+                "2024-03-01T09:30:15.123456700+05:30",
+            ])
+        );
+    }
+
+    async fn script_table(client: &mut MssqlClient, table: &str) -> String {
+        let query = build_query(&table_ddl_query(), Some(&[serde_json::json!(table)]), None).unwrap();
+        build_table_ddl(client.query_results(query).await.unwrap()).expect("table to script is missing")
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a SQL Server in SEAQUEL_TEST_MSSQL"]
+    async fn table_ddl_recreates_the_table() {
+        let mut client = test_server().await;
+        let cleanup = "DROP TABLE IF EXISTS dbo.seaquel_ddl_child; DROP TABLE IF EXISTS dbo.seaquel_ddl_parent;";
+        client.execute(Query::new(cleanup)).await.unwrap();
+        client
+            .execute(Query::new(
+                "CREATE TABLE dbo.seaquel_ddl_parent (id int NOT NULL CONSTRAINT pk_seaquel_ddl_parent PRIMARY KEY); \
+                 CREATE TABLE dbo.seaquel_ddl_child ( \
+                     id int IDENTITY(10, 5) NOT NULL CONSTRAINT pk_seaquel_ddl_child PRIMARY KEY, \
+                     parent_id int NULL CONSTRAINT fk_seaquel_ddl_child_parent \
+                         REFERENCES dbo.seaquel_ddl_parent (id) ON DELETE CASCADE, \
+                     code nvarchar(20) COLLATE Latin1_General_BIN2 NOT NULL \
+                         CONSTRAINT uq_seaquel_ddl_child_code UNIQUE, \
+                     quantity int NOT NULL CONSTRAINT df_seaquel_ddl_child_quantity DEFAULT (1) \
+                         CONSTRAINT ck_seaquel_ddl_child_quantity CHECK (quantity > 0), \
+                     price decimal(10, 2) NULL, \
+                     total AS (quantity * price) PERSISTED, \
+                     label AS (upper(code)) \
+                 ); \
+                 CREATE INDEX ix_seaquel_ddl_child_parent ON dbo.seaquel_ddl_child (parent_id DESC) INCLUDE (price);",
+            ))
+            .await
+            .unwrap();
+
+        let script = script_table(&mut client, "dbo.seaquel_ddl_child").await;
+        for expected in [
+            "IDENTITY(10, 5)",
+            "[total] AS ([quantity]*[price]) PERSISTED",
+            "CONSTRAINT [df_seaquel_ddl_child_quantity] DEFAULT ((1))",
+            "CONSTRAINT [ck_seaquel_ddl_child_quantity] CHECK",
+            "CONSTRAINT [uq_seaquel_ddl_child_code] UNIQUE",
+            "FOREIGN KEY ([parent_id]) REFERENCES [dbo].[seaquel_ddl_parent] ([id]) ON DELETE CASCADE",
+            "INCLUDE ([price])",
+        ] {
+            assert!(script.contains(expected), "{} missing from:\n{}", expected, script);
+        }
+
+        client.execute(Query::new("DROP TABLE dbo.seaquel_ddl_child")).await.unwrap();
+        client.execute(Query::new(script.clone())).await.unwrap();
+        let recreated = script_table(&mut client, "dbo.seaquel_ddl_child").await;
+        client.execute(Query::new(cleanup)).await.unwrap();
+
+        assert_eq!(recreated, script);
+    }
+}
@@ -0,0 +1,370 @@
+use crate::ssh_tunnel::TunnelError;
+use aes::cipher::block_padding::NoPadding;
+use aes::cipher::{BlockDecryptMut, KeyIvInit};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use russh_keys::ssh_key::encoding::Decode;
+use russh_keys::ssh_key::private::KeypairData;
+use russh_keys::ssh_key::PrivateKey;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::collections::HashMap;
+
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+
+/// A PuTTY key file, as written by PuTTYgen
+struct PpkFile {
+    version: u32,
+    algorithm: String,
+    encryption: String,
+    comment: String,
+    /// `Key: value` headers other than the ones above
+    headers: HashMap<String, String>,
+    public_blob: Vec<u8>,
+    private_blob: Vec<u8>,
+    mac: String,
+}
+
+fn invalid(message: impl Into<String>) -> TunnelError {
+    TunnelError {
+        message: format!("Invalid PuTTY key file: {}", message.into()),
+        code: "KEY_LOAD_ERROR".to_string(),
+    }
+}
+
+/// Load a PPK v2 or v3 key, converting it to the key types russh uses
+pub fn load(contents: &str, passphrase: Option<&str>) -> Result<PrivateKey, TunnelError> {
+    let ppk = parse(contents)?;
+
+    let (private_blob, mac_key) = match ppk.encryption.as_str() {
+        // Unencrypted v2 keys still have a MAC, keyed on the empty passphrase
+        "none" if ppk.version == 2 => (ppk.private_blob.clone(), v2_mac_key("")),
+        "none" => (ppk.private_blob.clone(), Vec::new()),
+        "aes256-cbc" => {
+            let passphrase = passphrase.ok_or(TunnelError {
+                message: "The SSH key is encrypted; enter its passphrase".to_string(),
+                code: "KEY_ENCRYPTED_NO_PASSPHRASE".to_string(),
+            })?;
+            let (key, iv, mac_key) = derive_keys(&ppk, passphrase)?;
+            let mut blob = ppk.private_blob.clone();
+            Aes256CbcDec::new_from_slices(&key, &iv)
+                .map_err(|e| invalid(e.to_string()))?
+                .decrypt_padded_mut::<NoPadding>(&mut blob)
+                .map_err(|_| invalid("the private key isn't a whole number of blocks"))?;
+            (blob, mac_key)
+        }
+        other => return Err(invalid(format!("unknown encryption {}", other))),
+    };
+
+    if !verify_mac(&ppk, &private_blob, &mac_key)? {
+        // A wrong passphrase decrypts to garbage, which the MAC catches
+        if ppk.encryption != "none" {
+            return Err(TunnelError {
+                message: "Wrong passphrase for the SSH key".to_string(),
+                code: "KEY_WRONG_PASSPHRASE".to_string(),
+            });
+        }
+        return Err(invalid("the MAC doesn't match, so the file is damaged"));
+    }
+    to_private_key(&ppk, &private_blob)
+}
+
+fn parse(contents: &str) -> Result<PpkFile, TunnelError> {
+    let mut lines = contents.lines().map(str::trim_end).filter(|line| !line.is_empty());
+
+    let first = lines.next().unwrap_or_default();
+    let (key, algorithm) = first.split_once(':').ok_or_else(|| invalid("missing header"))?;
+    let version = match key.strip_prefix("PuTTY-User-Key-File-") {
+        Some("2") => 2,
+        Some("3") => 3,
+        Some(other) => {
+            return Err(TunnelError {
+                message: format!(
+                    "PuTTY key file version {} isn't supported; convert it with a current PuTTYgen",
+                    other
+                ),
+                code: "PPK_UNSUPPORTED_VERSION".to_string(),
+            });
+        }
+        None => return Err(invalid("missing header")),
+    };
+
+    let mut headers = HashMap::new();
+    let mut public_blob = None;
+    let mut private_blob = None;
+    while let Some(line) = lines.next() {
+        // PuTTYgen writes `Comment: ` for an empty comment, which loses its
+        // trailing space along with the line ending
+        let (key, value) = line
+            .split_once(':')
+            .map(|(key, value)| (key, value.trim()))
+            .ok_or_else(|| invalid(format!("unexpected line: {}", line)))?;
+        match key {
+            "Public-Lines" | "Private-Lines" => {
+                let count: usize = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid(format!("bad {}: {}", key, value)))?;
+                let encoded: String = lines.by_ref().take(count).collect();
+                let blob = base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .map_err(|e| invalid(format!("bad base64 in {}: {}", key, e)))?;
+                if key == "Public-Lines" {
+                    public_blob = Some(blob);
+                } else {
+                    private_blob = Some(blob);
+                }
+            }
+            _ => {
+                headers.insert(key.to_string(), value.to_string());
+            }
+        }
+    }
+
+    let mut take = |key: &str| headers.remove(key).ok_or_else(|| invalid(format!("missing {}", key)));
+    let encryption = take("Encryption")?;
+    let comment = take("Comment")?;
+    let mac = take("Private-MAC")?;
+    Ok(PpkFile {
+        version,
+        algorithm: algorithm.trim().to_string(),
+        encryption,
+        comment,
+        headers,
+        public_blob: public_blob.ok_or_else(|| invalid("missing Public-Lines"))?,
+        private_blob: private_blob.ok_or_else(|| invalid("missing Private-Lines"))?,
+        mac,
+    })
+}
+
+/// The cipher key, IV and MAC key for an encrypted key file
+fn derive_keys(ppk: &PpkFile, passphrase: &str) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>), TunnelError> {
+    if ppk.version == 2 {
+        let mut key = Vec::with_capacity(40);
+        for counter in 0u32..2 {
+            let mut hasher = Sha1::new();
+            hasher.update(counter.to_be_bytes());
+            hasher.update(passphrase.as_bytes());
+            key.extend_from_slice(&hasher.finalize());
+        }
+        key.truncate(32);
+        return Ok((key, vec![0; 16], v2_mac_key(passphrase)));
+    }
+
+    let header = |key: &str| {
+        ppk.headers
+            .get(key)
+            .map(String::as_str)
+            .ok_or_else(|| invalid(format!("missing {}", key)))
+    };
+    let number = |key: &str| -> Result<u32, TunnelError> {
+        header(key)?.parse().map_err(|_| invalid(format!("bad {}", key)))
+    };
+    let algorithm = match header("Key-Derivation")? {
+        "Argon2id" => Algorithm::Argon2id,
+        "Argon2i" => Algorithm::Argon2i,
+        "Argon2d" => Algorithm::Argon2d,
+        other => return Err(invalid(format!("unknown key derivation {}", other))),
+    };
+    let salt = decode_hex(header("Argon2-Salt")?).ok_or_else(|| invalid("bad Argon2-Salt"))?;
+    let params = Params::new(
+        number("Argon2-Memory")?,
+        number("Argon2-Passes")?,
+        number("Argon2-Parallelism")?,
+        Some(80),
+    )
+    .map_err(|e| invalid(format!("bad Argon2 parameters: {}", e)))?;
+
+    let mut derived = [0u8; 80];
+    Argon2::new(algorithm, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut derived)
+        .map_err(|e| invalid(format!("key derivation failed: {}", e)))?;
+    Ok((derived[..32].to_vec(), derived[32..48].to_vec(), derived[48..].to_vec()))
+}
+
+fn v2_mac_key(passphrase: &str) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(b"putty-private-key-file-mac-key");
+    hasher.update(passphrase.as_bytes());
+    hasher.finalize().to_vec()
+}
+
+/// Check the MAC over the key's algorithm, encryption, comment and both
+/// blobs: HMAC-SHA1 for v2 and HMAC-SHA256 for v3
+fn verify_mac(ppk: &PpkFile, private_blob: &[u8], mac_key: &[u8]) -> Result<bool, TunnelError> {
+    let mut data = Vec::new();
+    for field in [
+        ppk.algorithm.as_bytes(),
+        ppk.encryption.as_bytes(),
+        ppk.comment.as_bytes(),
+        &ppk.public_blob,
+        private_blob,
+    ] {
+        write_string(&mut data, field);
+    }
+
+    let expected = match ppk.version {
+        2 => {
+            let mut mac = Hmac::<Sha1>::new_from_slice(mac_key).map_err(|e| invalid(e.to_string()))?;
+            mac.update(&data);
+            mac.finalize().into_bytes().to_vec()
+        }
+        _ => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(mac_key).map_err(|e| invalid(e.to_string()))?;
+            mac.update(&data);
+            mac.finalize().into_bytes().to_vec()
+        }
+    };
+    Ok(decode_hex(&ppk.mac).is_some_and(|mac| mac == expected))
+}
+
+/// Rebuild the key in OpenSSH's private key layout, which differs from
+/// PuTTY's only in how the fields are split and ordered
+fn to_private_key(ppk: &PpkFile, private_blob: &[u8]) -> Result<PrivateKey, TunnelError> {
+    let public = read_strings(&ppk.public_blob).ok_or_else(|| invalid("damaged public key"))?;
+    let private = read_strings(private_blob).ok_or_else(|| invalid("damaged private key"))?;
+    let field = |fields: &[Vec<u8>], i: usize| -> Result<Vec<u8>, TunnelError> {
+        fields.get(i).cloned().ok_or_else(|| invalid("damaged key"))
+    };
+
+    // PuTTY pads the private blob to the cipher block size, so it may have
+    // more strings than the key needs; only the leading ones count
+    let fields: Vec<Vec<u8>> = match ppk.algorithm.as_str() {
+        "ssh-rsa" => {
+            // Public: e, n. Private: d, p, q, iqmp. OpenSSH: n, e, d, iqmp, p, q.
+            vec![
+                field(&public, 2)?,
+                field(&public, 1)?,
+                field(&private, 0)?,
+                field(&private, 3)?,
+                field(&private, 1)?,
+                field(&private, 2)?,
+            ]
+        }
+        "ssh-dss" => {
+            // Public: p, q, g, y. Private: x.
+            let mut fields: Vec<Vec<u8>> = public.iter().skip(1).take(4).cloned().collect();
+            fields.push(field(&private, 0)?);
+            fields
+        }
+        "ecdsa-sha2-nistp256" | "ecdsa-sha2-nistp384" | "ecdsa-sha2-nistp521" => {
+            // Public: curve, point. Private: scalar.
+            vec![field(&public, 1)?, field(&public, 2)?, field(&private, 0)?]
+        }
+        "ssh-ed25519" => {
+            // OpenSSH keeps the public half alongside the private one
+            let public_key = field(&public, 1)?;
+            let mut keypair = field(&private, 0)?;
+            keypair.extend_from_slice(&public_key);
+            vec![public_key, keypair]
+        }
+        other => {
+            return Err(TunnelError {
+                message: format!("PuTTY keys of type {} aren't supported", other),
+                code: "KEY_FORMAT_UNKNOWN".to_string(),
+            });
+        }
+    };
+
+    let mut encoded = Vec::new();
+    write_string(&mut encoded, ppk.algorithm.as_bytes());
+    for field in &fields {
+        write_string(&mut encoded, field);
+    }
+    let keypair = KeypairData::decode(&mut encoded.as_slice()).map_err(|e| invalid(e.to_string()))?;
+    PrivateKey::new(keypair, ppk.comment.clone()).map_err(|e| invalid(e.to_string()))
+}
+
+/// Split SSH wire-format data into its length-prefixed strings, stopping at
+/// trailing bytes too short to be one
+fn read_strings(mut data: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut fields = Vec::new();
+    while data.len() >= 4 {
+        let len = u32::from_be_bytes(data[..4].try_into().ok()?) as usize;
+        let rest = &data[4..];
+        if rest.len() < len {
+            break;
+        }
+        fields.push(rest[..len].to_vec());
+        data = &rest[len..];
+    }
+    (!fields.is_empty()).then_some(fields)
+}
+
+fn write_string(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use russh_keys::ssh_key::private::Ed25519Keypair;
+
+    // Ed25519 keys made from the seed [7; 32], following PuTTY's key file
+    // format. Encrypted ones use PASSPHRASE; the v3 one with small Argon2
+    // parameters so the test stays quick.
+    const V2: &str = include_str!("../tests/fixtures/ed25519-v2.ppk");
+    const V2_ENCRYPTED: &str = include_str!("../tests/fixtures/ed25519-v2-encrypted.ppk");
+    const V3: &str = include_str!("../tests/fixtures/ed25519-v3.ppk");
+    const V3_ENCRYPTED: &str = include_str!("../tests/fixtures/ed25519-v3-encrypted.ppk");
+    const V1: &str = include_str!("../tests/fixtures/v1.ppk");
+    const PASSPHRASE: &str = "correct horse";
+
+    fn assert_seed_key(key: PrivateKey, comment: &str) {
+        let expected = PrivateKey::from(Ed25519Keypair::from_seed(&[7; 32]));
+        assert_eq!(key.public_key().key_data(), expected.public_key().key_data());
+        assert_eq!(key.key_data().ed25519().unwrap().private.to_bytes(), [7; 32]);
+        assert_eq!(key.comment(), comment);
+    }
+
+    fn error_code(contents: &str, passphrase: Option<&str>) -> String {
+        match load(contents, passphrase) {
+            Ok(_) => panic!("key loaded"),
+            Err(e) => e.code,
+        }
+    }
+
+    #[test]
+    fn loads_unencrypted_keys() {
+        // The v2 fixture has an empty comment
+        assert_seed_key(load(V2, None).unwrap(), "");
+        assert_seed_key(load(V3, None).unwrap(), "seaquel-test");
+    }
+
+    #[test]
+    fn loads_encrypted_keys_with_their_passphrase() {
+        assert_seed_key(load(V2_ENCRYPTED, Some(PASSPHRASE)).unwrap(), "seaquel-test");
+        assert_seed_key(load(V3_ENCRYPTED, Some(PASSPHRASE)).unwrap(), "seaquel-test");
+    }
+
+    #[test]
+    fn rejects_a_wrong_or_missing_passphrase() {
+        assert_eq!(error_code(V2_ENCRYPTED, Some("wrong")), "KEY_WRONG_PASSPHRASE");
+        assert_eq!(error_code(V3_ENCRYPTED, Some("wrong")), "KEY_WRONG_PASSPHRASE");
+        assert_eq!(error_code(V3_ENCRYPTED, None), "KEY_ENCRYPTED_NO_PASSPHRASE");
+    }
+
+    #[test]
+    fn rejects_version_1_files() {
+        assert_eq!(error_code(V1, None), "PPK_UNSUPPORTED_VERSION");
+    }
+
+    #[test]
+    fn rejects_a_damaged_mac() {
+        let damaged = V3.replace("Private-MAC: 5e", "Private-MAC: 00");
+        assert_eq!(error_code(&damaged, None), "KEY_LOAD_ERROR");
+    }
+}
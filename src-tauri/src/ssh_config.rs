@@ -0,0 +1,355 @@
+use crate::ssh_tunnel::{SshHop, TunnelConfig, TunnelError};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Nested Include and ProxyJump lookups stop here, which also breaks cycles
+const MAX_DEPTH: usize = 16;
+
+/// What ~/.ssh/config says about a host alias, as `ssh -G` would resolve it
+#[derive(Debug, Default, Serialize)]
+pub struct SshHostConfig {
+    pub alias: String,
+    pub host_name: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_files: Vec<String>,
+    /// Jump hosts in the order they're passed through, each resolved in turn
+    pub proxy_jump: Vec<SshHostConfig>,
+}
+
+/// Settings in the order they're found; OpenSSH keeps the first value of each
+#[derive(Default)]
+struct Settings {
+    host_name: Option<String>,
+    user: Option<String>,
+    port: Option<String>,
+    identity_files: Vec<String>,
+    proxy_jump: Option<String>,
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".ssh").join("config"))
+}
+
+/// Resolve `alias` against the user's OpenSSH config. A missing config file
+/// isn't an error; the alias just resolves to itself.
+fn resolve(alias: &str, depth: usize) -> Result<SshHostConfig, TunnelError> {
+    let mut settings = Settings::default();
+    if let Some(path) = user_config_path() {
+        read_config(&path, alias, true, &mut settings, depth);
+    }
+
+    let port = settings
+        .port
+        .map(|port| {
+            port.parse::<u16>().map_err(|_| TunnelError {
+                message: format!("Invalid Port for {} in ssh config: {}", alias, port),
+                code: "SSH_CONFIG_ERROR".to_string(),
+            })
+        })
+        .transpose()?;
+    let host_name = settings.host_name.map(|name| expand_tokens(&name, alias, alias, None));
+    let host = host_name.clone().unwrap_or_else(|| alias.to_string());
+    let identity_files = settings
+        .identity_files
+        .iter()
+        .map(|file| expand_tokens(file, alias, &host, settings.user.as_deref()))
+        .collect();
+
+    let mut proxy_jump = Vec::new();
+    if let Some(jumps) = settings.proxy_jump.filter(|jumps| !jumps.eq_ignore_ascii_case("none")) {
+        if depth >= MAX_DEPTH {
+            return Err(TunnelError {
+                message: format!("ProxyJump for {} nests too deeply", alias),
+                code: "SSH_CONFIG_ERROR".to_string(),
+            });
+        }
+        for jump in jumps.split(',').map(str::trim).filter(|jump| !jump.is_empty()) {
+            let (user, rest) = match jump.rsplit_once('@') {
+                Some((user, rest)) => (Some(user.to_string()), rest),
+                None => (None, jump),
+            };
+            let (jump_alias, port) = split_host_port(rest);
+            let mut hop = resolve(jump_alias, depth + 1)?;
+            hop.user = user.or(hop.user);
+            hop.port = match port {
+                Some(port) => Some(port.parse().map_err(|_| TunnelError {
+                    message: format!("Invalid ProxyJump port for {}: {}", alias, jump),
+                    code: "SSH_CONFIG_ERROR".to_string(),
+                })?),
+                None => hop.port,
+            };
+            // A jump host's own jumps come before it
+            proxy_jump.append(&mut hop.proxy_jump);
+            proxy_jump.push(hop);
+        }
+    }
+
+    Ok(SshHostConfig {
+        alias: alias.to_string(),
+        host_name,
+        user: settings.user,
+        port,
+        identity_files,
+        proxy_jump,
+    })
+}
+
+/// Split `host:port` or `[host]:port`
+fn split_host_port(value: &str) -> (&str, Option<&str>) {
+    if let Some(rest) = value.strip_prefix('[') {
+        if let Some((host, after)) = rest.split_once(']') {
+            return (host, after.strip_prefix(':'));
+        }
+    }
+    match value.split_once(':') {
+        Some((host, port)) if !port.contains(':') => (host, Some(port)),
+        _ => (value, None),
+    }
+}
+
+/// Apply the lines of one config file. `active` says whether the lines
+/// before the first Host belong to this host, which matters for files pulled
+/// in by an Include inside a Host block.
+fn read_config(path: &Path, alias: &str, mut active: bool, settings: &mut Settings, depth: usize) {
+    if depth >= MAX_DEPTH {
+        return;
+    }
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return;
+    };
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.find(|c: char| c.is_whitespace() || c == '=') {
+            Some(idx) => (&line[..idx], line[idx..].trim_start_matches(|c: char| c.is_whitespace() || c == '=')),
+            None => (line, ""),
+        };
+        let value = unquote(value.trim());
+
+        match key.to_ascii_lowercase().as_str() {
+            "host" => active = host_matches(value, alias),
+            // Only `Match all` is understood; other criteria never match
+            "match" => active = value.eq_ignore_ascii_case("all"),
+            _ if !active => {}
+            "include" => {
+                for pattern in value.split_whitespace() {
+                    for file in include_paths(pattern) {
+                        read_config(&file, alias, true, settings, depth + 1);
+                    }
+                }
+            }
+            "hostname" => {
+                settings.host_name.get_or_insert_with(|| value.to_string());
+            }
+            "user" => {
+                settings.user.get_or_insert_with(|| value.to_string());
+            }
+            "port" => {
+                settings.port.get_or_insert_with(|| value.to_string());
+            }
+            "identityfile" => settings.identity_files.push(value.to_string()),
+            "proxyjump" => {
+                settings.proxy_jump.get_or_insert_with(|| value.to_string());
+            }
+            _ => {}
+        }
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// A Host line matches when any pattern matches and no negated one does
+fn host_matches(patterns: &str, alias: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns.split_whitespace() {
+        match pattern.strip_prefix('!') {
+            Some(negated) if wildcard_match(negated, alias) => return false,
+            Some(_) => {}
+            None => matched |= wildcard_match(pattern, alias),
+        }
+    }
+    matched
+}
+
+/// `*` and `?` globbing, ignoring case
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(&'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Files named by an Include pattern. Relative paths are under ~/.ssh, and
+/// wildcards are allowed in the file name.
+fn include_paths(pattern: &str) -> Vec<PathBuf> {
+    let Some(home) = dirs::home_dir() else {
+        return Vec::new();
+    };
+    let path = match pattern.strip_prefix("~/") {
+        Some(rest) => home.join(rest),
+        None if Path::new(pattern).is_absolute() => PathBuf::from(pattern),
+        None => home.join(".ssh").join(pattern),
+    };
+
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    if !file_name.contains(['*', '?']) {
+        return vec![path];
+    }
+    let Some(dir) = path.parent() else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| wildcard_match(file_name, name))
+        })
+        .map(|entry| entry.path())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Expand `~` and the %-tokens that make sense without a connection
+fn expand_tokens(value: &str, alias: &str, host: &str, user: Option<&str>) -> String {
+    let home = dirs::home_dir().map(|home| home.to_string_lossy().into_owned()).unwrap_or_default();
+    let value = match value.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("{}{}", home, rest),
+        _ => value.to_string(),
+    };
+
+    let mut expanded = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('d') => expanded.push_str(&home),
+            Some('h') => expanded.push_str(host),
+            Some('n') => expanded.push_str(alias),
+            Some('r') => expanded.push_str(user.unwrap_or_default()),
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+    expanded
+}
+
+/// The first IdentityFile that exists, with the auth method it implies.
+/// Without one the agent is tried, as ssh itself would.
+fn default_auth(identity_files: &[String]) -> (String, Option<String>) {
+    match identity_files.iter().find(|file| Path::new(file).exists()) {
+        Some(file) => ("key".to_string(), Some(file.clone())),
+        None => ("agent".to_string(), None),
+    }
+}
+
+impl SshHostConfig {
+    fn to_hop(&self) -> SshHop {
+        let (auth_method, key_path) = default_auth(&self.identity_files);
+        SshHop {
+            host: self.host_name.clone().unwrap_or_else(|| self.alias.clone()),
+            port: self.port.unwrap_or(22),
+            username: self.user.clone().unwrap_or_default(),
+            auth_method,
+            auth_methods: Vec::new(),
+            password: None,
+            key_path,
+            key_passphrase: None,
+            cert_path: None,
+        }
+    }
+}
+
+/// Fill in whatever the tunnel config leaves out from ~/.ssh/config, treating
+/// `ssh_host` as an alias. Values given explicitly always win.
+pub fn apply_ssh_config(config: &mut TunnelConfig) -> Result<(), TunnelError> {
+    let resolved = resolve(&config.ssh_host, 0)?;
+
+    if let Some(host_name) = &resolved.host_name {
+        config.ssh_host = host_name.clone();
+    }
+    if config.ssh_port == 0 {
+        config.ssh_port = resolved.port.unwrap_or(22);
+    }
+    if config.ssh_username.is_empty() {
+        config.ssh_username = resolved.user.clone().unwrap_or_default();
+    }
+    if config.auth_method.is_empty() && config.auth_methods.is_empty() {
+        let (auth_method, key_path) = default_auth(&resolved.identity_files);
+        config.auth_method = auth_method;
+        if config.key_path.is_none() {
+            config.key_path = key_path;
+        }
+    }
+    if config.jump_hosts.is_empty() {
+        // Like the target, a jump host needs a User rather than an empty login
+        if let Some(hop) = resolved.proxy_jump.iter().find(|hop| hop.user.is_none()) {
+            return Err(TunnelError {
+                message: format!(
+                    "No SSH username configured for jump host {}; add a User for it or use user@{} in ProxyJump",
+                    hop.alias, hop.alias
+                ),
+                code: "AUTH_ERROR".to_string(),
+            });
+        }
+        config.jump_hosts = resolved.proxy_jump.iter().map(SshHostConfig::to_hop).collect();
+    }
+
+    if config.ssh_username.is_empty() {
+        return Err(TunnelError {
+            message: format!("No SSH username given or configured for {}", resolved.alias),
+            code: "AUTH_ERROR".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Resolve a host alias from ~/.ssh/config (following Include directives),
+/// for filling in the tunnel form
+#[tauri::command]
+pub async fn ssh_resolve_host_config(alias: String) -> Result<SshHostConfig, TunnelError> {
+    resolve(&alias, 0)
+}
@@ -13,37 +13,93 @@ export * as sshTunnel from '$lib/services/ssh-tunnel';
 
 // === DuckDB Commands ===
 
+export interface DuckDBSettings {
+	memory_limit?: string;
+	threads?: number;
+	temp_directory?: string;
+	extension_directory?: string;
+	allow_unsigned_extensions?: boolean;
+	http_proxy?: string;
+	http_proxy_username?: string;
+	http_proxy_password?: string;
+}
+
 export interface DuckDBConnectResult {
 	connection_id: string;
+	settings: {
+		memory_limit: string;
+		threads: number;
+		temp_directory: string;
+	};
 }
 
 export interface DuckDBQueryResult {
 	columns: string[];
+	column_types: string[];
+	column_hints: (string | null)[];
 	rows: unknown[][];
+	row_count: number;
+	execution_ms: number;
+	truncated: boolean;
+	total_rows_estimate: number | null;
 }
 
 export interface DuckDBExecuteResult {
 	rows_affected: number;
+	execution_ms: number;
+	statement_type: string;
 }
 
-export async function duckdbConnect(path: string): Promise<DuckDBConnectResult> {
-	return invoke<DuckDBConnectResult>('duckdb_connect', { path });
+export async function duckdbConnect(
+	path: string,
+	readOnly?: boolean,
+	settings?: DuckDBSettings,
+	motherduckToken?: string,
+	sharedMemoryName?: string
+): Promise<DuckDBConnectResult> {
+	return invoke<DuckDBConnectResult>('duckdb_connect', {
+		path,
+		readOnly,
+		settings,
+		motherduckToken,
+		sharedMemoryName
+	});
 }
 
 export async function duckdbDisconnect(connectionId: string): Promise<void> {
 	await invoke('duckdb_disconnect', { connectionId });
 }
 
-export async function duckdbQuery(connectionId: string, sql: string): Promise<DuckDBQueryResult> {
-	return invoke<DuckDBQueryResult>('duckdb_query', { connectionId, sql });
+export async function duckdbQuery(
+	connectionId: string,
+	sql: string,
+	params?: unknown[],
+	maxRows?: number,
+	maxInlineBlobBytes?: number
+): Promise<DuckDBQueryResult> {
+	return invoke<DuckDBQueryResult>('duckdb_query', {
+		connectionId,
+		sql,
+		params,
+		maxRows,
+		maxInlineBlobBytes
+	});
 }
 
-export async function duckdbExecute(connectionId: string, sql: string): Promise<DuckDBExecuteResult> {
-	return invoke<DuckDBExecuteResult>('duckdb_execute', { connectionId, sql });
+export async function duckdbExecute(
+	connectionId: string,
+	sql: string,
+	params?: unknown[]
+): Promise<DuckDBExecuteResult> {
+	return invoke<DuckDBExecuteResult>('duckdb_execute', { connectionId, sql, params });
 }
 
-export async function duckdbTest(path: string): Promise<void> {
-	await invoke('duckdb_test', { path });
+export async function duckdbTest(
+	path: string,
+	readOnly?: boolean,
+	motherduckToken?: string
+): Promise<void> {
+	await invoke('duckdb_test', { path, readOnly, motherduckToken });
 }
 
 // === App Commands ===
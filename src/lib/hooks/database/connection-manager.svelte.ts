@@ -8,7 +8,7 @@ import type { StateRestorationManager } from "./state-restoration.svelte.js";
 import type { TabOrderingManager } from "./tab-ordering.svelte.js";
 import { getAdapter, type DatabaseAdapter } from "$lib/db";
 import { createSshTunnel, closeSshTunnel } from "$lib/services/ssh-tunnel";
-import { mssqlConnect, mssqlDisconnect, mssqlQuery } from "$lib/services/mssql";
+import { mssqlConnect, mssqlDisconnect, mssqlQuery, mssqlTestConnection } from "$lib/services/mssql";
 import type { ProviderRegistry } from "$lib/providers";
 import { isTauri, isDemo } from "$lib/utils/environment";
 import { getKeyringService } from "$lib/services/keyring";
@@ -202,7 +202,7 @@ export class ConnectionManager {
         database: connection.databaseName,
         username: connection.username,
         password: connection.password,
-        encrypt: connection.sslMode !== "disable",
+        encrypt: connection.sslMode === "disable" ? "off" : "on",
         trustCert: connection.sslMode !== "require",
       });
       mssqlConnectionId = mssqlConn.connectionId;
@@ -355,7 +355,7 @@ export class ConnectionManager {
         database: connection.databaseName,
         username: connection.username,
         password: connection.password,
-        encrypt: connection.sslMode !== "disable",
+        encrypt: connection.sslMode === "disable" ? "off" : "on",
         trustCert: connection.sslMode !== "require",
       });
       mssqlConnectionId = mssqlConn.connectionId;
@@ -546,17 +546,15 @@ export class ConnectionManager {
         }
         const host = tunnelLocalPort ? "127.0.0.1" : connection.host;
         const port = tunnelLocalPort || connection.port;
-        const mssqlConn = await mssqlConnect({
+        await mssqlTestConnection({
           host,
           port,
           database: connection.databaseName,
           username: connection.username,
           password: connection.password,
-          encrypt: connection.sslMode !== "disable",
+          encrypt: connection.sslMode === "disable" ? "off" : "on",
           trustCert: connection.sslMode !== "require",
         });
-        // Close the test connection immediately
-        await mssqlDisconnect(mssqlConn.connectionId);
       } else if (connection.type === "duckdb") {
         // DuckDB uses dedicated provider
         const duckdbProvider = await this.providers.getOrCreateDuckDB();
@@ -7,7 +7,7 @@ import { detectQueryType, isWriteQuery, extractTableFromSelect } from "$lib/db/q
 import { splitSqlStatements, getStatementAtOffset } from "$lib/db/sql-parser";
 import { substituteParameters } from "$lib/db/query-params";
 import { m } from "$lib/paraglide/messages.js";
-import { mssqlQuery, mssqlExecute } from "$lib/services/mssql";
+import { mssqlQuery, mssqlQueryArrays, mssqlExecute, arrayRowsToObjects } from "$lib/services/mssql";
 import type { ProviderRegistry } from "$lib/providers";
 import { extractErrorMessage } from "$lib/errors";
 
@@ -167,8 +167,8 @@ export class QueryExecutionManager {
     let resultColumns: string[] = [];
     if (isMssql) {
       // MSSQL: bind values are already inlined
-      const result = await mssqlQuery(connection.mssqlConnectionId!, paginatedQuery);
-      dbResult = result.rows as Record<string, unknown>[];
+      const result = arrayRowsToObjects(await mssqlQueryArrays(connection.mssqlConnectionId!, paginatedQuery));
+      dbResult = result.rows;
       resultColumns = result.columns;
     } else if (providerConnectionId) {
       const provider = await this.providers.getForType(connection.type);
@@ -4,7 +4,14 @@
  */
 
 import { invoke } from '@tauri-apps/api/core';
-import type { GitCredentials, RepoStatus, SyncResult, ConflictContent } from '$lib/types';
+import type {
+	GitCredentials,
+	RepoStatus,
+	SyncResult,
+	ConflictContent,
+	BranchInfo,
+	CommitInfo
+} from '$lib/types';
 
 /**
  * Clone a Git repository to a local path.
@@ -120,6 +127,54 @@ export async function getRemoteUrl(path: string): Promise<string | null> {
 	return invoke<string | null>('git_get_remote_url', { path });
 }
 
+/**
+ * List local and remote branches, local ones first.
+ */
+export async function listBranches(path: string): Promise<BranchInfo[]> {
+	const branches = await invoke<RustBranchInfo[]>('git_list_branches', { path });
+	return branches.map(fromRustBranchInfo);
+}
+
+/**
+ * Create a branch at `fromRef` (HEAD by default) without checking it out.
+ */
+export async function createBranch(path: string, name: string, fromRef?: string): Promise<void> {
+	await invoke('git_create_branch', { path, name, fromRef: fromRef ?? null });
+}
+
+/**
+ * Check out a branch. Fails with WORKTREE_DIRTY when tracked files have
+ * uncommitted changes, unless `force` discards them.
+ */
+export async function checkoutBranch(path: string, name: string, force = false): Promise<void> {
+	await invoke('git_checkout_branch', { path, name, force });
+}
+
+/**
+ * Delete a local branch. Fails with BRANCH_NOT_MERGED for unmerged work
+ * unless `force`.
+ */
+export async function deleteBranch(path: string, name: string, force = false): Promise<void> {
+	await invoke('git_delete_branch', { path, name, force });
+}
+
+/**
+ * Get commit history, newest first. With `filePath`, only commits that
+ * changed that file are returned.
+ */
+export async function getLog(
+	path: string,
+	options: { limit?: number; skip?: number; filePath?: string } = {}
+): Promise<CommitInfo[]> {
+	const commits = await invoke<RustCommitInfo[]>('git_get_log', {
+		path,
+		limit: options.limit ?? null,
+		skip: options.skip ?? null,
+		filePath: options.filePath ?? null
+	});
+	return commits.map(fromRustCommitInfo);
+}
+
 // === Type conversion helpers (Rust uses snake_case, TS uses camelCase) ===
 
 interface RustCredentials {
@@ -147,6 +202,27 @@ interface RustRepoStatus {
 	untracked_files: string[];
 }
 
+interface RustBranchInfo {
+	name: string;
+	is_remote: boolean;
+	is_head: boolean;
+	upstream: string | null;
+	last_commit_id: string | null;
+	last_commit_summary: string | null;
+	last_commit_time: number | null;
+}
+
+interface RustCommitInfo {
+	id: string;
+	short_id: string;
+	author_name: string;
+	author_email: string;
+	timestamp: number;
+	summary: string;
+	is_merge: boolean;
+	files: string[];
+}
+
 function toRustCredentials(creds: GitCredentials): RustCredentials {
 	return {
 		username: creds.username ?? null,
@@ -177,3 +253,28 @@ function fromRustRepoStatus(status: RustRepoStatus): RepoStatus {
 		untrackedFiles: status.untracked_files
 	};
 }
+
+function fromRustBranchInfo(branch: RustBranchInfo): BranchInfo {
+	return {
+		name: branch.name,
+		isRemote: branch.is_remote,
+		isHead: branch.is_head,
+		upstream: branch.upstream,
+		lastCommitId: branch.last_commit_id,
+		lastCommitSummary: branch.last_commit_summary,
+		lastCommitTime: branch.last_commit_time
+	};
+}
+
+function fromRustCommitInfo(commit: RustCommitInfo): CommitInfo {
+	return {
+		id: commit.id,
+		shortId: commit.short_id,
+		authorName: commit.author_name,
+		authorEmail: commit.author_email,
+		timestamp: commit.timestamp,
+		summary: commit.summary,
+		isMerge: commit.is_merge,
+		files: commit.files
+	};
+}
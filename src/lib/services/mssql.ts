@@ -1,23 +1,73 @@
 import { invoke } from "@tauri-apps/api/core";
 
 export interface MssqlConfig {
+	/** Host name, or `host\INSTANCE` for a named instance */
 	host: string;
 	port: number;
+	/** Named instance resolved through SQL Browser instead of using `port` */
+	instanceName?: string;
 	database: string;
+	authMethod?: "sql_server" | "windows" | "integrated";
+	domain?: string;
 	username: string;
 	password: string;
-	encrypt?: boolean;
+	/** "strict" is TDS 8.0 (TLS before any TDS traffic); defaults to "on" */
+	encrypt?: "off" | "on" | "strict";
+	/** Accept any server certificate; prefer `caCertPath` */
 	trustCert?: boolean;
+	/** PEM or DER CA certificate to trust */
+	caCertPath?: string;
+	/** Name to validate the server certificate against, e.g. behind a tunnel */
+	hostnameOverride?: string;
+	/** Program name shown to DBAs; defaults to "Seaquel <version>" */
+	applicationName?: string;
+	/** Limit for connecting as a whole (TCP, TLS and login); defaults to 30 */
+	connectTimeoutSecs?: number;
+	/** Default limit for each query; queries past it are cancelled */
+	queryTimeoutSecs?: number;
+	/** ApplicationIntent=ReadOnly, for routing to an availability group secondary */
+	readOnlyIntent?: boolean;
+	/** Reject anything but SELECT queries with READ_ONLY_CONNECTION */
+	readOnly?: boolean;
+	/** Idle seconds before the connection is pinged; 0 turns pinging off */
+	keepAliveSecs?: number;
+	/** Binary cells longer than this come back as `{ $blob_length: n }`; 0 sends them whole */
+	binaryPreviewBytes?: number;
+	/** Sessions opened beyond the first when queries overlap; defaults to 1 */
+	extraSessions?: number;
+	/** Dial every address of the host at once (availability group listeners) */
+	multiSubnetFailover?: boolean;
+	/** `host[\instance][,port]` tried when the server can't be reached */
+	failoverPartner?: string;
 }
 
 export interface MssqlConnection {
 	connectionId: string;
+	/** Server session id (@@SPID) */
+	sessionId: number;
+	/** Host and address the connection landed on, e.g. "aglistener (10.0.2.7:1433)" */
+	endpoint: string;
+}
+
+/** PRINT output or a RAISERROR of severity 10 or lower */
+export interface MssqlMessage {
+	text: string;
 }
 
 export interface MssqlQueryResult {
 	columns: string[];
+	columnTypes: string[];
 	rows: Record<string, unknown>[];
 	rowsAffected: number;
+	messages: MssqlMessage[];
+}
+
+export interface MssqlArrayQueryResult {
+	columns: string[];
+	columnTypes: string[];
+	rows: unknown[][];
+	rowsAffected: number;
+	messages: MssqlMessage[];
 }
 
 interface MssqlError {
@@ -43,28 +93,73 @@ function formatError(error: unknown): Error {
 	return new Error("An unknown error occurred");
 }
 
+function toRustConfig(config: MssqlConfig) {
+	return {
+		host: config.host,
+		port: config.port,
+		instance_name: config.instanceName,
+		database: config.database,
+		auth_method: config.authMethod,
+		domain: config.domain,
+		username: config.username,
+		password: config.password,
+		encrypt: config.encrypt,
+		trust_cert: config.trustCert,
+		ca_cert_path: config.caCertPath,
+		hostname_override: config.hostnameOverride,
+		application_name: config.applicationName,
+		connect_timeout_secs: config.connectTimeoutSecs,
+		query_timeout_secs: config.queryTimeoutSecs,
+		read_only_intent: config.readOnlyIntent,
+		read_only: config.readOnly,
+		keep_alive_secs: config.keepAliveSecs,
+		binary_preview_bytes: config.binaryPreviewBytes,
+		extra_sessions: config.extraSessions,
+		multi_subnet_failover: config.multiSubnetFailover,
+		failover_partner: config.failoverPartner,
+	};
+}
+
 export async function mssqlConnect(config: MssqlConfig): Promise<MssqlConnection> {
 	try {
-		const result = await invoke<{ connection_id: string }>("mssql_connect", {
-			config: {
-				host: config.host,
-				port: config.port,
-				database: config.database,
-				username: config.username,
-				password: config.password,
-				encrypt: config.encrypt,
-				trust_cert: config.trustCert,
-			},
-		});
+		const result = await invoke<{ connection_id: string; session_id: number; endpoint: string }>(
+			"mssql_connect",
+			{ config: toRustConfig(config) }
+		);
 
 		return {
 			connectionId: result.connection_id,
+			sessionId: result.session_id,
+			endpoint: result.endpoint,
 		};
 	} catch (error) {
 		throw formatError(error);
 	}
 }
 
+export interface MssqlTestResult {
+	version: string;
+	edition: string;
+	/** The database the login ended up in */
+	database: string;
+}
+
+/**
+ * Connect, log in and read the server version, then close. Errors are
+ * prefixed with a code such as DNS_ERROR, CONNECTION_REFUSED, TLS_ERROR,
+ * AUTH_FAILED or DATABASE_NOT_FOUND, or TCP_TIMEOUT, TLS_TIMEOUT or
+ * LOGIN_TIMEOUT for the stage that ran out of time.
+ */
+export async function mssqlTestConnection(config: MssqlConfig): Promise<MssqlTestResult> {
+	try {
+		return await invoke<MssqlTestResult>("mssql_test_connection", {
+			config: toRustConfig(config),
+		});
+	} catch (error) {
+		throw formatError(error);
+	}
+}
+
 export async function mssqlDisconnect(connectionId: string): Promise<void> {
 	try {
 		await invoke("mssql_disconnect", { connectionId });
@@ -73,48 +168,127 @@ export async function mssqlDisconnect(connectionId: string): Promise<void> {
 	}
 }
 
+/**
+ * Run a query. `params` are bound as @P1, @P2, ...; strings are sent as
+ * NVARCHAR unless `paramTypes` names another SQL type (e.g. "varchar",
+ * "datetime2"). Binary values are passed as `{ $blob: "<base64>" }`.
+ */
 export async function mssqlQuery(
 	connectionId: string,
-	sql: string
+	sql: string,
+	params?: unknown[],
+	paramTypes?: (string | null)[]
 ): Promise<MssqlQueryResult> {
 	try {
 		const result = await invoke<{
 			columns: string[];
+			column_types: string[];
 			rows: Record<string, unknown>[];
 			rows_affected: number;
+			messages: MssqlMessage[];
 		}>("mssql_query", {
 			connectionId,
 			sql,
+			params,
+			paramTypes,
 		});
 
 		return {
 			columns: result.columns,
+			columnTypes: result.column_types,
 			rows: result.rows,
 			rowsAffected: result.rows_affected,
+			messages: result.messages,
 		};
 	} catch (error) {
 		throw formatError(error);
 	}
 }
 
-export async function mssqlExecute(
+/**
+ * Like mssqlQuery, but rows come back as arrays aligned with `columns`, so
+ * duplicate and unnamed columns (`SELECT a.id, b.id`, `COUNT(*)`) survive.
+ */
+export async function mssqlQueryArrays(
 	connectionId: string,
 	sql: string
+): Promise<MssqlArrayQueryResult> {
+	try {
+		const result = await invoke<{
+			columns: string[];
+			column_types: string[];
+			rows: unknown[][];
+			rows_affected: number;
+			messages: MssqlMessage[];
+		}>("mssql_query", {
+			connectionId,
+			sql,
+			rowsAsArrays: true,
+		});
+
+		return {
+			columns: result.columns,
+			columnTypes: result.column_types,
+			rows: result.rows,
+			rowsAffected: result.rows_affected,
+			messages: result.messages,
+		};
+	} catch (error) {
+		throw formatError(error);
+	}
+}
+
+/**
+ * Turn array rows into objects, renaming repeated or empty column names
+ * (`id`, `id_2`, `(No column name)`) so no column is dropped.
+ */
+export function arrayRowsToObjects(result: MssqlArrayQueryResult): {
+	columns: string[];
+	rows: Record<string, unknown>[];
+} {
+	const seen = new Map<string, number>();
+	const columns = result.columns.map((name) => {
+		const base = name || "(No column name)";
+		const count = (seen.get(base) ?? 0) + 1;
+		seen.set(base, count);
+		return count === 1 ? base : `${base}_${count}`;
+	});
+	const rows = result.rows.map((row) => {
+		const obj: Record<string, unknown> = {};
+		columns.forEach((col, i) => {
+			obj[col] = row[i];
+		});
+		return obj;
+	});
+	return { columns, rows };
+}
+
+export async function mssqlExecute(
+	connectionId: string,
+	sql: string,
+	params?: unknown[],
+	paramTypes?: (string | null)[]
 ): Promise<MssqlQueryResult> {
 	try {
 		const result = await invoke<{
 			columns: string[];
+			column_types: string[];
 			rows: Record<string, unknown>[];
 			rows_affected: number;
+			messages: MssqlMessage[];
 		}>("mssql_execute", {
 			connectionId,
 			sql,
+			params,
+			paramTypes,
 		});
 
 		return {
 			columns: result.columns,
+			columnTypes: result.column_types,
 			rows: result.rows,
 			rowsAffected: result.rows_affected,
+			messages: result.messages,
 		};
 	} catch (error) {
 		throw formatError(error);
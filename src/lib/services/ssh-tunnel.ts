@@ -1,43 +1,445 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+export type TunnelMode = "local" | "socks";
 
 export interface TunnelConfig {
+	/** Host name, or a ~/.ssh/config alias that fills in the fields left out */
 	sshHost: string;
-	sshPort: number;
-	sshUsername: string;
-	authMethod: "password" | "key";
+	sshPort?: number;
+	sshUsername?: string;
+	authMethod?: "password" | "key" | "agent" | "keyboard_interactive";
+	/** Methods to try in order, e.g. `["key", "password"]` for servers that want both; overrides `authMethod` */
+	authMethods?: NonNullable<TunnelConfig["authMethod"]>[];
 	password?: string;
 	keyPath?: string;
 	keyPassphrase?: string;
-	remoteHost: string;
-	remotePort: number;
+	/** OpenSSH certificate for the key; `<keyPath>-cert.pub` is picked up when this is left out */
+	certPath?: string;
+	/** Where connections are forwarded to; not needed in socks mode */
+	remoteHost?: string;
+	remotePort?: number;
+	/** Forward to this Unix socket on the SSH server instead, e.g. Postgres's `/var/run/postgresql/.s.PGSQL.5432` */
+	remoteSocketPath?: string;
+	/** "socks" makes the local port a SOCKS5 proxy into the SSH server's network */
+	mode?: TunnelMode;
+	/** Jump hosts to pass through in order, as with `ssh -J` */
+	jumpHosts?: SshHop[];
+	/** Attempts to re-establish a dropped session (defaults to 5; 0 disables) */
+	reconnectMaxAttempts?: number;
+	/** Seconds between keepalive packets (defaults to 30; 0 disables) */
+	keepaliveIntervalSecs?: number;
+	/** Fixed local port to listen on; a random free one when omitted */
+	localPort?: number;
+	/** Local address to listen on (defaults to 127.0.0.1) */
+	bindAddress?: string;
+	/** Required to bind an address other machines can reach */
+	allowExternal?: boolean;
+	/** Close the tunnel after this many seconds without connections */
+	idleTimeoutSecs?: number;
+	/** Open a separate tunnel even if an identical one is already open */
+	forceNew?: boolean;
+	/** Unique among open tunnels, and accepted anywhere a tunnel id is */
+	name?: string;
+	/** Ask the SSH server to compress, like `ssh -C`; ignored if it can't */
+	compression?: boolean;
+	/** Algorithms to offer in place of the defaults, best first, e.g. `["diffie-hellman-group14-sha1"]` */
+	kexAlgorithms?: string[];
+	ciphers?: string[];
+	hostKeyAlgorithms?: string[];
+	/** Also offer the SHA-1 and CBC algorithms that old servers such as RHEL 6 need */
+	legacyCompat?: boolean;
+	/** Connections forwarded at once before new ones are refused (defaults to 32; 0 for no limit) */
+	maxConnections?: number;
+}
+
+export interface SshHop {
+	host: string;
+	port: number;
+	username: string;
+	authMethod: NonNullable<TunnelConfig["authMethod"]>;
+	authMethods?: NonNullable<TunnelConfig["authMethod"]>[];
+	password?: string;
+	keyPath?: string;
+	keyPassphrase?: string;
+	certPath?: string;
 }
 
 export interface TunnelResult {
 	tunnelId: string;
 	localPort: number;
+	bindAddress: string;
 }
 
+/** A round of keyboard-interactive prompts from the SSH server */
+export interface SshAuthPrompt {
+	requestId: string;
+	sshHost: string;
+	name: string;
+	instructions: string;
+	/** `echo` is false for secrets that shouldn't be shown as typed */
+	prompts: { prompt: string; echo: boolean }[];
+}
+
+function toRawConfig(config: TunnelConfig) {
+	return {
+		ssh_host: config.sshHost,
+		ssh_port: config.sshPort,
+		ssh_username: config.sshUsername,
+		auth_method: config.authMethod,
+		auth_methods: config.authMethods,
+		password: config.password,
+		key_path: config.keyPath,
+		key_passphrase: config.keyPassphrase,
+		cert_path: config.certPath,
+		remote_host: config.remoteHost,
+		remote_port: config.remotePort,
+		remote_socket_path: config.remoteSocketPath,
+		mode: config.mode,
+		jump_hosts: config.jumpHosts?.map((hop) => ({
+			host: hop.host,
+			port: hop.port,
+			username: hop.username,
+			auth_method: hop.authMethod,
+			auth_methods: hop.authMethods,
+			password: hop.password,
+			key_path: hop.keyPath,
+			key_passphrase: hop.keyPassphrase,
+			cert_path: hop.certPath,
+		})),
+		reconnect_max_attempts: config.reconnectMaxAttempts,
+		keepalive_interval_secs: config.keepaliveIntervalSecs,
+		local_port: config.localPort,
+		bind_address: config.bindAddress,
+		allow_external: config.allowExternal,
+		idle_timeout_secs: config.idleTimeoutSecs,
+		force_new: config.forceNew,
+		name: config.name,
+		compression: config.compression,
+		kex_algorithms: config.kexAlgorithms,
+		ciphers: config.ciphers,
+		host_key_algorithms: config.hostKeyAlgorithms,
+		legacy_compat: config.legacyCompat,
+		max_connections: config.maxConnections,
+	};
+}
+
+/**
+ * Open a tunnel, or share an open one with the same servers, credentials and
+ * remote host. Each call needs its own `closeSshTunnel`.
+ *
+ * Key problems reject with code KEY_ENCRYPTED_NO_PASSPHRASE or
+ * KEY_WRONG_PASSPHRASE, which are worth asking for the passphrase again, or
+ * KEY_NOT_FOUND, KEY_FORMAT_UNKNOWN, PPK_UNSUPPORTED_VERSION or KEY_LOAD_ERROR.
+ * Certificates outside their validity window reject with CERT_EXPIRED or
+ * CERT_NOT_YET_VALID, and unreadable ones with CERT_LOAD_ERROR.
+ * With `remoteSocketPath`, STREAMLOCAL_UNSUPPORTED means the SSH server won't
+ * forward to Unix sockets. ALGORITHM_MISMATCH lists what the server offers
+ * when it shares no algorithms with the client. A `name` another open tunnel
+ * already has rejects with NAME_IN_USE.
+ */
 export async function createSshTunnel(config: TunnelConfig): Promise<TunnelResult> {
-	const result = await invoke<{ tunnel_id: string; local_port: number }>("create_ssh_tunnel", {
-		config: {
-			ssh_host: config.sshHost,
-			ssh_port: config.sshPort,
-			ssh_username: config.sshUsername,
-			auth_method: config.authMethod,
-			password: config.password,
-			key_path: config.keyPath,
-			key_passphrase: config.keyPassphrase,
-			remote_host: config.remoteHost,
-			remote_port: config.remotePort,
-		},
+	const result = await invoke<{ tunnel_id: string; local_port: number; bind_address: string }>("create_ssh_tunnel", {
+		config: toRawConfig(config),
 	});
 
 	return {
 		tunnelId: result.tunnel_id,
 		localPort: result.local_port,
+		bindAddress: result.bind_address,
 	};
 }
 
+export interface SshTestResult {
+	serverVersion: string;
+	authMethod: NonNullable<TunnelConfig["authMethod"]>;
+	/** Only set when the remote host was checked */
+	remoteReachable: boolean | null;
+	remoteError: string | null;
+}
+
+/**
+ * Connect and authenticate without opening a tunnel. With `checkRemote`, also
+ * check that the SSH server can reach `remoteHost:remotePort`.
+ */
+export async function testSshConnection(config: TunnelConfig, checkRemote = false): Promise<SshTestResult> {
+	const result = await invoke<{
+		server_version: string;
+		auth_method: SshTestResult["authMethod"];
+		remote_reachable: boolean | null;
+		remote_error: string | null;
+	}>("ssh_test_connection", { config: toRawConfig(config), checkRemote });
+
+	return {
+		serverVersion: result.server_version,
+		authMethod: result.auth_method,
+		remoteReachable: result.remote_reachable,
+		remoteError: result.remote_error,
+	};
+}
+
+/** Takes a tunnel id or name */
 export async function closeSshTunnel(tunnelId: string): Promise<void> {
 	await invoke("close_ssh_tunnel", { tunnelId });
 }
+
+export async function onSshAuthPrompt(handler: (prompt: SshAuthPrompt) => void): Promise<UnlistenFn> {
+	return listen<{
+		request_id: string;
+		ssh_host: string;
+		name: string;
+		instructions: string;
+		prompts: { prompt: string; echo: boolean }[];
+	}>("ssh://auth-prompt", (event) => {
+		handler({
+			requestId: event.payload.request_id,
+			sshHost: event.payload.ssh_host,
+			name: event.payload.name,
+			instructions: event.payload.instructions,
+			prompts: event.payload.prompts,
+		});
+	});
+}
+
+/** Answer a prompt round in order, or pass null to cancel the sign-in */
+export async function answerSshPrompts(requestId: string, answers: string[] | null): Promise<void> {
+	await invoke("ssh_tunnel_answer_prompts", { requestId, answers });
+}
+
+export interface SshPassphrasePrompt {
+	requestId: string;
+	sshHost: string;
+	keyPath: string;
+	/** Starts at 1; the connection fails after the third wrong passphrase */
+	attempt: number;
+	/** Why the previous passphrase was rejected */
+	error: string | null;
+}
+
+/** An encrypted SSH key needs a passphrase that wasn't saved, or was wrong */
+export async function onSshPassphrasePrompt(handler: (prompt: SshPassphrasePrompt) => void): Promise<UnlistenFn> {
+	return listen<{
+		request_id: string;
+		ssh_host: string;
+		key_path: string;
+		attempt: number;
+		error: string | null;
+	}>("tunnel://passphrase-required", (event) => {
+		handler({
+			requestId: event.payload.request_id,
+			sshHost: event.payload.ssh_host,
+			keyPath: event.payload.key_path,
+			attempt: event.payload.attempt,
+			error: event.payload.error,
+		});
+	});
+}
+
+/** Pass `null` to cancel connecting */
+export async function provideSshPassphrase(requestId: string, passphrase: string | null): Promise<void> {
+	await invoke("ssh_tunnel_provide_passphrase", { requestId, passphrase });
+}
+
+export interface TunnelInfo {
+	tunnelId: string;
+	name: string | null;
+	sshHost: string;
+	sshUsername: string;
+	remoteHost: string;
+	remotePort: number;
+	remoteSocketPath: string | null;
+	mode: TunnelMode;
+	localPort: number;
+	bindAddress: string;
+	/** Listening on an address other machines can reach */
+	external: boolean;
+	/** Milliseconds since the Unix epoch */
+	createdAt: number;
+	connected: boolean;
+	activeConnections: number;
+	/** null when unlimited */
+	maxConnections: number | null;
+	bytesIn: number;
+	bytesOut: number;
+	/** Connections sharing the tunnel */
+	refCount: number;
+	/** Whether the SSH server agreed to compress */
+	compression: boolean;
+}
+
+export async function listActiveTunnels(): Promise<TunnelInfo[]> {
+	const tunnels = await invoke<
+		{
+			tunnel_id: string;
+			name: string | null;
+			ssh_host: string;
+			ssh_username: string;
+			remote_host: string;
+			remote_port: number;
+			remote_socket_path: string | null;
+			mode: TunnelMode;
+			local_port: number;
+			bind_address: string;
+			external: boolean;
+			created_at: number;
+			connected: boolean;
+			active_connections: number;
+			max_connections: number | null;
+			bytes_in: number;
+			bytes_out: number;
+			ref_count: number;
+			compression: boolean;
+		}[]
+	>("list_active_tunnels");
+
+	return tunnels.map((tunnel) => ({
+		tunnelId: tunnel.tunnel_id,
+		name: tunnel.name,
+		sshHost: tunnel.ssh_host,
+		sshUsername: tunnel.ssh_username,
+		remoteHost: tunnel.remote_host,
+		remotePort: tunnel.remote_port,
+		remoteSocketPath: tunnel.remote_socket_path,
+		mode: tunnel.mode,
+		localPort: tunnel.local_port,
+		bindAddress: tunnel.bind_address,
+		external: tunnel.external,
+		createdAt: tunnel.created_at,
+		connected: tunnel.connected,
+		activeConnections: tunnel.active_connections,
+		maxConnections: tunnel.max_connections,
+		bytesIn: tunnel.bytes_in,
+		bytesOut: tunnel.bytes_out,
+		refCount: tunnel.ref_count,
+		compression: tunnel.compression,
+	}));
+}
+
+export interface TunnelStatus {
+	/** "degraded" means the local port is open but the SSH session isn't answering */
+	status: "active" | "degraded" | "not_found";
+	localPort: number | null;
+	lastRttMs: number | null;
+}
+
+/** Takes a tunnel id or name */
+export async function checkTunnelStatus(tunnelId: string): Promise<TunnelStatus> {
+	const result = await invoke<{
+		status: TunnelStatus["status"];
+		local_port: number | null;
+		last_rtt_ms: number | null;
+	}>("check_tunnel_status", { tunnelId });
+
+	return {
+		status: result.status,
+		localPort: result.local_port,
+		lastRttMs: result.last_rtt_ms,
+	};
+}
+
+export interface TunnelStats {
+	tunnelId: string;
+	name: string | null;
+	bytesIn: number;
+	bytesOut: number;
+	connectionsOpened: number;
+	activeConnections: number;
+	/** Throughput over the last ten seconds */
+	bytesInPerSec: number;
+	bytesOutPerSec: number;
+	/** Whether the SSH server agreed to compress */
+	compression: boolean;
+}
+
+interface RawTunnelStats {
+	tunnel_id: string;
+	name: string | null;
+	bytes_in: number;
+	bytes_out: number;
+	connections_opened: number;
+	active_connections: number;
+	bytes_in_per_sec: number;
+	bytes_out_per_sec: number;
+	compression: boolean;
+}
+
+function toTunnelStats(stats: RawTunnelStats): TunnelStats {
+	return {
+		tunnelId: stats.tunnel_id,
+		name: stats.name,
+		bytesIn: stats.bytes_in,
+		bytesOut: stats.bytes_out,
+		connectionsOpened: stats.connections_opened,
+		activeConnections: stats.active_connections,
+		bytesInPerSec: stats.bytes_in_per_sec,
+		bytesOutPerSec: stats.bytes_out_per_sec,
+		compression: stats.compression,
+	};
+}
+
+/** Takes a tunnel id or name */
+export async function getTunnelStats(tunnelId: string): Promise<TunnelStats> {
+	return toTunnelStats(await invoke<RawTunnelStats>("get_tunnel_stats", { tunnelId }));
+}
+
+/** Sent every couple of seconds while a tunnel has active connections */
+export async function onTunnelStats(handler: (stats: TunnelStats) => void): Promise<UnlistenFn> {
+	return listen<RawTunnelStats>("tunnel://stats", (event) => handler(toTunnelStats(event.payload)));
+}
+
+export interface TunnelErrorEvent {
+	tunnelId: string;
+	name: string | null;
+	/** The `host:port` connections are forwarded to */
+	remote: string;
+	message: string;
+}
+
+/**
+ * Errors from a tunnel's forwarded connections and its local listener, and
+ * connections refused for going over `maxConnections`, at most one of each
+ * kind per second per tunnel
+ */
+export async function onTunnelError(
+	handler: (kind: "connection" | "listener" | "rejected", error: TunnelErrorEvent) => void,
+): Promise<UnlistenFn> {
+	const toEvent = (payload: { tunnel_id: string; name: string | null; remote: string; message: string }) => ({
+		tunnelId: payload.tunnel_id,
+		name: payload.name,
+		remote: payload.remote,
+		message: payload.message,
+	});
+	const unlistenConnection = await listen<{ tunnel_id: string; name: string | null; remote: string; message: string }>(
+		"tunnel://connection-error",
+		(event) => handler("connection", toEvent(event.payload)),
+	);
+	const unlistenListener = await listen<{ tunnel_id: string; name: string | null; remote: string; message: string }>(
+		"tunnel://listener-error",
+		(event) => handler("listener", toEvent(event.payload)),
+	);
+
+	const unlistenRejected = await listen<{ tunnel_id: string; name: string | null; remote: string; message: string }>(
+		"tunnel://connection-rejected",
+		(event) => handler("rejected", toEvent(event.payload)),
+	);
+
+	return () => {
+		unlistenConnection();
+		unlistenListener();
+		unlistenRejected();
+	};
+}
+
+export interface TunnelClosedEvent {
+	tunnelId: string;
+	name: string | null;
+	reason: "idle_timeout";
+}
+
+/** Tunnels that closed themselves, as opposed to through `closeSshTunnel` */
+export async function onTunnelClosed(handler: (event: TunnelClosedEvent) => void): Promise<UnlistenFn> {
+	return listen<{ tunnel_id: string; name: string | null; reason: TunnelClosedEvent["reason"] }>(
+		"tunnel://closed",
+		(event) => handler({ tunnelId: event.payload.tunnel_id, name: event.payload.name, reason: event.payload.reason }),
+	);
+}
@@ -13,7 +13,7 @@ export type DatabaseType = 'postgres' | 'mysql' | 'sqlite' | 'mariadb' | 'mssql'
 /**
  * SSH tunnel authentication methods.
  */
-export type SSHAuthMethod = 'password' | 'key';
+export type SSHAuthMethod = 'password' | 'key' | 'agent' | 'keyboard_interactive';
 
 /**
  * Configuration for SSH tunnel connections.
@@ -28,7 +28,7 @@ export interface SSHTunnelConfig {
 	port: number;
 	/** SSH username for authentication */
 	username: string;
-	/** Authentication method: password or SSH key */
+	/** Authentication method: password, SSH key, the running SSH agent or keyboard-interactive prompts */
 	authMethod: SSHAuthMethod;
 	/** Path to SSH private key file (for key auth) */
 	keyPath?: string;
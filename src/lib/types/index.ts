@@ -98,6 +98,8 @@ export type {
 	GitCredentials,
 	SyncResult,
 	RepoStatus,
+	BranchInfo,
+	CommitInfo,
 	ConflictContent,
 	QueryFrontmatter,
 	SharedQueryFolder,
@@ -126,6 +126,46 @@ export interface RepoStatus {
 	untrackedFiles: string[];
 }
 
+/**
+ * A local or remote branch in a Git repository.
+ */
+export interface BranchInfo {
+	/** Short name, e.g. `main` or `origin/main` */
+	name: string;
+	/** Whether this is a remote-tracking branch */
+	isRemote: boolean;
+	/** Whether this is the checked out branch */
+	isHead: boolean;
+	/** The remote branch a local branch tracks */
+	upstream: string | null;
+	/** Last commit hash */
+	lastCommitId: string | null;
+	/** First line of the last commit message */
+	lastCommitSummary: string | null;
+	/** Last commit time, in seconds since the Unix epoch */
+	lastCommitTime: number | null;
+}
+
+/**
+ * A commit in a Git repository's history.
+ */
+export interface CommitInfo {
+	/** Full commit hash */
+	id: string;
+	/** Abbreviated commit hash */
+	shortId: string;
+	authorName: string;
+	authorEmail: string;
+	/** Commit time, in seconds since the Unix epoch */
+	timestamp: number;
+	/** First line of the commit message */
+	summary: string;
+	/** Whether the commit has more than one parent */
+	isMerge: boolean;
+	/** Paths changed since the first parent */
+	files: string[];
+}
+
 /**
  * Content of a conflicting file for merge resolution.
  */